@@ -18,6 +18,10 @@ export interface UserSecret {
   public_key: string;
   pk_salt: string;
   pk_nonce: string;
+  /** Absent for rows written before the Ed25519 signing key existed */
+  encrypted_signing_key: string | null;
+  signing_nonce: string | null;
+  signing_public_key: string | null;
   created_at: string;
 }
 
@@ -27,6 +31,9 @@ export interface SaveUserSecretsInput {
   publicKey: string;
   pkSalt: string;
   pkNonce: string;
+  encryptedSigningKey: string;
+  signingNonce: string;
+  signingPublicKey: string;
 }
 
 export interface SaveUserSecretsResult {
@@ -88,6 +95,9 @@ export async function saveUserSecrets(
     public_key: input.publicKey,
     pk_salt: input.pkSalt,
     pk_nonce: input.pkNonce,
+    encrypted_signing_key: input.encryptedSigningKey,
+    signing_nonce: input.signingNonce,
+    signing_public_key: input.signingPublicKey,
   });
 
   if (error) {
@@ -101,6 +111,78 @@ export async function saveUserSecrets(
   return { success: true };
 }
 
+export interface Pepper {
+  version: number;
+  secret: string;
+}
+
+/** Largest pepper version the crypto core accepts (a u32) */
+const MAX_PEPPER_VERSION = 0xffffffff;
+
+/**
+ * Parses `NOVAULT_PEPPERS`, validating it like the `novault` CLI does.
+ *
+ * @throws If an entry is not `version:hex`, a version is not a u32 or is
+ * listed twice, or a secret is not hex
+ */
+function parsePeppers(value: string): Pepper[] {
+  const peppers: Pepper[] = [];
+  for (const entry of value.split(",").map((entry) => entry.trim())) {
+    if (!entry) continue;
+
+    const [version, secret, ...rest] = entry
+      .split(":")
+      .map((part) => part.trim());
+    if (secret === undefined || rest.length > 0) {
+      throw new Error(
+        "NOVAULT_PEPPERS: expected comma-separated version:hex pairs",
+      );
+    }
+    if (!/^\d+$/.test(version) || Number(version) > MAX_PEPPER_VERSION) {
+      throw new Error(`NOVAULT_PEPPERS: invalid pepper version "${version}"`);
+    }
+    if (!/^([0-9a-fA-F]{2})+$/.test(secret)) {
+      throw new Error(`NOVAULT_PEPPERS: pepper ${version} is not hex`);
+    }
+    if (peppers.some((pepper) => pepper.version === Number(version))) {
+      throw new Error(`NOVAULT_PEPPERS: pepper ${version} is listed twice`);
+    }
+    peppers.push({ version: Number(version), secret });
+  }
+  return peppers;
+}
+
+/**
+ * Returns the KDF peppers to an authenticated user.
+ *
+ * Read from `NOVAULT_PEPPERS` as comma-separated `version:hex` pairs, the
+ * same format the `novault` CLI reads. Several versions are listed while
+ * users are moved to a new pepper.
+ *
+ * Key derivation runs in the browser, so every signed-in user receives the
+ * peppers. They keep a leaked database dump from being attacked offline on
+ * its own, but not by anyone who also holds an account: such an attacker
+ * can guess passwords and PINs against sealed keys they obtain. Rotate the
+ * pepper if it leaks; it is not a substitute for strong passwords.
+ *
+ * @returns The peppers, or an empty list when not signed in
+ * @throws If `NOVAULT_PEPPERS` is malformed
+ */
+export async function getPeppers(): Promise<Pepper[]> {
+  const supabase = await createClient();
+
+  const {
+    data: { user },
+  } = await supabase.auth.getUser();
+
+  if (!user) {
+    console.error("[getPeppers] No authenticated user found");
+    return [];
+  }
+
+  return parsePeppers(process.env.NOVAULT_PEPPERS ?? "");
+}
+
 export async function signOut() {
   const supabase = await createClient();
   await supabase.auth.signOut();
@@ -66,6 +66,8 @@ export interface FileDek {
   encrypted_dek: string;
   dek_nonce: string;
   ephemeral_public_key: string;
+  /** Key wrap scheme ID to pass to `decrypt_file`; 1 for rows stored before HKDF */
+  wrap_scheme: number;
   created_at: string;
 }
 
@@ -100,7 +102,7 @@ export async function getFileDek(fileId: string): Promise<GetFileDekResult> {
     .schema("api")
     .from("file_dek")
     .select(
-      "key_id, file_id, owner_id, encrypted_dek, dek_nonce, ephemeral_public_key, created_at",
+      "key_id, file_id, owner_id, encrypted_dek, dek_nonce, ephemeral_public_key, wrap_scheme, created_at",
     )
     .eq("file_id", fileId)
     .eq("owner_id", user.id)
@@ -9,6 +9,7 @@ import {
 } from "@/app/home/storage/actions";
 import { HexToUint8Array } from "@/utils/hexUtils";
 import { createClient } from "@/services/supabase/client";
+import { registerPeppers } from "@/utils/pepper";
 
 /** Props for the FileDecryptionModal component */
 interface FileDecryptionModalProps {
@@ -203,6 +204,7 @@ export default function FileDecryptionModal({
 
       const wasm = await import("@/pkg/rust");
       await wasm.default();
+      await registerPeppers(wasm);
 
       // Step 4: Decrypt file content
       setStep("decrypting-file");
@@ -227,6 +229,7 @@ export default function FileDecryptionModal({
         encryptedDekBytes,
         dekNonceBytes,
         fileNonceBytes,
+        dek.wrap_scheme,
       );
 
       if (!decryptResult.success) {
@@ -2,6 +2,10 @@
 
 import { useState } from "react";
 import { saveUserSecrets } from "@/app/home/actions";
+import { registerPeppers } from "@/utils/pepper";
+
+/** Time the master key derivation should take on the user's device */
+const UNLOCK_TARGET_MS = 1000;
 
 interface SetupMasterKeyModalProps {
   isOpen: boolean;
@@ -42,8 +46,11 @@ export default function SetupMasterKeyModal({
     try {
       const wasm = await import("@/pkg/rust");
       await wasm.default();
+      await registerPeppers(wasm);
 
-      const encryptedResult = wasm.encrypt_master_key(password);
+      // Fit the Argon2id cost to this device, never below the crate's floor
+      const kdfParams = wasm.calibrate_kdf(UNLOCK_TARGET_MS);
+      const encryptedResult = wasm.encrypt_master_key(password, kdfParams);
 
       const result = await saveUserSecrets({
         userId,
@@ -51,6 +58,9 @@ export default function SetupMasterKeyModal({
         publicKey: encryptedResult.public_key_hex,
         pkSalt: encryptedResult.salt,
         pkNonce: encryptedResult.nonce_hex,
+        encryptedSigningKey: encryptedResult.encrypted_signing_key_hex,
+        signingNonce: encryptedResult.signing_nonce_hex,
+        signingPublicKey: encryptedResult.signing_public_key_hex,
       });
 
       if (!result.success) {
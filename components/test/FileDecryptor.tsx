@@ -3,6 +3,7 @@
 import { useState, useRef } from "react";
 import { getUserSecrets } from "@/app/home/actions";
 import { HexToUint8Array } from "@/utils/hexUtils";
+import { registerPeppers } from "@/utils/pepper";
 
 interface FileDecryptorProps {
   userId: string;
@@ -22,6 +23,7 @@ export default function FileDecryptor({ userId }: FileDecryptorProps) {
   const [fileNonceHex, setFileNonceHex] = useState("");
   const [encryptedDekHex, setEncryptedDekHex] = useState("");
   const [dekNonceHex, setDekNonceHex] = useState("");
+  const [wrapScheme, setWrapScheme] = useState("2");
   const [ephemeralPublicKeyHex, setEphemeralPublicKeyHex] = useState("");
   const [loading, setLoading] = useState(false);
   const [result, setResult] = useState<DecryptionResult | null>(null);
@@ -73,6 +75,7 @@ export default function FileDecryptor({ userId }: FileDecryptorProps) {
       console.log("[FileDecryptor] Loading WASM module...");
       const wasm = await import("@/pkg/rust");
       await wasm.default();
+      await registerPeppers(wasm);
 
       // Step 3: Read encrypted file as ArrayBuffer
       console.log("[FileDecryptor] Reading encrypted file...");
@@ -112,6 +115,7 @@ export default function FileDecryptor({ userId }: FileDecryptorProps) {
         encryptedDekBytes,
         dekNonceBytes,
         fileNonceBytes,
+        Number(wrapScheme),
       );
 
       if (!decryptResult.success) {
@@ -193,6 +197,7 @@ export default function FileDecryptor({ userId }: FileDecryptorProps) {
     setFileNonceHex("");
     setEncryptedDekHex("");
     setDekNonceHex("");
+    setWrapScheme("2");
     setEphemeralPublicKeyHex("");
     setResult(null);
     if (fileInputRef.current) {
@@ -277,6 +282,22 @@ export default function FileDecryptor({ userId }: FileDecryptorProps) {
           />
         </div>
 
+        {/* Wrap Scheme Input */}
+        <div className="flex flex-col gap-1">
+          <label className="text-sm text-foreground/70">
+            Wrap Scheme (from encryption)
+          </label>
+          <select
+            value={wrapScheme}
+            onChange={(e) => setWrapScheme(e.target.value)}
+            className="border border-foreground/20 p-2 rounded text-foreground bg-background text-sm"
+            disabled={loading}
+          >
+            <option value="2">2: ECDH + HKDF</option>
+            <option value="1">1: Raw ECDH (older files)</option>
+          </select>
+        </div>
+
         {/* Ephemeral Public Key Input */}
         <div className="flex flex-col gap-1">
           <label className="text-sm text-foreground/70">
@@ -4,6 +4,7 @@ import { useState, useRef } from "react";
 import { getUserSecrets } from "@/app/home/actions";
 import { HexToUint8Array } from "@/utils/hexUtils";
 import { insertFileMetadata, uploadFile } from "@/utils/api";
+import { registerPeppers } from "@/utils/pepper";
 
 interface FileEncryptorProps {
   userId: string;
@@ -16,6 +17,7 @@ interface EncryptionResult {
   encryptedDekHex?: string;
   dekNonceHex?: string;
   ephemeralPublicKeyHex?: string;
+  wrapScheme?: number;
   originalHashHex?: string;
   fileName?: string;
   error?: string;
@@ -23,6 +25,7 @@ interface EncryptionResult {
 
 export default function FileEncryptor({ userId }: FileEncryptorProps) {
   const [file, setFile] = useState<File | null>(null);
+  const [password, setPassword] = useState("");
   const [loading, setLoading] = useState(false);
   const [result, setResult] = useState<EncryptionResult | null>(null);
   const fileInputRef = useRef<HTMLInputElement>(null);
@@ -41,8 +44,8 @@ export default function FileEncryptor({ userId }: FileEncryptorProps) {
    * Encrypts the selected file using hybrid encryption (X25519 + AES-256-GCM)
    */
   const handleEncrypt = async () => {
-    if (!file) {
-      console.log("[FileEncryptor] Missing file");
+    if (!file || !password) {
+      console.log("[FileEncryptor] Missing file or password");
       return;
     }
 
@@ -66,6 +69,7 @@ export default function FileEncryptor({ userId }: FileEncryptorProps) {
       console.log("[FileEncryptor] Loading WASM module...");
       const wasm = await import("@/pkg/rust");
       await wasm.default();
+      await registerPeppers(wasm);
 
       // Step 3: Read file as ArrayBuffer
       console.log("[FileEncryptor] Reading file...");
@@ -73,10 +77,32 @@ export default function FileEncryptor({ userId }: FileEncryptorProps) {
       const fileData = new Uint8Array(fileBuffer);
       console.log("[FileEncryptor] File read, size:", fileData.length, "bytes");
 
-      // Step 4: Convert public key hex to bytes and encrypt the file
+      // Step 4: Unlock the vault for the file digest key; the stored
+      // file_hash is keyed with it, so it cannot be computed from the public key
+      console.log("[FileEncryptor] Unlocking vault for the digest key...");
+      const vault = new wasm.UnlockedVault(
+        password,
+        secrets.pk_salt,
+        HexToUint8Array(secrets.encrypted_private_key),
+        HexToUint8Array(secrets.pk_nonce),
+      );
+      let digestKey: Uint8Array;
+      try {
+        digestKey = vault.file_digest_key();
+      } finally {
+        vault.free();
+      }
+
+      // Step 5: Convert public key hex to bytes and encrypt the file
       console.log("[FileEncryptor] Encrypting file with public key...");
       const publicKeyBytes = HexToUint8Array(secrets.public_key);
-      const encryptResult = wasm.encrypt_file(fileData, publicKeyBytes);
+      const encryptResult = wasm.encrypt_file(
+        fileData,
+        publicKeyBytes,
+        undefined,
+        digestKey,
+      );
+      digestKey.fill(0);
 
       if (!encryptResult.success) {
         console.error(
@@ -96,7 +122,7 @@ export default function FileEncryptor({ userId }: FileEncryptorProps) {
         encryptResult.ephemeral_public_key_hex,
       );
       console.log(
-        "[FileEncryptor] Original file hash:",
+        "[FileEncryptor] Keyed file digest:",
         encryptResult.original_hash_hex,
       );
       console.log(
@@ -112,6 +138,7 @@ export default function FileEncryptor({ userId }: FileEncryptorProps) {
         encryptedDekHex: encryptResult.encrypted_dek_hex,
         dekNonceHex: encryptResult.dek_nonce_hex,
         ephemeralPublicKeyHex: encryptResult.ephemeral_public_key_hex,
+        wrapScheme: encryptResult.wrap_scheme,
         originalHashHex: encryptResult.original_hash_hex,
         fileName: file.name,
       });
@@ -184,6 +211,7 @@ export default function FileEncryptor({ userId }: FileEncryptorProps) {
    */
   const handleClear = () => {
     setFile(null);
+    setPassword("");
     setResult(null);
     if (fileInputRef.current) {
       fileInputRef.current.value = "";
@@ -212,11 +240,22 @@ export default function FileEncryptor({ userId }: FileEncryptorProps) {
           )}
         </div>
 
+        {/* Password Input */}
+        <input
+          type="password"
+          value={password}
+          onChange={(e) => setPassword(e.target.value)}
+          onKeyDown={(e) => e.key === "Enter" && handleEncrypt()}
+          className="border border-foreground/20 p-2 rounded text-foreground bg-background"
+          placeholder="Enter master password"
+          disabled={loading}
+        />
+
         {/* Action Buttons */}
         <div className="flex gap-2">
           <button
             onClick={handleEncrypt}
-            disabled={loading || !file}
+            disabled={loading || !file || !password}
             className="flex-1 bg-foreground text-background p-2 rounded   disabled:opacity-50"
           >
             {loading ? "Encrypting..." : "Encrypt"}
@@ -274,9 +313,14 @@ export default function FileEncryptor({ userId }: FileEncryptorProps) {
                   </code>
                 </div>
 
+                <div className="flex flex-col gap-1">
+                  <span className="font-medium">Wrap Scheme:</span>
+                  <span className="text-xs">{result.wrapScheme}</span>
+                </div>
+
                 <div className="flex flex-col gap-1">
                   <span className="font-medium">
-                    Original File Hash (SHA-256):
+                    File Digest (HMAC-SHA256):
                   </span>
                   <code className="text-xs break-all bg-green-200 p-1 rounded">
                     {result.originalHashHex}
@@ -1,6 +1,7 @@
 "use client";
 
 import { useState } from "react";
+import { registerPeppers } from "@/utils/pepper";
 
 export default function MasterKeyDeriver() {
   const [userInput, setUserInput] = useState("");
@@ -21,6 +22,7 @@ export default function MasterKeyDeriver() {
       console.log("[MasterKeyDeriver] Starting key generation");
       const wasm = await import("@/pkg/rust");
       await wasm.default();
+      await registerPeppers(wasm);
 
       const encryptedResult = wasm.encrypt_master_key(userInput);
       console.log("[MasterKeyDeriver] Key generated successfully");
@@ -3,6 +3,7 @@
 import { useState } from "react";
 import { getUserSecrets } from "@/app/home/actions";
 import { HexToUint8Array } from "@/utils/hexUtils";
+import { registerPeppers } from "@/utils/pepper";
 
 interface VerifyPrivateKeyProps {
   userId: string;
@@ -13,7 +14,7 @@ export default function VerifyPrivateKey({ userId }: VerifyPrivateKeyProps) {
   const [loading, setLoading] = useState(false);
   const [result, setResult] = useState<{
     success: boolean;
-    privateKey?: string;
+    publicKey?: string;
     error?: string;
   } | null>(null);
 
@@ -33,6 +34,7 @@ export default function VerifyPrivateKey({ userId }: VerifyPrivateKeyProps) {
 
       const wasm = await import("@/pkg/rust");
       await wasm.default();
+      await registerPeppers(wasm);
 
       // Convert hex values to bytes before passing to WASM
       const encryptedPrivateKeyBytes = HexToUint8Array(
@@ -51,7 +53,7 @@ export default function VerifyPrivateKey({ userId }: VerifyPrivateKeyProps) {
         console.log("[VerifyPrivateKey] Verification successful");
         setResult({
           success: true,
-          privateKey: decryptResult.private_key_hex,
+          publicKey: decryptResult.public_key_hex,
         });
       } else {
         console.error(
@@ -126,7 +128,7 @@ export default function VerifyPrivateKey({ userId }: VerifyPrivateKeyProps) {
               <div className="flex flex-col gap-1">
                 <span>✓ Password verified!</span>
                 <code className="text-xs break-all bg-green-200 p-1 rounded">
-                  {result.privateKey}
+                  {result.publicKey}
                 </code>
               </div>
             ) : (
@@ -0,0 +1,846 @@
+//! `novault` command-line tool.
+//!
+//! Runs the same crypto core as the web app, natively, so exported files can
+//! be decrypted and backups verified without a browser. Build it with:
+//!
+//! ```text
+//! cargo build --release --no-default-features --features cli --bin novault
+//! ```
+//!
+//! Files are read from `--input` (default stdin) and written to `--output`
+//! (default stdout). Two on-disk formats are accepted:
+//!
+//! - a NoVault container (see `rust::container`), which carries its own
+//!   wrapped DEK; chunked containers are processed as a stream
+//! - the loose format the web app stores today: the raw ciphertext plus a
+//!   JSON fields file holding the hex columns of the file and DEK rows
+//!   (`--fields`)
+//!
+//! The user's secrets file is the JSON of their `user_secrets` row, as written
+//! by `novault keygen`. The password is prompted for on the terminal, or taken
+//! from `NOVAULT_PASSWORD` for unattended jobs.
+//!
+//! The server's peppers (see `rust::pepper`) are read from `NOVAULT_PEPPERS`,
+//! as comma-separated `version:hex` pairs.
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+use serde::{Deserialize, Serialize};
+
+use rust::container::{ContainerHeader, ALG_X25519_AES256GCM_STREAM, CONTAINER_MAGIC, MAX_HEADER_LEN, PREAMBLE_LEN};
+use rust::decrypt_file::DecryptedFileResult;
+use rust::encrypt_file::encrypt_file;
+use rust::key_wrap::KeyWrapScheme;
+use rust::logger::{set_log_level, LogLevel};
+use rust::pepper::register_pepper;
+use rust::recovery::recover_master_key;
+use rust::rotation::RotationProgress;
+use rust::kdf::{calibrate_kdf, needs_kdf_upgrade};
+use rust::masterkey_generator::{encrypt_master_key, rewrap_private_key, EncryptedMasterKey};
+use rust::stream::StreamEncryptor;
+use rust::vault::UnlockedVault;
+use rust::{bytes_to_hex, hex_to_bytes};
+
+/// Read size used when streaming
+const IO_CHUNK: usize = 64 * 1024;
+
+#[derive(Parser)]
+#[command(name = "novault", version, about = "Offline NoVault encryption tool")]
+struct Cli {
+    /// Print the crypto core's info log to stderr; debug messages are
+    /// compiled out of release builds, so only debug builds print them
+    #[arg(short, long, global = true)]
+    verbose: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Generate a new password-protected key pair (like `encrypt_master_key`)
+    Keygen {
+        /// Where to write the secrets JSON (default stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Fit the Argon2id cost to this machine, aiming for an unlock time
+        /// of this many milliseconds
+        #[arg(long)]
+        calibrate: Option<u32>,
+    },
+    /// Change the password of a secrets file, keeping the key pair (like
+    /// `rewrap_private_key`); the new password is read from
+    /// `NOVAULT_NEW_PASSWORD` when set. Also upgrades outdated KDF parameters
+    Passwd {
+        /// The user's secrets JSON, as written by `keygen`
+        #[arg(short, long)]
+        secrets: PathBuf,
+        /// Where to write the new secrets JSON (default stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Create a 24-word recovery phrase for a secrets file, replacing any
+    /// earlier one; the phrase is printed to stderr once
+    RecoveryKey {
+        /// The user's secrets JSON, as written by `keygen`
+        #[arg(short, long)]
+        secrets: PathBuf,
+        /// Where to write the secrets JSON with the recovery key (default stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Set a new password with the recovery phrase; the phrase is read from
+    /// `NOVAULT_RECOVERY_PHRASE` when set
+    Recover {
+        /// The user's secrets JSON, with a recovery key
+        #[arg(short, long)]
+        secrets: PathBuf,
+        /// Where to write the new secrets JSON (default stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Replace the key pair with a new one, keeping the old private key in the
+    /// keyring; the new password is read from `NOVAULT_NEW_PASSWORD` when set.
+    /// Run `rewrap` on the user's files afterwards
+    Rotate {
+        /// The user's secrets JSON, as written by `keygen`
+        #[arg(short, long)]
+        secrets: PathBuf,
+        /// Where to write the new secrets JSON (default stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Move containers addressed to a previous key to the current one, in
+    /// place; files already moved are skipped, so an interrupted run can be
+    /// repeated
+    Rewrap {
+        /// The user's secrets JSON, with the keyring from `rotate`
+        #[arg(short, long)]
+        secrets: PathBuf,
+        /// Container files to update
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Replace the `file_hash` in a loose fields file with the user's keyed
+    /// digest, in place; accepts unkeyed SHA-256 hashes and digests from
+    /// before a `rotate`
+    Rehash {
+        /// The user's secrets JSON, with the keyring from `rotate` if any
+        #[arg(short, long)]
+        secrets: PathBuf,
+        /// Hex fields JSON of the file, to update
+        #[arg(long)]
+        fields: PathBuf,
+        /// The raw ciphertext (default stdin)
+        #[arg(short, long)]
+        input: Option<PathBuf>,
+    },
+    /// Encrypt a file to one or more recipients' public keys
+    Encrypt {
+        /// Recipient's X25519 public key, as hex; repeat for several recipients
+        #[arg(short, long, required = true)]
+        recipient: Vec<String>,
+        /// Write the web app's loose format: raw ciphertext to the output and
+        /// the hex fields to this JSON file, instead of a container
+        #[arg(long)]
+        fields: Option<PathBuf>,
+        /// Plaintext bytes per chunk for container output
+        #[arg(long)]
+        chunk_size: Option<u32>,
+        /// Encrypt as this user (secrets JSON, prompts for the password), so
+        /// recipients can verify who sent the file; container output only
+        #[arg(long, conflicts_with = "fields")]
+        sender: Option<PathBuf>,
+        /// The uploader's secrets JSON (prompts for the password), whose key
+        /// keys the file digest; implied by --sender
+        #[arg(short, long, required_unless_present = "sender", conflicts_with = "sender")]
+        secrets: Option<PathBuf>,
+        #[command(flatten)]
+        io: IoArgs,
+    },
+    /// Decrypt a file with the user's secrets, prompting for the password
+    Decrypt {
+        #[command(flatten)]
+        source: Source,
+        #[command(flatten)]
+        io: IoArgs,
+    },
+    /// Decrypt a file without writing it out, checking every tag and the hash
+    Verify {
+        #[command(flatten)]
+        source: Source,
+        /// Expected keyed digest of the plaintext, as hex; defaults to
+        /// `file_hash` from the fields file
+        #[arg(long)]
+        hash: Option<String>,
+        /// Input file (default stdin)
+        #[arg(short, long)]
+        input: Option<PathBuf>,
+    },
+    /// Print the header of a container; no secrets needed
+    Inspect {
+        /// Input file (default stdin)
+        #[arg(short, long)]
+        input: Option<PathBuf>,
+    },
+}
+
+#[derive(clap::Args)]
+struct IoArgs {
+    /// Input file (default stdin)
+    #[arg(short, long)]
+    input: Option<PathBuf>,
+    /// Output file (default stdout)
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+struct Source {
+    /// The user's secrets JSON, as written by `keygen`
+    #[arg(short, long)]
+    secrets: PathBuf,
+    /// Hex fields JSON for input in the web app's loose format; omit for containers
+    #[arg(long)]
+    fields: Option<PathBuf>,
+}
+
+/// A `user_secrets` row
+#[derive(Serialize, Deserialize)]
+struct Secrets {
+    encrypted_private_key: String,
+    public_key: String,
+    pk_salt: String,
+    pk_nonce: String,
+    // Absent for secrets written before the Ed25519 signing key existed
+    #[serde(default)]
+    encrypted_signing_key: String,
+    #[serde(default)]
+    signing_nonce: String,
+    #[serde(default)]
+    signing_public_key: String,
+    /// The keys sealed under the recovery phrase, if one was created
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    recovery: Option<RecoveryFields>,
+    /// The private keys from before a rotation, encrypted to the current key
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    keyring: String,
+}
+
+/// The hex columns of a recovery key; the phrase itself is never stored
+#[derive(Clone, Serialize, Deserialize)]
+struct RecoveryFields {
+    encrypted_private_key: String,
+    nonce: String,
+    #[serde(default)]
+    encrypted_signing_key: String,
+    #[serde(default)]
+    signing_nonce: String,
+}
+
+/// The hex columns the web app stores for a file and its wrapped DEK
+#[derive(Serialize, Deserialize)]
+struct FileFields {
+    file_nonce: String,
+    encrypted_dek: String,
+    dek_nonce: String,
+    ephemeral_public_key: String,
+    /// Absent in fields written before wrap schemes existed, which used raw ECDH
+    #[serde(default)]
+    wrap_scheme: Option<u8>,
+    /// Absent in fields written before key IDs were recorded
+    #[serde(default)]
+    key_id: String,
+    /// Keyed digest of the plaintext; `encrypt` leaves it empty for `rehash`
+    /// to fill in, and older fields hold an unkeyed SHA-256
+    #[serde(default)]
+    file_hash: String,
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("novault: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<(), String> {
+    set_log_level(if cli.verbose { LogLevel::Trace } else { LogLevel::Warn });
+    register_env_peppers()?;
+
+    match cli.command {
+        Command::Keygen { output, calibrate } => keygen(output.as_deref(), calibrate),
+        Command::Passwd { secrets, output } => passwd(&secrets, output.as_deref()),
+        Command::RecoveryKey { secrets, output } => recovery_key(&secrets, output.as_deref()),
+        Command::Recover { secrets, output } => recover(&secrets, output.as_deref()),
+        Command::Rotate { secrets, output } => rotate(&secrets, output.as_deref()),
+        Command::Rewrap { secrets, files } => rewrap(&secrets, &files),
+        Command::Rehash { secrets, fields, input } => rehash(&secrets, &fields, &mut open_input(input.as_deref())?),
+        Command::Encrypt { recipient, fields, chunk_size, sender, secrets, io } => {
+            if fields.is_some() && recipient.len() > 1 {
+                return Err("--fields stores a single recipient; use container output for several".to_string());
+            }
+            let mut recipients = Vec::with_capacity(recipient.len() * 32);
+            for key in &recipient {
+                recipients.extend(hex_to_bytes(key).map_err(|e| format!("Recipient key: {}", e))?);
+            }
+            let uploader = sender.as_deref().or(secrets.as_deref()).expect("clap requires --secrets without --sender");
+            let uploader = unlock(uploader)?;
+            let mut input = open_input(io.input.as_deref())?;
+            let mut output = open_output(io.output.as_deref())?;
+            match fields {
+                Some(fields) => encrypt_loose(&recipients, &uploader, &fields, &mut input, &mut output),
+                None => encrypt_stream(&recipients, chunk_size, &uploader, sender.is_some(), &mut input, &mut output),
+            }
+        }
+        Command::Decrypt { source, io } => {
+            let mut input = open_input(io.input.as_deref())?;
+            let mut output = open_output(io.output.as_deref())?;
+            let digest = decrypt(&source, &mut input, &mut output)?;
+            eprintln!("digest {}", digest);
+            Ok(())
+        }
+        Command::Verify { source, hash, input } => {
+            let mut input = open_input(input.as_deref())?;
+            let actual = decrypt(&source, &mut input, &mut io::sink())?;
+            let expected = match (hash, &source.fields) {
+                (Some(hash), _) => Some(hash),
+                (None, Some(path)) => Some(read_json::<FileFields>(path)?.file_hash).filter(|h| !h.is_empty()),
+                (None, None) => None,
+            };
+            match expected {
+                Some(expected) if !expected.eq_ignore_ascii_case(&actual) => Err(format!(
+                    "Digest mismatch: expected {}, got {}; `novault rehash` updates unkeyed hashes",
+                    expected, actual
+                )),
+                Some(_) => {
+                    println!("OK digest {} (matches)", actual);
+                    Ok(())
+                }
+                None => {
+                    println!("OK digest {}", actual);
+                    Ok(())
+                }
+            }
+        }
+        Command::Inspect { input } => inspect(&mut open_input(input.as_deref())?),
+    }
+}
+
+fn keygen(output: Option<&Path>, calibrate: Option<u32>) -> Result<(), String> {
+    let password = read_new_password(PASSWORD_ENV)?;
+    let params = calibrate.map(calibrate_kdf).transpose()?;
+    if let Some(params) = &params {
+        eprintln!(
+            "kdf m={} t={} p={}",
+            params.memory_kib(),
+            params.iterations(),
+            params.parallelism()
+        );
+    }
+    let key = encrypt_master_key(&password, params)?;
+    let secrets = secrets_of(&key);
+    let mut out = open_output(output)?;
+    write_json(&mut out, &secrets)?;
+    eprintln!("public key {}", secrets.public_key);
+    eprintln!("fingerprint {}", key.fingerprint()?.hex());
+    Ok(())
+}
+
+fn passwd(secrets_path: &Path, output: Option<&Path>) -> Result<(), String> {
+    let secrets: Secrets = read_json(secrets_path)?;
+    let old_password = read_password("Current password: ")?;
+    let new_password = read_new_password(NEW_PASSWORD_ENV)?;
+    let key = rewrap_private_key(
+        &old_password,
+        &secrets.pk_salt,
+        &hex_field(&secrets.encrypted_private_key, "encrypted_private_key")?,
+        &hex_field(&secrets.pk_nonce, "pk_nonce")?,
+        &new_password,
+        optional_hex_field(&secrets.encrypted_signing_key, "encrypted_signing_key")?,
+        optional_hex_field(&secrets.signing_nonce, "signing_nonce")?,
+        // Only secrets written before signing keys existed get a new one
+        secrets.encrypted_signing_key.trim().is_empty(),
+    )?;
+    let rewrapped = Secrets {
+        recovery: secrets.recovery,
+        keyring: secrets.keyring,
+        ..secrets_of(&key)
+    };
+    print_new_signing_key(&key);
+    write_json(&mut open_output(output)?, &rewrapped)?;
+    eprintln!("public key {}", rewrapped.public_key);
+    Ok(())
+}
+
+fn recovery_key(secrets_path: &Path, output: Option<&Path>) -> Result<(), String> {
+    let secrets: Secrets = read_json(secrets_path)?;
+    let kit = unlock(secrets_path)?.create_recovery_key()?;
+    let updated = Secrets {
+        recovery: Some(RecoveryFields {
+            encrypted_private_key: kit.encrypted_private_key_hex(),
+            nonce: kit.nonce_hex(),
+            encrypted_signing_key: kit.encrypted_signing_key_hex(),
+            signing_nonce: kit.signing_nonce_hex(),
+        }),
+        ..secrets
+    };
+    write_json(&mut open_output(output)?, &updated)?;
+    eprintln!("Recovery phrase; write it down, it is not shown again:");
+    eprintln!("{}", kit.mnemonic());
+    Ok(())
+}
+
+fn recover(secrets_path: &Path, output: Option<&Path>) -> Result<(), String> {
+    let secrets: Secrets = read_json(secrets_path)?;
+    let recovery = secrets
+        .recovery
+        .clone()
+        .ok_or_else(|| format!("{} has no recovery key", secrets_path.display()))?;
+    let phrase = match std::env::var(RECOVERY_PHRASE_ENV) {
+        Ok(phrase) => phrase,
+        Err(_) => prompt_password("Recovery phrase: ")?,
+    };
+    let new_password = read_new_password(NEW_PASSWORD_ENV)?;
+    let key = recover_master_key(
+        &phrase,
+        &hex_field(&recovery.encrypted_private_key, "recovery.encrypted_private_key")?,
+        &hex_field(&recovery.nonce, "recovery.nonce")?,
+        optional_hex_field(&recovery.encrypted_signing_key, "recovery.encrypted_signing_key")?,
+        optional_hex_field(&recovery.signing_nonce, "recovery.signing_nonce")?,
+        &new_password,
+        secrets.encrypted_signing_key.trim().is_empty(),
+        None,
+    )?;
+    let recovered = Secrets {
+        recovery: Some(recovery),
+        keyring: secrets.keyring.clone(),
+        ..secrets_of(&key)
+    };
+    if recovered.public_key != secrets.public_key {
+        return Err("Recovered key does not match the secrets file".to_string());
+    }
+    write_json(&mut open_output(output)?, &recovered)?;
+    eprintln!("public key {}", recovered.public_key);
+    print_new_signing_key(&key);
+    Ok(())
+}
+
+fn rotate(secrets_path: &Path, output: Option<&Path>) -> Result<(), String> {
+    let secrets: Secrets = read_json(secrets_path)?;
+    let mut vault = unlock(secrets_path)?;
+    let new_password = read_new_password(NEW_PASSWORD_ENV)?;
+    let rotated = vault.rotate_key(&new_password, secrets.encrypted_signing_key.trim().is_empty(), None)?;
+    let rotated_secrets = Secrets {
+        keyring: bytes_to_hex(&rotated.keyring()),
+        ..secrets_of(&rotated.master_key())
+    };
+    write_json(&mut open_output(output)?, &rotated_secrets)?;
+    eprintln!("public key {}", rotated_secrets.public_key);
+    print_new_signing_key(&rotated.master_key());
+    if secrets.recovery.is_some() {
+        eprintln!("note: the recovery key holds the old key and was dropped; create a new one with `recovery-key`");
+    }
+    Ok(())
+}
+
+fn rewrap(secrets_path: &Path, files: &[PathBuf]) -> Result<(), String> {
+    let vault = unlock(secrets_path)?;
+    let mut progress = RotationProgress::new(files.len() as u32);
+    for path in files {
+        let mut input = open_input(Some(path))?;
+        let header = read_header(&mut input)?;
+        match vault.rotate_container_header(&header, &mut progress) {
+            Ok(Some(new_header)) => {
+                // Write next to the original and rename, so a crash never leaves a half-written file
+                let temp_path = path.with_extension("novault-rewrap");
+                let mut output = open_output(Some(&temp_path))?;
+                write_all(&mut output, &new_header)?;
+                io::copy(&mut input, &mut output).map_err(|e| format!("{}: {}", path.display(), e))?;
+                output.flush().map_err(|e| e.to_string())?;
+                drop(output);
+                std::fs::rename(&temp_path, path).map_err(|e| format!("{}: {}", path.display(), e))?;
+                eprintln!("rewrapped {}", path.display());
+            }
+            Ok(None) => eprintln!("unchanged {}", path.display()),
+            Err(e) => eprintln!("failed    {}: {}", path.display(), e),
+        }
+    }
+    eprintln!(
+        "{} rewrapped, {} unchanged, {} failed",
+        progress.rewrapped(),
+        progress.unchanged(),
+        progress.failed()
+    );
+    if progress.failed() > 0 {
+        return Err("Some files could not be rewrapped".to_string());
+    }
+    Ok(())
+}
+
+fn rehash(secrets_path: &Path, fields_path: &Path, input: &mut dyn Read) -> Result<(), String> {
+    let vault = unlock(secrets_path)?;
+    let mut fields: FileFields = read_json(fields_path)?;
+    let result = decrypt_loose(&vault, &fields, &read_all(input)?)?;
+    if !result.success() {
+        return Err(result.error_message());
+    }
+    match vault.migrate_file_digest(&result.decrypted_data(), &fields.file_hash)? {
+        Some(digest) => {
+            fields.file_hash = digest;
+            // Write next to the original and rename, as in `rewrap`
+            let temp_path = fields_path.with_extension("novault-rehash");
+            write_json(&mut open_output(Some(&temp_path))?, &fields)?;
+            std::fs::rename(&temp_path, fields_path).map_err(|e| format!("{}: {}", fields_path.display(), e))?;
+            eprintln!("rehashed  {}", fields_path.display());
+        }
+        None => eprintln!("unchanged {}", fields_path.display()),
+    }
+    Ok(())
+}
+
+/// Reports a signing key generated for secrets that had none
+fn print_new_signing_key(key: &EncryptedMasterKey) {
+    if key.signing_key_created() {
+        eprintln!("new signing public key {}", key.signing_public_key_hex());
+    }
+}
+
+/// Prompts for a new password twice, or reads it from `env`
+fn read_new_password(env: &str) -> Result<String, String> {
+    if let Ok(password) = std::env::var(env) {
+        return Ok(password);
+    }
+    let password = prompt_password("New password: ")?;
+    let confirm = prompt_password("Confirm password: ")?;
+    if password != confirm {
+        return Err("Passwords do not match".to_string());
+    }
+    if password.is_empty() {
+        return Err("Password is required".to_string());
+    }
+    Ok(password)
+}
+
+fn secrets_of(key: &EncryptedMasterKey) -> Secrets {
+    Secrets {
+        encrypted_private_key: key.encrypted_private_key_hex(),
+        public_key: key.public_key_hex(),
+        pk_salt: key.salt(),
+        pk_nonce: key.nonce_hex(),
+        encrypted_signing_key: key.encrypted_signing_key_hex(),
+        signing_nonce: key.signing_nonce_hex(),
+        signing_public_key: key.signing_public_key_hex(),
+        recovery: None,
+        keyring: String::new(),
+    }
+}
+
+/// Streams the input into a chunked container, authenticated as the uploader if `as_sender`
+fn encrypt_stream(
+    recipient: &[u8],
+    chunk_size: Option<u32>,
+    uploader: &UnlockedVault,
+    as_sender: bool,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<(), String> {
+    let mut encryptor = if as_sender {
+        uploader.stream_encryptor(recipient, chunk_size)?
+    } else {
+        StreamEncryptor::new(recipient, chunk_size, None, &uploader.file_digest_key()?)?
+    };
+    write_all(output, &encryptor.header())?;
+    let mut buf = vec![0u8; IO_CHUNK];
+    loop {
+        let n = read_some(input, &mut buf)?;
+        if n == 0 {
+            break;
+        }
+        write_all(output, &encryptor.push(&buf[..n])?)?;
+    }
+    write_all(output, &encryptor.finish()?)?;
+    output.flush().map_err(|e| e.to_string())?;
+    print_digest(&encryptor.original_hash_hex());
+    Ok(())
+}
+
+/// Encrypts the whole input the way the web app does, with the DEK fields in a side file
+fn encrypt_loose(
+    recipient: &[u8],
+    uploader: &UnlockedVault,
+    fields_path: &Path,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<(), String> {
+    let plaintext = read_all(input)?;
+    let result = encrypt_file(&plaintext, recipient, Some(KeyWrapScheme::LATEST.id()), &uploader.file_digest_key()?);
+    if !result.success() {
+        return Err(result.error_message());
+    }
+    let fields = FileFields {
+        file_nonce: result.file_nonce_hex(),
+        encrypted_dek: result.encrypted_dek_hex(),
+        dek_nonce: result.dek_nonce_hex(),
+        ephemeral_public_key: result.ephemeral_public_key_hex(),
+        wrap_scheme: Some(result.wrap_scheme()),
+        key_id: result.key_id_hex(),
+        file_hash: result.original_hash_hex(),
+    };
+    write_json(&mut open_output(Some(fields_path))?, &fields)?;
+    write_all(output, &result.encrypted_data())?;
+    output.flush().map_err(|e| e.to_string())
+}
+
+/// Unlocks the private key in a secrets file, prompting for the password
+fn unlock(secrets_path: &Path) -> Result<UnlockedVault, String> {
+    let secrets: Secrets = read_json(secrets_path)?;
+    if needs_kdf_upgrade(&secrets.pk_salt)? {
+        eprintln!("note: {} uses outdated KDF parameters or an old pepper; `novault passwd` upgrades them", secrets_path.display());
+    }
+    let password = read_password("Password: ")?;
+    let mut vault = UnlockedVault::new(
+        &password,
+        &secrets.pk_salt,
+        &hex_field(&secrets.encrypted_private_key, "encrypted_private_key")?,
+        &hex_field(&secrets.pk_nonce, "pk_nonce")?,
+        optional_hex_field(&secrets.encrypted_signing_key, "encrypted_signing_key")?,
+        optional_hex_field(&secrets.signing_nonce, "signing_nonce")?,
+    )?;
+    if let Some(keyring) = optional_hex_field(&secrets.keyring, "keyring")? {
+        vault.load_keyring(&keyring)?;
+    }
+    Ok(vault)
+}
+
+/// Decrypts the input to `output` and returns the keyed digest of the plaintext
+///
+/// The key ID of a verified sender is printed to stderr.
+fn decrypt(source: &Source, input: &mut dyn Read, output: &mut dyn Write) -> Result<String, String> {
+    let vault = unlock(&source.secrets)?;
+
+    if let Some(fields_path) = &source.fields {
+        let fields: FileFields = read_json(fields_path)?;
+        let result = decrypt_loose(&vault, &fields, &read_all(input)?)?;
+        print_sender(&result.sender_key_id_hex());
+        return finish_single(result.success(), result.error_message(), &result.decrypted_data(), result.file_hash_hex(), output);
+    }
+
+    let header = read_header(input)?;
+    let (parsed, _) = ContainerHeader::parse(&header)?;
+    if parsed.algorithm != ALG_X25519_AES256GCM_STREAM {
+        let mut container = header;
+        container.extend(read_all(input)?);
+        let result = vault.decrypt_file_container(&container);
+        print_sender(&result.sender_key_id_hex());
+        return finish_single(result.success(), result.error_message(), &result.decrypted_data(), result.file_hash_hex(), output);
+    }
+
+    let mut decryptor = vault.stream_decryptor(&header)?;
+    let mut buf = vec![0u8; IO_CHUNK];
+    loop {
+        let n = read_some(input, &mut buf)?;
+        if n == 0 {
+            break;
+        }
+        write_all(output, &decryptor.push(&buf[..n])?)?;
+    }
+    write_all(output, &decryptor.finish()?)?;
+    output.flush().map_err(|e| e.to_string())?;
+    print_sender(&decryptor.sender_key_id_hex());
+    Ok(decryptor.file_hash_hex())
+}
+
+/// Decrypts ciphertext in the loose format with its DEK fields
+fn decrypt_loose(vault: &UnlockedVault, fields: &FileFields, ciphertext: &[u8]) -> Result<DecryptedFileResult, String> {
+    Ok(vault.decrypt_file(
+        ciphertext,
+        &hex_field(&fields.ephemeral_public_key, "ephemeral_public_key")?,
+        &hex_field(&fields.encrypted_dek, "encrypted_dek")?,
+        &hex_field(&fields.dek_nonce, "dek_nonce")?,
+        &hex_field(&fields.file_nonce, "file_nonce")?,
+        fields.wrap_scheme,
+        optional_hex_field(&fields.key_id, "key_id")?,
+    ))
+}
+
+/// Prints the keyed digest of an encrypted file, if it has one
+fn print_digest(digest: &str) {
+    if !digest.is_empty() {
+        eprintln!("digest {}", digest);
+    }
+}
+
+fn print_sender(sender_key_id: &str) {
+    if !sender_key_id.is_empty() {
+        eprintln!("sender {}", sender_key_id);
+    }
+}
+
+fn finish_single(
+    success: bool,
+    error_message: String,
+    plaintext: &[u8],
+    hash: String,
+    output: &mut dyn Write,
+) -> Result<String, String> {
+    if !success {
+        return Err(error_message);
+    }
+    write_all(output, plaintext)?;
+    output.flush().map_err(|e| e.to_string())?;
+    Ok(hash)
+}
+
+fn inspect(input: &mut dyn Read) -> Result<(), String> {
+    let header = read_header(input)?;
+    let (parsed, header_len) = ContainerHeader::parse(&header)?;
+    let body_len = io::copy(input, &mut io::sink()).map_err(|e| e.to_string())?;
+
+    println!("format version:       {}", parsed.version);
+    println!("algorithm:            {}", parsed.algorithm);
+    println!("header length:        {} bytes", header_len);
+    println!("ciphertext length:    {} bytes", body_len.saturating_sub(parsed.sender_tags_len() as u64));
+    println!("file nonce:           {}", bytes_to_hex(&parsed.file_nonce));
+    if let Some(chunk_size) = parsed.chunk_size {
+        println!("chunk size:           {} bytes", chunk_size);
+    }
+    println!("recipients:           {}", parsed.recipients.len());
+    for (index, recipient) in parsed.recipients.iter().enumerate() {
+        println!("recipient {}:", index);
+        println!("  key id:               {}", recipient.key_id_hex());
+        println!("  wrap scheme:          {}", recipient.wrap_scheme());
+        println!("  ephemeral public key: {}", recipient.ephemeral_public_key_hex());
+        if !recipient.sender_public_key.is_empty() {
+            println!("  sender public key:    {}", recipient.sender_public_key_hex());
+        }
+        println!("  dek nonce:            {}", recipient.dek_nonce_hex());
+        println!("  encrypted dek:        {}", recipient.encrypted_dek_hex());
+    }
+    if parsed.sender_tag_count > 0 {
+        println!("sender tags:          {}", parsed.sender_tag_count);
+    }
+    Ok(())
+}
+
+/// Reads exactly the container header from the start of the input
+fn read_header(input: &mut dyn Read) -> Result<Vec<u8>, String> {
+    let mut header = vec![0u8; PREAMBLE_LEN];
+    input
+        .read_exact(&mut header)
+        .map_err(|_| "Input is too short to be a container".to_string())?;
+    if header[..4] != CONTAINER_MAGIC {
+        return Err("Input is not a NoVault container; pass --fields for the web app's loose format".to_string());
+    }
+    let body_len = u32::from_be_bytes([header[6], header[7], header[8], header[9]]) as usize;
+    if body_len > MAX_HEADER_LEN {
+        return Err(format!("Container header length {} is too large", body_len));
+    }
+    header.resize(PREAMBLE_LEN + body_len, 0);
+    input
+        .read_exact(&mut header[PREAMBLE_LEN..])
+        .map_err(|_| "Container header is truncated".to_string())?;
+    Ok(header)
+}
+
+/// Environment variable that replaces the password prompt
+const PASSWORD_ENV: &str = "NOVAULT_PASSWORD";
+const PEPPERS_ENV: &str = "NOVAULT_PEPPERS";
+const NEW_PASSWORD_ENV: &str = "NOVAULT_NEW_PASSWORD";
+const RECOVERY_PHRASE_ENV: &str = "NOVAULT_RECOVERY_PHRASE";
+
+/// Registers every `version:hex` pepper listed in `NOVAULT_PEPPERS`
+fn register_env_peppers() -> Result<(), String> {
+    let Ok(peppers) = std::env::var(PEPPERS_ENV) else {
+        return Ok(());
+    };
+    for entry in peppers.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let (version, secret) = entry
+            .split_once(':')
+            .ok_or_else(|| format!("{}: expected comma-separated version:hex pairs", PEPPERS_ENV))?;
+        let version = version
+            .trim()
+            .parse()
+            .map_err(|_| format!("{}: invalid pepper version {:?}", PEPPERS_ENV, version))?;
+        register_pepper(version, &hex_field(secret, PEPPERS_ENV)?)?;
+    }
+    Ok(())
+}
+
+fn read_password(prompt: &str) -> Result<String, String> {
+    if let Ok(password) = std::env::var(PASSWORD_ENV) {
+        return Ok(password);
+    }
+    prompt_password(prompt)
+}
+
+fn prompt_password(prompt: &str) -> Result<String, String> {
+    rpassword::prompt_password(prompt).map_err(|e| format!("Cannot read password: {}", e))
+}
+
+fn hex_field(value: &str, name: &str) -> Result<Vec<u8>, String> {
+    hex_to_bytes(value.trim()).map_err(|e| format!("{}: {}", name, e))
+}
+
+fn optional_hex_field(value: &str, name: &str) -> Result<Option<Vec<u8>>, String> {
+    if value.trim().is_empty() {
+        return Ok(None);
+    }
+    hex_field(value, name).map(Some)
+}
+
+fn open_input(path: Option<&Path>) -> Result<Box<dyn Read>, String> {
+    match path {
+        Some(path) if path != Path::new("-") => {
+            let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            Ok(Box::new(file))
+        }
+        _ => Ok(Box::new(io::stdin().lock())),
+    }
+}
+
+fn open_output(path: Option<&Path>) -> Result<Box<dyn Write>, String> {
+    match path {
+        Some(path) if path != Path::new("-") => {
+            let file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            Ok(Box::new(io::BufWriter::new(file)))
+        }
+        _ => Ok(Box::new(io::stdout().lock())),
+    }
+}
+
+fn read_some(input: &mut dyn Read, buf: &mut [u8]) -> Result<usize, String> {
+    loop {
+        match input.read(buf) {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            result => return result.map_err(|e| e.to_string()),
+        }
+    }
+}
+
+fn read_all(input: &mut dyn Read) -> Result<Vec<u8>, String> {
+    let mut data = Vec::new();
+    input.read_to_end(&mut data).map_err(|e| e.to_string())?;
+    Ok(data)
+}
+
+fn write_all(output: &mut dyn Write, data: &[u8]) -> Result<(), String> {
+    output.write_all(data).map_err(|e| e.to_string())
+}
+
+fn read_json<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<T, String> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    serde_json::from_reader(file).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn write_json<T: Serialize>(output: &mut dyn Write, value: &T) -> Result<(), String> {
+    serde_json::to_writer_pretty(&mut *output, value).map_err(|e| e.to_string())?;
+    writeln!(output).map_err(|e| e.to_string())?;
+    output.flush().map_err(|e| e.to_string())
+}
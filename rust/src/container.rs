@@ -0,0 +1,404 @@
+//! Self-describing binary container for encrypted files.
+//!
+//! A container bundles everything needed to decrypt a file (apart from the
+//! user's own secrets) into a single blob, so the downloaded object alone is
+//! enough to recover the plaintext.
+//!
+//! ```text
+//! offset  size  field
+//! 0       4     magic "NVLT"
+//! 4       1     format version
+//! 5       1     algorithm ID
+//! 6       4     header body length (u32, big-endian)
+//! 10      n     header body
+//! 10+n    ..    ciphertext
+//! ```
+//!
+//! Header body for format version 3 (written by this build):
+//!
+//! ```text
+//! 12 bytes   file nonce
+//! 4 bytes    plaintext chunk size (u32, big-endian; chunked algorithm only)
+//! 2 bytes    recipient count (u16, big-endian; at least 1)
+//! then, per recipient:
+//! 1 byte     key wrap scheme (see [`crate::key_wrap::KeyWrapScheme`])
+//! 8 bytes    recipient key ID (see [`crate::key_wrap::key_id`]; all zeros if unknown)
+//! 32 bytes   ephemeral public key (HPKE: encapsulated key)
+//! 32 bytes   sender public key (HPKE Auth only)
+//! 0/12 bytes DEK nonce (none for HPKE, which derives it)
+//! 2 bytes    encrypted DEK length (u16, big-endian)
+//! n bytes    encrypted DEK
+//! then:
+//! 2 bytes    sender tag count (u16, big-endian)
+//! ```
+//!
+//! The ciphertext is followed by the sender tags, one per sender-authenticated
+//! (HPKE Auth) entry:
+//!
+//! ```text
+//! 8 bytes    recipient key ID
+//! 32 bytes   HPKE encapsulated key
+//! 16 bytes   HPKE Auth seal of an empty message
+//! ```
+//!
+//! The DEK is shared by every recipient, so the HPKE Auth entry alone only
+//! proves who wrapped the DEK: any co-recipient could encrypt other content
+//! under it. Each sender tag is therefore sealed by the sender to one
+//! recipient with a [`ContentCommitment`] (a hash of [`ContainerHeader::context`]
+//! and the whole ciphertext) as its AAD, and the HPKE Auth entries themselves
+//! bind the context. A file reports its sender only if the reader's own tag
+//! opens. Tags sit after the payload so that streams can seal them once the
+//! last chunk is written, and re-wrapping entries (see [`crate::rotation`])
+//! never has to touch them.
+//!
+//! Format version 2 is version 3 without sender tags; its HPKE Auth entries
+//! bind nothing but the DEK, so a sender is only reported for single-recipient
+//! version 2 containers, where no one else holds the DEK.
+//!
+//! Format version 1 had a single recipient without count, scheme or key ID:
+//! the file nonce and chunk size are followed directly by the ephemeral public
+//! key, DEK nonce, length and encrypted DEK, wrapped with the raw ECDH scheme.
+//! It is still accepted by the parser.
+
+use sha2::{Digest, Sha256};
+
+use crate::error::NovaultError;
+use crate::hpke::ENC_LEN;
+use crate::key_wrap::{KeyWrapScheme, WrappedDek, KEY_ID_LEN};
+
+/// Magic bytes at the start of every container
+pub const CONTAINER_MAGIC: [u8; 4] = *b"NVLT";
+
+/// Current container format version
+pub const CONTAINER_VERSION: u8 = 3;
+
+/// Container format version without sender tags
+pub const CONTAINER_VERSION_V2: u8 = 2;
+
+/// Original single-recipient container format version
+pub const CONTAINER_VERSION_V1: u8 = 1;
+
+/// Algorithm ID: DEK wrapped with X25519 ECDH + AES-256-GCM, payload encrypted with AES-256-GCM
+pub const ALG_X25519_AES256GCM: u8 = 1;
+
+/// Algorithm ID: same DEK wrapping, payload split into fixed-size AES-256-GCM
+/// chunks (see [`crate::stream`])
+pub const ALG_X25519_AES256GCM_STREAM: u8 = 2;
+
+/// Length of the fixed preamble (magic, version, algorithm, header body length)
+pub const PREAMBLE_LEN: usize = 4 + 1 + 1 + 4;
+
+/// Largest header body length a reader accepts
+///
+/// Checked before allocating for a header whose length comes from the
+/// preamble. A header with [`u16::MAX`] recipients (at most 123 bytes each)
+/// still fits.
+pub const MAX_HEADER_LEN: usize = 16 * 1024 * 1024;
+
+/// Length of one sender tag (key ID, encapsulated key, AEAD tag)
+pub const SENDER_TAG_LEN: usize = KEY_ID_LEN + ENC_LEN + 16;
+
+/// Domain label of [`ContentCommitment`]
+const COMMITMENT_LABEL: &[u8] = b"novault/content/v3";
+
+/// Header of an encrypted file container
+#[derive(Clone, Debug)]
+pub struct ContainerHeader {
+    pub version: u8,
+    pub algorithm: u8,
+    pub file_nonce: Vec<u8>,
+    /// Plaintext chunk size, present only for the chunked algorithm
+    pub chunk_size: Option<u32>,
+    /// One wrapped DEK per recipient; always exactly one for version 1
+    pub recipients: Vec<WrappedDek>,
+    /// Number of sender tags after the ciphertext; always 0 before version 3
+    pub sender_tag_count: u16,
+}
+
+/// Running SHA-256 over a container's context and ciphertext, which the
+/// sender tags authenticate
+pub(crate) struct ContentCommitment(Sha256);
+
+impl ContainerHeader {
+    /// Serializes the header, including the preamble
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut body = Vec::with_capacity(12 + 4 + 2 + self.recipients.len() * (1 + KEY_ID_LEN + 32 + 12 + 2 + 48));
+        body.extend_from_slice(&self.file_nonce);
+        if let Some(chunk_size) = self.chunk_size {
+            body.extend_from_slice(&chunk_size.to_be_bytes());
+        }
+
+        if self.version == CONTAINER_VERSION_V1 {
+            debug_assert_eq!(self.recipients.len(), 1);
+            write_wrapped_dek(&mut body, &self.recipients[0]);
+        } else {
+            body.extend_from_slice(&(self.recipients.len() as u16).to_be_bytes());
+            for wrapped in &self.recipients {
+                body.push(wrapped.scheme.id());
+                if wrapped.key_id.len() == KEY_ID_LEN {
+                    body.extend_from_slice(&wrapped.key_id);
+                } else {
+                    body.extend_from_slice(&[0u8; KEY_ID_LEN]);
+                }
+                write_wrapped_dek(&mut body, wrapped);
+            }
+        }
+        if self.version >= CONTAINER_VERSION {
+            body.extend_from_slice(&self.sender_tag_count.to_be_bytes());
+        }
+
+        let mut out = Vec::with_capacity(PREAMBLE_LEN + body.len());
+        out.extend_from_slice(&CONTAINER_MAGIC);
+        out.push(self.version);
+        out.push(self.algorithm);
+        out.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        out.extend_from_slice(&body);
+        out
+    }
+
+    /// Parses a header from the start of `data`
+    ///
+    /// # Returns
+    /// The parsed header and the total header length, i.e. the offset at which
+    /// the ciphertext starts
+    pub fn parse(data: &[u8]) -> Result<(ContainerHeader, usize), NovaultError> {
+        let mut reader = ByteReader::new(data);
+
+        if reader.take(4, "Magic")? != CONTAINER_MAGIC {
+            return Err(NovaultError::InvalidContainer("Not a NoVault container (bad magic bytes)".to_string()));
+        }
+
+        let version = reader.u8("Format version")?;
+        if !(CONTAINER_VERSION_V1..=CONTAINER_VERSION).contains(&version) {
+            return Err(NovaultError::UnsupportedVersion(version));
+        }
+
+        let algorithm = reader.u8("Algorithm ID")?;
+        if algorithm != ALG_X25519_AES256GCM && algorithm != ALG_X25519_AES256GCM_STREAM {
+            return Err(NovaultError::UnsupportedAlgorithm(algorithm));
+        }
+
+        let body_len = reader.u32("Header length")? as usize;
+        if body_len > MAX_HEADER_LEN {
+            return Err(NovaultError::InvalidContainer(format!("Header length {} is too large", body_len)));
+        }
+        let mut body = ByteReader::new(reader.take(body_len, "Header")?);
+
+        let file_nonce = body.take(12, "File Nonce")?.to_vec();
+        let chunk_size = if algorithm == ALG_X25519_AES256GCM_STREAM {
+            let size = body.u32("Chunk size")?;
+            if size == 0 {
+                return Err(NovaultError::InvalidContainer("Chunk size must be non-zero".to_string()));
+            }
+            Some(size)
+        } else {
+            None
+        };
+
+        let recipients = if version == CONTAINER_VERSION_V1 {
+            vec![read_wrapped_dek(&mut body, KeyWrapScheme::RawEcdh, Vec::new())?]
+        } else {
+            let count = body.u16("Recipient count")?;
+            if count == 0 {
+                return Err(NovaultError::InvalidContainer("Container has no recipients".to_string()));
+            }
+            let mut recipients = Vec::with_capacity(count as usize);
+            for _ in 0..count {
+                let scheme = KeyWrapScheme::from_id(body.u8("Key wrap scheme")?)?;
+                let key_id = body.take(KEY_ID_LEN, "Key ID")?;
+                let key_id = if key_id == [0u8; KEY_ID_LEN] { Vec::new() } else { key_id.to_vec() };
+                recipients.push(read_wrapped_dek(&mut body, scheme, key_id)?);
+            }
+            recipients
+        };
+        let sender_tag_count = if version >= CONTAINER_VERSION { body.u16("Sender tag count")? } else { 0 };
+
+        if !body.is_empty() {
+            return Err(NovaultError::InvalidContainer("Header has trailing bytes".to_string()));
+        }
+
+        let header = ContainerHeader {
+            version,
+            algorithm,
+            file_nonce,
+            chunk_size,
+            recipients,
+            sender_tag_count,
+        };
+        Ok((header, PREAMBLE_LEN + body_len))
+    }
+
+    /// Everything in the header except the recipient entries and tag count
+    ///
+    /// Sender-authenticated entries are sealed with the context as HPKE AAD,
+    /// and [`ContentCommitment`] starts with it. Empty before version 3, whose
+    /// entries were sealed without AAD.
+    pub fn context(&self) -> Vec<u8> {
+        if self.version < CONTAINER_VERSION {
+            return Vec::new();
+        }
+        let mut context = Vec::with_capacity(2 + 12 + 4);
+        context.push(self.version);
+        context.push(self.algorithm);
+        context.extend_from_slice(&self.file_nonce);
+        if let Some(chunk_size) = self.chunk_size {
+            context.extend_from_slice(&chunk_size.to_be_bytes());
+        }
+        context
+    }
+
+    /// Total length of the sender tags after the ciphertext
+    pub fn sender_tags_len(&self) -> usize {
+        self.sender_tag_count as usize * SENDER_TAG_LEN
+    }
+}
+
+impl ContentCommitment {
+    pub(crate) fn new(header: &ContainerHeader) -> Self {
+        let mut hash = Sha256::new();
+        hash.update(COMMITMENT_LABEL);
+        hash.update(header.context());
+        ContentCommitment(hash)
+    }
+
+    /// Commitment to a single-shot ciphertext
+    pub(crate) fn of(header: &ContainerHeader, ciphertext: &[u8]) -> [u8; 32] {
+        let mut commitment = ContentCommitment::new(header);
+        commitment.update(ciphertext);
+        commitment.finalize()
+    }
+
+    pub(crate) fn update(&mut self, ciphertext: &[u8]) {
+        self.0.update(ciphertext);
+    }
+
+    pub(crate) fn finalize(self) -> [u8; 32] {
+        self.0.finalize().into()
+    }
+}
+
+fn write_wrapped_dek(body: &mut Vec<u8>, wrapped: &WrappedDek) {
+    body.extend_from_slice(&wrapped.ephemeral_public_key);
+    if wrapped.scheme.is_authenticated() {
+        body.extend_from_slice(&wrapped.sender_public_key);
+    }
+    body.extend_from_slice(&wrapped.dek_nonce);
+    body.extend_from_slice(&(wrapped.encrypted_dek.len() as u16).to_be_bytes());
+    body.extend_from_slice(&wrapped.encrypted_dek);
+}
+
+fn read_wrapped_dek(body: &mut ByteReader, scheme: KeyWrapScheme, key_id: Vec<u8>) -> Result<WrappedDek, NovaultError> {
+    let ephemeral_public_key = body.take(32, "Ephemeral Public Key")?.to_vec();
+    let sender_public_key = if scheme.is_authenticated() {
+        body.take(32, "Sender Public Key")?.to_vec()
+    } else {
+        Vec::new()
+    };
+    let dek_nonce = body.take(scheme.nonce_len(), "DEK Nonce")?.to_vec();
+    let dek_len = body.u16("Encrypted DEK length")? as usize;
+    let encrypted_dek = body.take(dek_len, "Encrypted DEK")?.to_vec();
+    Ok(WrappedDek {
+        scheme,
+        key_id,
+        ephemeral_public_key,
+        dek_nonce,
+        encrypted_dek,
+        sender_public_key,
+    })
+}
+
+/// Builds a complete container from a header, ciphertext and sender tags
+pub fn build_container(header: &ContainerHeader, ciphertext: &[u8], sender_tags: &[u8]) -> Vec<u8> {
+    debug_assert_eq!(sender_tags.len(), header.sender_tags_len());
+    let mut out = header.to_bytes();
+    out.extend_from_slice(ciphertext);
+    out.extend_from_slice(sender_tags);
+    out
+}
+
+/// Splits a container into its header, ciphertext and sender tags
+pub fn parse_container(data: &[u8]) -> Result<(ContainerHeader, &[u8], &[u8]), NovaultError> {
+    let (header, header_len) = ContainerHeader::parse(data)?;
+    let body = &data[header_len..];
+    let ciphertext_len = body
+        .len()
+        .checked_sub(header.sender_tags_len())
+        .ok_or_else(|| NovaultError::Truncated("Container is truncated: sender tags are missing".to_string()))?;
+    let (ciphertext, sender_tags) = body.split_at(ciphertext_len);
+    Ok((header, ciphertext, sender_tags))
+}
+
+/// Minimal cursor over a byte slice used for parsing headers
+pub(crate) struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        ByteReader { data, pos: 0 }
+    }
+
+    pub(crate) fn take(&mut self, len: usize, name: &str) -> Result<&'a [u8], NovaultError> {
+        let remaining = self.data.len() - self.pos;
+        if len > remaining {
+            return Err(NovaultError::Truncated(format!(
+                "{} truncated: need {} bytes, got {}",
+                name, len, remaining
+            )));
+        }
+        let slice = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(slice)
+    }
+
+    pub(crate) fn u8(&mut self, name: &str) -> Result<u8, NovaultError> {
+        Ok(self.take(1, name)?[0])
+    }
+
+    pub(crate) fn u16(&mut self, name: &str) -> Result<u16, NovaultError> {
+        let bytes = self.take(2, name)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub(crate) fn u32(&mut self, name: &str) -> Result<u32, NovaultError> {
+        let bytes = self.take(4, name)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pos == self.data.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_lengths_are_capped() {
+        let recipient = WrappedDek {
+            scheme: KeyWrapScheme::HpkeAuth,
+            key_id: vec![1; KEY_ID_LEN],
+            ephemeral_public_key: vec![2; 32],
+            dek_nonce: Vec::new(),
+            encrypted_dek: vec![3; 48],
+            sender_public_key: vec![4; 32],
+        };
+        let header = ContainerHeader {
+            version: CONTAINER_VERSION,
+            algorithm: ALG_X25519_AES256GCM_STREAM,
+            file_nonce: vec![0; 12],
+            chunk_size: Some(1024),
+            recipients: vec![recipient; u16::MAX as usize],
+            sender_tag_count: u16::MAX,
+        };
+        let bytes = header.to_bytes();
+        assert!(bytes.len() - PREAMBLE_LEN <= MAX_HEADER_LEN);
+        assert_eq!(ContainerHeader::parse(&bytes).unwrap().0.recipients.len(), u16::MAX as usize);
+
+        let mut oversized = bytes[..PREAMBLE_LEN].to_vec();
+        oversized[6..10].copy_from_slice(&(MAX_HEADER_LEN as u32 + 1).to_be_bytes());
+        assert!(matches!(ContainerHeader::parse(&oversized), Err(NovaultError::InvalidContainer(_))));
+    }
+}
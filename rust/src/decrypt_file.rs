@@ -1,35 +1,27 @@
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 use aes_gcm::{
     Aes256Gcm, Nonce, aead::{Aead, KeyInit, generic_array::GenericArray}
 };
-use x25519_dalek::{PublicKey, StaticSecret};
 
-pub use crate::{bytes_to_hex, log};
+pub use crate::bytes_to_hex;
 pub use crate::encrypt_file::hash_file;
-
-fn validate_inputs(private_key: &[u8], ephemeral_public_key: &[u8], dek_nonce: &[u8], file_nonce: &[u8]) -> Result<(), String> {
-    check_len(32, private_key, "Private Key")?;
-    check_len(32, ephemeral_public_key, "Ephemeral Public Key")?;
-    check_len(12, dek_nonce, "DEK Nonce")?;
-    check_len(12, file_nonce, "File Nonce")?;
-    Ok(())
-}
-
-fn check_len(max: usize, input: &[u8], name: &str) -> Result<(), String> {
-    if input.len() != max {
-        Err(format!("{} must be {} bytes, got {}", name, max, input.len()))
-    } else {
-        Ok(())
-    }    
-}
+use crate::container::{parse_container, ContainerHeader, ContentCommitment, ALG_X25519_AES256GCM, CONTAINER_VERSION, CONTAINER_VERSION_V2};
+use crate::error::NovaultError;
+use crate::file_digest::{file_digest_hex, file_digest_key};
+use crate::key_wrap::{check_len, open_sender_tag, unwrap_dek_for_recipient, WrappedDek};
+use crate::masterkey_decryptor::decrypt_private_key_bytes;
+use crate::secret::SecretKey;
+use crate::logger::{log_debug, log_info, log_warn};
 
 /// Result of file decryption operation
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub struct DecryptedFileResult {
     success: bool,
     decrypted_data: Vec<u8>,
     file_hash_hex: String,
-    error_message: String,
+    sender_key_id: Vec<u8>,
+    error: Option<NovaultError>,
 }
 
 /// Decrypts file data using hybrid decryption (X25519 + AES-256-GCM)
@@ -53,10 +45,14 @@ pub struct DecryptedFileResult {
 /// * `encrypted_dek` - The encrypted DEK bytes
 /// * `dek_nonce` - The nonce used for DEK encryption (12 bytes)
 /// * `file_nonce` - The nonce used for file encryption (12 bytes)
+/// * `wrap_scheme` - The key wrap scheme ID stored with the DEK; defaults to 1 (raw ECDH)
+/// * `key_id` - The recipient key ID stored with the DEK, if any (8 bytes); a DEK
+///   for another key then fails with `NOT_RECIPIENT` without a decryption attempt
 /// 
 /// # Returns
-/// DecryptedFileResult containing decrypted data and its hash for verification
-#[wasm_bindgen]
+/// DecryptedFileResult containing decrypted data and its keyed digest for verification
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[allow(clippy::too_many_arguments)]
 pub fn decrypt_file(
     encrypted_data: &[u8], 
     password: &str,
@@ -67,136 +63,360 @@ pub fn decrypt_file(
     encrypted_dek: &[u8],
     dek_nonce: &[u8],
     file_nonce: &[u8],
+    wrap_scheme: Option<u8>,
+    key_id: Option<Vec<u8>>,
 ) -> DecryptedFileResult {
-    log("[decrypt_file] Starting file decryption...");
-    log(&format!("[decrypt_file] Encrypted size: {} bytes", encrypted_data.len()));
+    log_debug!("[decrypt_file] Starting file decryption...");
+    log_debug!("[decrypt_file] Encrypted size: {} bytes", encrypted_data.len());
 
-    // Step 1: Decrypt the private key from the user's secrets
-    log("[decrypt_file] Decrypting private key...");
-    
-    let key_result = crate::masterkey_decryptor::decrypt_private_key(
-        password,
-        pk_salt,
-        encrypted_private_key,
-        pk_nonce
+    let wrapped_dek = match WrappedDek::from_fields(ephemeral_public_key, encrypted_dek, dek_nonce, wrap_scheme, key_id.as_deref()) {
+        Ok(wrapped) => wrapped,
+        Err(e) => return DecryptedFileResult::failed(e),
+    };
+
+    let private_key_bytes = match unlock_private_key(password, pk_salt, encrypted_private_key, pk_nonce) {
+        Ok(key) => key,
+        Err(e) => return DecryptedFileResult::failed(e),
+    };
+
+    decrypt_with_private_key(encrypted_data, &private_key_bytes, &loose_header(file_nonce, wrapped_dek), &[])
+        .with_digest(&file_digest_key(&private_key_bytes))
+}
+
+/// Decrypts a file stored as a single self-describing container
+///
+/// Same as [`decrypt_file`], but the file nonce, ephemeral public key and
+/// wrapped DEK are read from the container header instead of being passed in
+/// separately. For a container with several recipients, the entry matching
+/// the user's key is used.
+///
+/// # Arguments
+/// * `container` - The container bytes produced by `EncryptedFileResult.container`
+/// * `password` - The user's master password
+/// * `pk_salt` - Salt used for deriving the key encryption key
+/// * `encrypted_private_key` - The encrypted private key bytes (48 bytes)
+/// * `pk_nonce` - The nonce used for private key encryption (12 bytes)
+///
+/// # Returns
+/// DecryptedFileResult containing decrypted data and its keyed digest for verification
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn decrypt_file_container(
+    container: &[u8],
+    password: &str,
+    pk_salt: &str,
+    encrypted_private_key: &[u8],
+    pk_nonce: &[u8],
+) -> DecryptedFileResult {
+    let (header, ciphertext, sender_tags) = match read_container(container) {
+        Ok(parsed) => parsed,
+        Err(e) => return DecryptedFileResult::failed(e),
+    };
+
+    let private_key_bytes = match unlock_private_key(password, pk_salt, encrypted_private_key, pk_nonce) {
+        Ok(key) => key,
+        Err(e) => return DecryptedFileResult::failed(e),
+    };
+
+    decrypt_with_private_key(ciphertext, &private_key_bytes, &header, sender_tags)
+        .with_digest(&file_digest_key(&private_key_bytes))
+}
+
+/// Parses a single-shot container, rejecting chunked ones
+///
+/// # Returns
+/// The header, the ciphertext and the sender tags
+pub(crate) fn read_container(container: &[u8]) -> Result<(ContainerHeader, &[u8], &[u8]), NovaultError> {
+    log_debug!("[decrypt_file_container] Parsing container...");
+
+    let (header, ciphertext, sender_tags) = parse_container(container).map_err(|e| {
+        log_warn!("[decrypt_file_container] Invalid container: {}", e);
+        e
+    })?;
+    log_debug!(
+        "[decrypt_file_container] Container v{} (algorithm {}), ciphertext: {} bytes",
+        header.version, header.algorithm, ciphertext.len()
     );
 
-    if !key_result.success() {
-         log(&format!("[decrypt_file] Private key decryption failed: {}", key_result.error_message()));
-         return DecryptedFileResult {
-            success: false,
-            decrypted_data: vec![],
-            file_hash_hex: String::new(),
-            error_message: format!("Private key decryption failed: {}", key_result.error_message()),
-        };
+    if header.chunk_size.is_some() {
+        log_warn!("[decrypt_file_container] Chunked container passed to single-shot decryption");
+        return Err(NovaultError::InvalidInput("Container is chunked; use StreamDecryptor instead".to_string()));
     }
-    
-    let private_key_bytes = key_result.private_key();
 
-    if let Err(e) = validate_inputs(&private_key_bytes, ephemeral_public_key, dek_nonce, file_nonce) {
-        log(&format!("[decrypt_file] Input validation failed: {}", e));
-        return DecryptedFileResult {
-            success: false,
-            decrypted_data: vec![],
-            file_hash_hex: String::new(),
-            error_message: e,
-        };
+    Ok((header, ciphertext, sender_tags))
+}
+
+/// The header of a file stored as loose fields, as if it were a version 2 container
+pub(crate) fn loose_header(file_nonce: &[u8], wrapped_dek: WrappedDek) -> ContainerHeader {
+    ContainerHeader {
+        version: CONTAINER_VERSION_V2,
+        algorithm: ALG_X25519_AES256GCM,
+        file_nonce: file_nonce.to_vec(),
+        chunk_size: None,
+        recipients: vec![wrapped_dek],
+        sender_tag_count: 0,
     }
+}
 
-    log("[decrypt_file] Performing ECDH to derive shared secret...");
-    let private_key_array: [u8; 32] = private_key_bytes.try_into().unwrap();
-    let ephemeral_public_array: [u8; 32] = ephemeral_public_key.try_into().unwrap();
+/// Step 1: Decrypt the private key from the user's secrets
+pub(crate) fn unlock_private_key(
+    password: &str,
+    pk_salt: &str,
+    encrypted_private_key: &[u8],
+    pk_nonce: &[u8],
+) -> Result<SecretKey, NovaultError> {
+    log_debug!("[decrypt_file] Decrypting private key...");
     
-    let private_key = StaticSecret::from(private_key_array);
-    let ephemeral_public = PublicKey::from(ephemeral_public_array);
+    let private_key = decrypt_private_key_bytes(password, pk_salt, encrypted_private_key, pk_nonce)
+        .map_err(|e| {
+            log_warn!("[decrypt_file] Private key decryption failed: {}", e);
+            e
+        })?;
     
-    let shared_secret = private_key.diffie_hellman(&ephemeral_public);
-    log("[decrypt_file] Shared secret derived via ECDH");
-
-    // Step 3: Decrypt the DEK using the shared secret
-    log("[decrypt_file] Decrypting DEK...");
-    let shared_key = GenericArray::from_slice(shared_secret.as_bytes());
-    let dek_cipher = Aes256Gcm::new(shared_key);
-    let dek_nonce_ga = Nonce::from_slice(dek_nonce);
-
-    let dek = match dek_cipher.decrypt(dek_nonce_ga, encrypted_dek) {
-        Ok(decrypted) => {
-            log(&format!("[decrypt_file] DEK decrypted! Size: {} bytes", decrypted.len()));
-            decrypted
+    SecretKey::from_slice(private_key.expose(), "Private Key")
+}
+
+/// Steps 2-4: Unwrap the DEK from the caller's entry and decrypt the file
+///
+/// A sender is reported only once [`verified_sender_key_id`] accepts it.
+pub(crate) fn decrypt_with_private_key(
+    encrypted_data: &[u8],
+    private_key_bytes: &SecretKey,
+    header: &ContainerHeader,
+    sender_tags: &[u8],
+) -> DecryptedFileResult {
+    let file_nonce = header.file_nonce.as_slice();
+    if let Err(e) = check_len(12, file_nonce, "File Nonce") {
+        log_warn!("[decrypt_file] Input validation failed: {}", e);
+        return DecryptedFileResult::failed(e);
+    }
+
+    // Steps 2-3: ECDH with the ephemeral public key, then decrypt the DEK
+    log_debug!("[decrypt_file] Decrypting DEK...");
+    let (dek, entry) = match unwrap_dek_for_recipient(private_key_bytes, &header.recipients, &header.context()) {
+        Ok(unwrapped) => unwrapped,
+        Err(e) => {
+            log_debug!("[decrypt_file] {}", e);
+            return DecryptedFileResult::failed(e);
         }
+    };
+
+    // Step 4: Decrypt the file using the DEK
+    log_debug!("[decrypt_file] Decrypting file data...");
+    let dek_key = GenericArray::from_slice(dek.expose());
+    let file_cipher = Aes256Gcm::new(dek_key);
+    let file_nonce_ga = Nonce::from_slice(file_nonce);
+
+    let decrypted = match file_cipher.decrypt(file_nonce_ga, encrypted_data) {
+        Ok(decrypted) => decrypted,
         Err(e) => {
-            log(&format!("[decrypt_file] DEK decryption failed: {}", e));
-            return DecryptedFileResult {
-                success: false,
-                decrypted_data: vec![],
-                file_hash_hex: String::new(),
-                error_message: "DEK decryption failed. Invalid private key or corrupted data.".to_string(),
-            };
+            log_warn!("[decrypt_file] File decryption failed: {}", e);
+            return DecryptedFileResult::failed(NovaultError::PayloadAuthFailed { chunk: None });
         }
     };
+    let sender_key_id = match verified_sender_key_id(private_key_bytes, header, entry, sender_tags, || {
+        ContentCommitment::of(header, encrypted_data)
+    }) {
+        Ok(sender_key_id) => sender_key_id,
+        Err(e) => return DecryptedFileResult::failed(e),
+    };
 
-    if dek.len() != 32 {
-        log(&format!("[decrypt_file] Invalid DEK length after decryption: {}", dek.len()));
-        return DecryptedFileResult {
+    log_info!("[decrypt_file] Decryption successful! Decrypted size: {} bytes", decrypted.len());
+    DecryptedFileResult {
+        success: true,
+        decrypted_data: decrypted,
+        file_hash_hex: String::new(),
+        sender_key_id,
+        error: None,
+    }
+}
+
+/// Key ID of the sender of the caller's entry, if the sender provably wrote the content
+///
+/// Version 3 containers prove it with the caller's sender tag over
+/// `commitment`, which is only computed for sender-authenticated entries.
+/// Version 2 entries only prove who wrapped the DEK, so their sender is
+/// reported only when the caller is the sole recipient.
+///
+/// # Returns
+/// The sender's key ID, or an empty vector if the entry is anonymous
+///
+/// # Errors
+/// [`NovaultError::SenderAuthFailed`] if the sender tag is missing or does not match
+pub(crate) fn verified_sender_key_id(
+    private_key: &SecretKey,
+    header: &ContainerHeader,
+    entry: &WrappedDek,
+    sender_tags: &[u8],
+    commitment: impl FnOnce() -> [u8; 32],
+) -> Result<Vec<u8>, NovaultError> {
+    if !entry.scheme.is_authenticated() {
+        return Ok(Vec::new());
+    }
+    if header.version < CONTAINER_VERSION {
+        if header.recipients.len() == 1 {
+            return Ok(entry.sender_key_id());
+        }
+        log_warn!("[decrypt_file] Version {} container with several recipients; not reporting a sender", header.version);
+        return Ok(Vec::new());
+    }
+    open_sender_tag(private_key, entry, sender_tags, &commitment())?;
+    Ok(entry.sender_key_id())
+}
+
+impl DecryptedFileResult {
+    pub(crate) fn failed(error: NovaultError) -> Self {
+        DecryptedFileResult {
             success: false,
             decrypted_data: vec![],
             file_hash_hex: String::new(),
-            error_message: format!("Decrypted DEK must be 32 bytes, got {}", dek.len()),
-        };
+            sender_key_id: vec![],
+            error: Some(error),
+        }
     }
 
-    // Step 4: Decrypt the file using the DEK
-    log("[decrypt_file] Decrypting file data...");
-    let dek_key = GenericArray::from_slice(&dek);
-    let file_cipher = Aes256Gcm::new(dek_key);
-    let file_nonce_ga = Nonce::from_slice(file_nonce);
-
-    match file_cipher.decrypt(file_nonce_ga, encrypted_data) {
-        Ok(decrypted) => {
-            log(&format!("[decrypt_file] Decryption successful! Decrypted size: {} bytes", decrypted.len()));
-            
-            // Compute hash of decrypted file for verification
-            let file_hash = hash_file(&decrypted);
-            log(&format!("[decrypt_file] Decrypted file hash: {}", file_hash));
-            
-            DecryptedFileResult {
-                success: true,
-                decrypted_data: decrypted,
-                file_hash_hex: file_hash,
-                error_message: String::new(),
-            }
+    /// Sets `file_hash_hex` to the keyed digest of the decrypted data
+    pub(crate) fn with_digest(mut self, digest_key: &SecretKey) -> Self {
+        if self.success {
+            self.file_hash_hex = file_digest_hex(digest_key, &self.decrypted_data);
+            log_debug!("[decrypt_file] Decrypted file digest: {}", self.file_hash_hex);
         }
-        Err(e) => {
-            log(&format!("[decrypt_file] File decryption failed: {}", e));
-            DecryptedFileResult {
-                success: false,
-                decrypted_data: vec![],
-                file_hash_hex: String::new(),
-                error_message: "File decryption failed. Invalid DEK or corrupted data.".to_string(),
-            }
+        self
+    }
+
+    /// The decrypted data and sender key ID, or the error decryption failed with
+    pub(crate) fn into_data(self) -> Result<(Vec<u8>, Vec<u8>), NovaultError> {
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok((self.decrypted_data, self.sender_key_id)),
         }
     }
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 impl DecryptedFileResult {
-    #[wasm_bindgen(getter)]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
     pub fn success(&self) -> bool {
         self.success
     }
 
-    #[wasm_bindgen(getter)]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
     pub fn decrypted_data(&self) -> Vec<u8> {
         self.decrypted_data.clone()
     }
 
-    #[wasm_bindgen(getter)]
+    /// Keyed digest of the decrypted data (see [`crate::file_digest`]), to
+    /// compare with the stored `file_hash`
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
     pub fn file_hash_hex(&self) -> String {
         self.file_hash_hex.clone()
     }
 
-    #[wasm_bindgen(getter)]
+    /// Key ID of the verified sender (see [`crate::key_wrap::key_id`])
+    ///
+    /// Set only for files encrypted with sender authentication, where the DEK
+    /// opens only with the sender's public key and the sender sealed the
+    /// content to this recipient. Empty for anonymous files: anyone who knows
+    /// the recipient's public key could have made them.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn sender_key_id_hex(&self) -> String {
+        bytes_to_hex(&self.sender_key_id)
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
     pub fn error_message(&self) -> String {
-        self.error_message.clone()
+        self.error.as_ref().map(ToString::to_string).unwrap_or_default()
+    }
+
+    /// Stable code of the failure (see [`crate::error::ErrorCode`]), or an empty string on success
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn error_code(&self) -> String {
+        self.error.as_ref().map(|e| e.code().as_str().to_string()).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::build_container;
+    use crate::encrypt_file::encrypt_payload;
+    use crate::key_wrap::{generate_dek, key_id, public_key_of, KeyWrapScheme};
+
+    /// Sender and two recipients, with the recipients' public keys concatenated
+    fn parties() -> (SecretKey, SecretKey, SecretKey, Vec<u8>) {
+        let (sender, alice, bob) = (generate_dek(), generate_dek(), generate_dek());
+        let recipients = [public_key_of(&alice), public_key_of(&bob)].concat();
+        (sender, alice, bob, recipients)
+    }
+
+    fn decrypt(container: &[u8], private_key: &SecretKey) -> DecryptedFileResult {
+        let (header, ciphertext, sender_tags) = read_container(container).unwrap();
+        decrypt_with_private_key(ciphertext, private_key, &header, sender_tags)
+    }
+
+    #[test]
+    fn every_recipient_verifies_the_sender() {
+        let (sender, alice, bob, recipients) = parties();
+        let container = encrypt_payload(b"minutes", &recipients, KeyWrapScheme::HpkeAuth, Some(&sender))
+            .into_container()
+            .unwrap();
+
+        for recipient in [&alice, &bob] {
+            let result = decrypt(&container, recipient);
+            assert!(result.success(), "{}", result.error_message());
+            assert_eq!(result.decrypted_data(), b"minutes");
+            assert_eq!(result.sender_key_id, key_id(&public_key_of(&sender)));
+        }
+    }
+
+    #[test]
+    fn co_recipient_cannot_forge_content() {
+        let (sender, alice, bob, recipients) = parties();
+        let container = encrypt_payload(b"pay 10 to carol", &recipients, KeyWrapScheme::HpkeAuth, Some(&sender))
+            .into_container()
+            .unwrap();
+
+        // Bob re-encrypts new content under the DEK he shares with Alice
+        let (header, _, sender_tags) = read_container(&container).unwrap();
+        let (dek, _) = unwrap_dek_for_recipient(&bob, &header.recipients, &header.context()).unwrap();
+        let forged = Aes256Gcm::new(GenericArray::from_slice(dek.expose()))
+            .encrypt(Nonce::from_slice(&header.file_nonce), b"pay 99 to bob".as_slice())
+            .unwrap();
+
+        let result = decrypt(&build_container(&header, &forged, sender_tags), &alice);
+        assert_eq!(result.error_code(), "SENDER_AUTH_FAILED");
+
+        // Dropping the sender tags does not help either
+        let mut stripped = header.clone();
+        stripped.sender_tag_count = 0;
+        let result = decrypt(&build_container(&stripped, &forged, &[]), &alice);
+        assert_eq!(result.error_code(), "SENDER_AUTH_FAILED");
+    }
+
+    #[test]
+    fn entries_do_not_open_in_an_older_container_version() {
+        let (sender, alice, _, recipients) = parties();
+        let container = encrypt_payload(b"minutes", &recipients, KeyWrapScheme::HpkeAuth, Some(&sender))
+            .into_container()
+            .unwrap();
+
+        // A version 2 container with only Alice's entry would report the
+        // sender without a tag, but the entry is bound to version 3
+        let (mut header, ciphertext, _) = read_container(&container).unwrap();
+        header.version = crate::container::CONTAINER_VERSION_V2;
+        header.recipients.truncate(1);
+        header.sender_tag_count = 0;
+        let result = decrypt(&build_container(&header, ciphertext, &[]), &alice);
+        assert_eq!(result.error_code(), "DEK_UNWRAP_FAILED");
+    }
+
+    #[test]
+    fn anonymous_files_report_no_sender() {
+        let (_, alice, _, recipients) = parties();
+        let container = encrypt_payload(b"minutes", &recipients, KeyWrapScheme::HpkeBase, None)
+            .into_container()
+            .unwrap();
+        let result = decrypt(&container, &alice);
+        assert!(result.success(), "{}", result.error_message());
+        assert!(result.sender_key_id.is_empty());
     }
 }
@@ -0,0 +1,515 @@
+//! Per-device keys, enrolled by the master key and unlocked with a PIN.
+//!
+//! Typing the master password on every device is slow (the KEK uses the full
+//! Argon2id cost) and spreads the password around. Instead, each device gets
+//! its own X25519 key pair, sealed under a short PIN with a lighter KDF:
+//!
+//! 1. The new device calls [`create_device_key`] and sends its public key to
+//!    a device where the vault is unlocked.
+//! 2. That vault calls [`crate::vault::UnlockedVault::authorize_device`],
+//!    which encrypts the master keys to the device key as a
+//!    sender-authenticated container (the device grant), and adds the device
+//!    to the [`DeviceList`].
+//! 3. The device unlocks with [`crate::vault::UnlockedVault::unlock_with_device`],
+//!    using only its PIN, the stored grant and the verified device list.
+//!
+//! The PIN KEK uses the registered pepper like the password KEK, but the web
+//! app hands the peppers to every signed-in user, so they do not stop an
+//! offline guess at the PIN once the sealed device key is stolen. Only the
+//! PIN protects it: keep the sealed key on the device rather than on the
+//! server, and PINs must be at least [`MIN_PIN_LEN`] characters, or
+//! [`MIN_NUMERIC_PIN_LEN`] digits for an all-digit PIN.
+//!
+//! The device list is signed with the master signing key, so clients can tell
+//! which device keys the user authorized. Revoking a device removes it from
+//! the list, and a device refuses to open its grant once it is no longer in
+//! the list; the server should also delete the grant. A revoked device that was
+//! already compromised has seen the master keys, so after losing a device,
+//! also rotate the master key pair.
+//!
+//! A device grant (the plaintext inside the container) is [`GRANT_LEN`] bytes:
+//!
+//! ```text
+//! version (1) | flags (1) | private key (32) | signing seed (32)
+//! ```
+//!
+//! Bit 0 of `flags` is set if the grant includes a signing key; otherwise the
+//! seed is zero.
+//!
+//! A signed device list is:
+//!
+//! ```text
+//! magic "NVDL" (4) | format version (1) | list version (u32, big-endian)
+//! | device count (u16, big-endian)
+//! | per device: public key (32) | name length (1) | name (UTF-8)
+//! | Ed25519 signature (64)
+//! ```
+//!
+//! The signature covers [`DEVICE_LIST_CONTEXT`] followed by everything before
+//! it. The list version goes up with every change; clients remember the
+//! highest one they have seen, so the server cannot roll back a revocation.
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+use aes_gcm::aead::OsRng;
+use argon2::password_hash::SaltString;
+use zeroize::Zeroizing;
+
+use crate::bytes_to_hex;
+use crate::container::ByteReader;
+use crate::decrypt_file::{decrypt_with_private_key, read_container};
+use crate::encrypt_file::encrypt_payload;
+use crate::error::NovaultError;
+use crate::kdf::{derive_key, encode_salt, params_for_new_key, KdfParams};
+use crate::key_wrap::{check_len, key_id, public_key_of, KeyWrapScheme};
+use crate::masterkey_decryptor::open_keys_with;
+use crate::masterkey_generator::{generate_private_key, seal_key};
+use crate::pepper::{current_pepper_version, LEGACY_PEPPER_VERSION};
+use crate::secret::SecretKey;
+use crate::signing::{self, SIGNATURE_LEN};
+use crate::get_key_encryption_key;
+use crate::logger::{log_debug, log_info, log_warn};
+
+/// Argon2id parameters for device PINs: [`KdfParams::MINIMUM`]
+pub const DEVICE_KDF_PARAMS: KdfParams = KdfParams::MINIMUM;
+
+/// Shortest accepted device PIN, in characters
+pub const MIN_PIN_LEN: usize = 8;
+
+/// Shortest accepted device PIN made only of digits
+pub const MIN_NUMERIC_PIN_LEN: usize = 12;
+
+/// Version byte of the device grant format
+pub const GRANT_VERSION: u8 = 1;
+
+/// Size of a decrypted device grant in bytes
+pub const GRANT_LEN: usize = 2 + 64;
+
+/// Magic bytes at the start of a signed device list
+pub const DEVICE_LIST_MAGIC: [u8; 4] = *b"NVDL";
+
+/// Current device list format version
+pub const DEVICE_LIST_VERSION: u8 = 1;
+
+/// Prefix of the message the device list signature covers
+pub const DEVICE_LIST_CONTEXT: &[u8] = b"novault/device-list/v1\0";
+
+/// Flag set if a grant includes a signing key
+const FLAG_SIGNING_KEY: u8 = 0x01;
+
+/// A device's key pair, with the private key sealed under its PIN
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct DeviceKey {
+    salt: String,
+    nonce: Vec<u8>,
+    encrypted_private_key: Vec<u8>,
+    public_key: Vec<u8>,
+}
+
+/// One authorized device
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone)]
+pub struct DeviceEntry {
+    public_key: Vec<u8>,
+    name: String,
+}
+
+/// The devices the user has authorized
+///
+/// Edit it with [`DeviceList::add`] and [`DeviceList::revoke`], then sign it
+/// with [`crate::vault::UnlockedVault::sign_device_list`].
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Default)]
+pub struct DeviceList {
+    version: u32,
+    devices: Vec<DeviceEntry>,
+    /// Set by [`DeviceList::verify`]; any edit clears it
+    verified: bool,
+}
+
+/// Generates a key pair for this device and seals it under a PIN
+///
+/// # Arguments
+/// * `pin` - The device PIN, at least [`MIN_PIN_LEN`] characters, or
+///   [`MIN_NUMERIC_PIN_LEN`] if it is all digits
+/// * `params` - Argon2id parameters for the PIN KEK; defaults to [`DEVICE_KDF_PARAMS`]
+///
+/// # Returns
+/// DeviceKey; store all of its fields on the device or server, and send the
+/// public key to an unlocked vault to be authorized
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn create_device_key(pin: &str, params: Option<KdfParams>) -> Result<DeviceKey, NovaultError> {
+    check_pin(pin)?;
+    let params = params_for_new_key(Some(params.unwrap_or(DEVICE_KDF_PARAMS)))?;
+    let pepper_version = current_pepper_version().ok_or(NovaultError::PepperUnavailable(LEGACY_PEPPER_VERSION))?;
+
+    let private_key = generate_private_key();
+    let salt = SaltString::generate(&mut OsRng);
+    let encryption_key = derive_key(&params, pepper_version, pin, salt.as_str())?;
+    let (nonce, encrypted_private_key) = seal_key(&encryption_key, &private_key);
+    log_info!("[create_device_key] Device key created");
+
+    Ok(DeviceKey {
+        salt: encode_salt(&params, pepper_version, salt.as_str())?,
+        nonce,
+        encrypted_private_key,
+        public_key: public_key_of(&private_key).to_vec(),
+    })
+}
+
+/// Rejects PINs too short to hold up against an offline guess
+fn check_pin(pin: &str) -> Result<(), NovaultError> {
+    let (min_len, kind) = if pin.chars().all(|c| c.is_ascii_digit()) {
+        (MIN_NUMERIC_PIN_LEN, "digits")
+    } else {
+        (MIN_PIN_LEN, "characters")
+    };
+    if pin.chars().count() < min_len {
+        log_warn!("[create_device_key] Device PIN is too short");
+        return Err(NovaultError::InvalidInput(format!("Device PIN must be at least {} {}", min_len, kind)));
+    }
+    Ok(())
+}
+
+/// Encrypts the master keys to a device as a grant container
+pub(crate) fn create_device_grant(
+    private_key: &SecretKey,
+    signing_key: Option<&SecretKey>,
+    device_public_key: &[u8],
+) -> Result<Vec<u8>, NovaultError> {
+    check_len(32, device_public_key, "Device public key")?;
+    let mut grant = Zeroizing::new(vec![0u8; GRANT_LEN]);
+    grant[0] = GRANT_VERSION;
+    grant[2..34].copy_from_slice(private_key.expose());
+    if let Some(signing_key) = signing_key {
+        grant[1] = FLAG_SIGNING_KEY;
+        grant[34..].copy_from_slice(signing_key.expose());
+    }
+    let container = encrypt_payload(&grant, device_public_key, KeyWrapScheme::HpkeAuth, Some(private_key))
+        .into_container()?;
+    log_info!("[create_device_grant] Device {} authorized", bytes_to_hex(&key_id(device_public_key)));
+    Ok(container)
+}
+
+/// Opens a device key with its PIN and the master keys from its grant
+///
+/// The device must be in `devices`, which must come from
+/// [`DeviceList::verify`]. The grant must come from the master key
+/// `public_key` and hold its private key.
+///
+/// # Returns
+/// The master private key and signing seed, if the grant has one
+pub(crate) fn open_device_grant(
+    pin: &str,
+    salt: &str,
+    encrypted_private_key: &[u8],
+    nonce: &[u8],
+    grant: &[u8],
+    public_key: &[u8],
+    devices: &DeviceList,
+) -> Result<(SecretKey, Option<SecretKey>), NovaultError> {
+    check_len(32, public_key, "Public key")?;
+    if !devices.verified {
+        return Err(NovaultError::InvalidInput("Device list has not been verified".to_string()));
+    }
+    let (header, ciphertext, sender_tags) = read_container(grant)?;
+    let (device_key, _) = open_keys_with(encrypted_private_key, nonce, None, None, || {
+        log_debug!("[open_device_grant] Deriving encryption key from PIN...");
+        get_key_encryption_key(pin, salt)
+    })?;
+    if !devices.contains(&public_key_of(&device_key)) {
+        log_warn!("[open_device_grant] Device is not in device list version {}", devices.version);
+        return Err(NovaultError::InvalidInput("Device is not in the device list".to_string()));
+    }
+
+    let (grant, sender_key_id) =
+        decrypt_with_private_key(ciphertext, &device_key, &header, sender_tags).into_data()?;
+    let grant = Zeroizing::new(grant);
+    if sender_key_id != key_id(public_key) {
+        log_warn!("[open_device_grant] Grant was not issued by this master key");
+        return Err(NovaultError::InvalidInput("Device grant was not issued by this master key".to_string()));
+    }
+    if grant.len() != GRANT_LEN || grant[0] != GRANT_VERSION {
+        return Err(NovaultError::InvalidInput("Malformed device grant".to_string()));
+    }
+
+    let private_key = SecretKey::from_slice(&grant[2..34], "Private key")?;
+    if public_key_of(&private_key)[..] != *public_key {
+        return Err(NovaultError::InvalidInput("Device grant holds a different private key".to_string()));
+    }
+    let signing_key = if grant[1] & FLAG_SIGNING_KEY != 0 {
+        Some(SecretKey::from_slice(&grant[34..], "Signing key")?)
+    } else {
+        None
+    };
+    log_debug!("[open_device_grant] Device grant opened");
+    Ok((private_key, signing_key))
+}
+
+/// Encodes and signs a device list
+pub(crate) fn sign_device_list(signing_key: &SecretKey, list: &DeviceList) -> Vec<u8> {
+    let mut data = list.to_bytes();
+    let signature = signing::sign(signing_key, DEVICE_LIST_CONTEXT, &data);
+    data.extend_from_slice(&signature);
+    log_info!("[sign_device_list] Signed device list version {}", list.version);
+    data
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl DeviceKey {
+    /// Salt and KDF parameters of the PIN KEK
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn salt(&self) -> String {
+        self.salt.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn nonce(&self) -> Vec<u8> {
+        self.nonce.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn nonce_hex(&self) -> String {
+        bytes_to_hex(&self.nonce)
+    }
+
+    /// The device private key, sealed under the PIN KEK
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn encrypted_private_key(&self) -> Vec<u8> {
+        self.encrypted_private_key.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn encrypted_private_key_hex(&self) -> String {
+        bytes_to_hex(&self.encrypted_private_key)
+    }
+
+    /// The device's X25519 public key, to be authorized by the vault
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn public_key(&self) -> Vec<u8> {
+        self.public_key.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn public_key_hex(&self) -> String {
+        bytes_to_hex(&self.public_key)
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl DeviceEntry {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn public_key(&self) -> Vec<u8> {
+        self.public_key.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn public_key_hex(&self) -> String {
+        bytes_to_hex(&self.public_key)
+    }
+
+    /// Key ID of the device public key (see [`crate::key_wrap::key_id`])
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn key_id_hex(&self) -> String {
+        bytes_to_hex(&key_id(&self.public_key))
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl DeviceList {
+    /// An empty list at version 0
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new() -> DeviceList {
+        DeviceList::default()
+    }
+
+    /// Parses a signed device list and checks its signature
+    ///
+    /// # Arguments
+    /// * `data` - The signed list, as from `sign_device_list`
+    /// * `signing_public_key` - The user's Ed25519 signing public key (32 bytes)
+    /// * `min_version` - The highest list version seen before; an older list is rejected
+    pub fn verify(data: &[u8], signing_public_key: &[u8], min_version: Option<u32>) -> Result<DeviceList, NovaultError> {
+        if data.len() < SIGNATURE_LEN {
+            return Err(NovaultError::Truncated("Device list truncated".to_string()));
+        }
+        let (body, signature) = data.split_at(data.len() - SIGNATURE_LEN);
+        if !signing::verify_with_context(signing_public_key, DEVICE_LIST_CONTEXT, body, signature)? {
+            log_warn!("[DeviceList] Device list signature is invalid");
+            return Err(NovaultError::InvalidInput("Device list signature is invalid".to_string()));
+        }
+
+        let mut list = DeviceList::parse(body)?;
+        if let Some(min_version) = min_version.filter(|&min_version| list.version < min_version) {
+            log_warn!("[DeviceList] Device list rolled back from version {} to {}", min_version, list.version);
+            return Err(NovaultError::InvalidInput(format!(
+                "Device list version {} is older than version {}",
+                list.version, min_version
+            )));
+        }
+        log_debug!("[DeviceList] Verified device list version {}", list.version);
+        list.verified = true;
+        Ok(list)
+    }
+
+    /// Adds a device and bumps the list version
+    ///
+    /// # Arguments
+    /// * `public_key` - The device's X25519 public key (32 bytes)
+    /// * `name` - A label for the device, at most 255 bytes of UTF-8
+    pub fn add(&mut self, public_key: &[u8], name: &str) -> Result<(), NovaultError> {
+        check_len(32, public_key, "Device public key")?;
+        if name.len() > u8::MAX as usize {
+            return Err(NovaultError::InvalidInput("Device name must be at most 255 bytes".to_string()));
+        }
+        if self.contains(public_key) {
+            return Err(NovaultError::InvalidInput("Device is already in the list".to_string()));
+        }
+        if self.devices.len() == u16::MAX as usize {
+            return Err(NovaultError::InvalidInput("Too many devices".to_string()));
+        }
+        self.devices.push(DeviceEntry {
+            public_key: public_key.to_vec(),
+            name: name.to_string(),
+        });
+        self.bump_version()
+    }
+
+    /// Removes a device and bumps the list version
+    ///
+    /// Sign and store the new list, then delete the device's grant.
+    pub fn revoke(&mut self, public_key: &[u8]) -> Result<(), NovaultError> {
+        let count = self.devices.len();
+        self.devices.retain(|device| device.public_key != public_key);
+        if self.devices.len() == count {
+            return Err(NovaultError::InvalidInput("Device is not in the list".to_string()));
+        }
+        log_info!("[DeviceList] Device {} revoked", bytes_to_hex(&key_id(public_key)));
+        self.bump_version()
+    }
+
+    /// Whether a device public key is authorized by this list
+    pub fn contains(&self, public_key: &[u8]) -> bool {
+        self.devices.iter().any(|device| device.public_key == public_key)
+    }
+
+    /// Goes up with every change; remember the highest verified version
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// The authorized devices, in the order they were added
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn devices(&self) -> Vec<DeviceEntry> {
+        self.devices.clone()
+    }
+}
+
+impl DeviceList {
+    fn bump_version(&mut self) -> Result<(), NovaultError> {
+        self.verified = false;
+        self.version = self
+            .version
+            .checked_add(1)
+            .ok_or_else(|| NovaultError::InvalidState("Device list version overflow".to_string()))?;
+        Ok(())
+    }
+
+    /// Encodes the list without its signature
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&DEVICE_LIST_MAGIC);
+        out.push(DEVICE_LIST_VERSION);
+        out.extend_from_slice(&self.version.to_be_bytes());
+        out.extend_from_slice(&(self.devices.len() as u16).to_be_bytes());
+        for device in &self.devices {
+            out.extend_from_slice(&device.public_key);
+            out.push(device.name.len() as u8);
+            out.extend_from_slice(device.name.as_bytes());
+        }
+        out
+    }
+
+    fn parse(body: &[u8]) -> Result<DeviceList, NovaultError> {
+        let mut reader = ByteReader::new(body);
+        if reader.take(4, "Magic")? != DEVICE_LIST_MAGIC {
+            return Err(NovaultError::InvalidInput("Not a device list".to_string()));
+        }
+        let format_version = reader.u8("Format version")?;
+        if format_version != DEVICE_LIST_VERSION {
+            return Err(NovaultError::InvalidInput(format!(
+                "Unsupported device list version: {}",
+                format_version
+            )));
+        }
+        let version = reader.u32("List version")?;
+        let count = reader.u16("Device count")?;
+        let mut list = DeviceList { version, devices: Vec::with_capacity(count as usize), verified: false };
+        for _ in 0..count {
+            let public_key = reader.take(32, "Device public key")?.to_vec();
+            let name_len = reader.u8("Device name length")?;
+            let name = std::str::from_utf8(reader.take(name_len as usize, "Device name")?)
+                .map_err(|_| NovaultError::InvalidInput("Device name is not UTF-8".to_string()))?
+                .to_string();
+            list.devices.push(DeviceEntry { public_key, name });
+        }
+        if !reader.is_empty() {
+            return Err(NovaultError::InvalidInput("Trailing bytes after device list".to_string()));
+        }
+        Ok(list)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pepper::register_pepper;
+    use crate::signing::{generate_signing_key, signing_public_key_of};
+
+    #[test]
+    fn short_pins_are_rejected() {
+        for pin in ["", "1234", "abc1234", "12345678901", "ab cd"] {
+            assert_eq!(check_pin(pin).unwrap_err().code().as_str(), "INVALID_INPUT", "{:?}", pin);
+        }
+        for pin in ["abcd1234", "123456789012", "pâté-pâté"] {
+            assert!(check_pin(pin).is_ok(), "{:?}", pin);
+        }
+    }
+
+    #[test]
+    fn grants_open_only_for_devices_in_the_verified_list() {
+        register_pepper(0, b"device-test-pepper").unwrap();
+        let (private_key, signing_key) = (generate_private_key(), generate_signing_key());
+        let (public_key, signing_public_key) = (public_key_of(&private_key), signing_public_key_of(&signing_key));
+        let device = create_device_key("correct horse", None).unwrap();
+        let grant = create_device_grant(&private_key, Some(&signing_key), &device.public_key).unwrap();
+        let open = |devices: &DeviceList| {
+            let DeviceKey { salt, nonce, encrypted_private_key, .. } = &device;
+            open_device_grant("correct horse", salt, encrypted_private_key, nonce, &grant, &public_key, devices)
+        };
+
+        let mut list = DeviceList::new();
+        list.add(&device.public_key, "laptop").unwrap();
+        assert_eq!(open(&list).unwrap_err().code().as_str(), "INVALID_INPUT");
+        let signed = sign_device_list(&signing_key, &list);
+        let verified = DeviceList::verify(&signed, &signing_public_key, None).unwrap();
+        let (opened, _) = open(&verified).unwrap();
+        assert_eq!(opened.expose(), private_key.expose());
+
+        let mut edited = verified.clone();
+        edited.add(&[7; 32], "phone").unwrap();
+        assert_eq!(open(&edited).unwrap_err().code().as_str(), "INVALID_INPUT");
+
+        let mut revoked = verified;
+        revoked.revoke(&device.public_key).unwrap();
+        let signed = sign_device_list(&signing_key, &revoked);
+        let revoked = DeviceList::verify(&signed, &signing_public_key, Some(1)).unwrap();
+        assert_eq!(open(&revoked).unwrap_err().code().as_str(), "INVALID_INPUT");
+    }
+}
@@ -1,28 +1,40 @@
-/// The encryption process:
-/// 1. Generate a random DEK (Data Encryption Key)
-/// 2. Encrypt the file using the DEK with AES-256-GCM
-/// 3. Generate an ephemeral X25519 key pair
-/// 4. Perform ECDH with recipient's public key to derive a shared secret
-/// 5. Encrypt the DEK using the shared secret with AES-256-GCM
+//! The encryption process:
+//! 1. Generate a random DEK (Data Encryption Key)
+//! 2. Encrypt the file using the DEK with AES-256-GCM
+//! 3. Generate an ephemeral X25519 key pair
+//! 4. Perform ECDH with recipient's public key to derive a shared secret
+//! 5. Encrypt the DEK using the shared secret with AES-256-GCM
+//!
+//! With several recipients, steps 3-5 are repeated per recipient while the
+//! payload is still encrypted only once.
+//!
+//! These functions do not identify the sender. To encrypt as the vault owner,
+//! so recipients can verify who sent the file, use
+//! [`crate::vault::UnlockedVault::encrypt_file`].
 
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 use aes_gcm::{
-    Aes256Gcm, Nonce, aead::{Aead, KeyInit, OsRng, generic_array::GenericArray}
+    Aes256Gcm, Nonce, aead::{Aead, KeyInit, generic_array::GenericArray}
 };
-use x25519_dalek::{PublicKey, StaticSecret};
 
-pub use crate::{generate_nonce, bytes_to_hex, hash_file, log};
+pub use crate::{generate_nonce, bytes_to_hex, hash_file};
+use crate::container::{self, ContainerHeader, ContentCommitment, ALG_X25519_AES256GCM, CONTAINER_VERSION};
+use crate::error::NovaultError;
+use crate::file_digest::file_digest_hex;
+use crate::key_wrap::{check_len, generate_dek, seal_sender_tags, wrap_dek_for_recipients, KeyWrapScheme, WrappedDek};
+use crate::secret::SecretKey;
+use crate::logger::{log_debug, log_info, log_warn, Redacted};
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub struct EncryptedFileResult {
     success: bool,
     encrypted_data: Vec<u8>,
-    file_nonce_hex: String,
-    encrypted_dek: Vec<u8>,
-    dek_nonce_hex: String,
-    ephemeral_public_key: Vec<u8>,
+    file_nonce: Vec<u8>,
+    recipients: Vec<WrappedDek>,
+    sender_tags: Vec<u8>,
     original_hash_hex: String,
-    error_message: String,
+    error: Option<NovaultError>,
 }
 
 /// Encrypts file data using hybrid encryption (X25519 + AES-256-GCM)
@@ -30,6 +42,10 @@ pub struct EncryptedFileResult {
 /// # Arguments
 /// * `file_data` - The raw file bytes to encrypt
 /// * `recipient_public_key` - The recipient's X25519 public key (32 bytes)
+/// * `wrap_scheme` - Optional [`KeyWrapScheme`] ID for the DEK wrap; defaults to
+///   2 (ECDH with HKDF). Raw ECDH (1) is only accepted for decryption
+/// * `digest_key` - The uploader's file digest key from
+///   `UnlockedVault.file_digest_key` (32 bytes); it keys `original_hash_hex`
 /// 
 /// # Returns
 /// EncryptedFileResult containing:
@@ -38,174 +54,324 @@ pub struct EncryptedFileResult {
 /// - encrypted_dek: The encrypted Data Encryption Key
 /// - dek_nonce_hex: Nonce used for DEK encryption
 /// - ephemeral_public_key: The ephemeral public key for ECDH
-/// - original_hash_hex: SHA-256 hash of the original file
-#[wasm_bindgen]
+/// - wrap_scheme: The key wrap scheme ID, to store with the DEK fields
+/// - original_hash_hex: Keyed digest of the original file (see [`crate::file_digest`])
+/// - container: All of the above packed into one blob (see [`crate::container`])
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub fn encrypt_file(
     file_data: &[u8], 
     recipient_public_key: &[u8],
+    wrap_scheme: Option<u8>,
+    digest_key: &[u8],
 ) -> EncryptedFileResult {
-    log("[encrypt_file] Starting file encryption...");
-    log(&format!("[encrypt_file] File size: {} bytes", file_data.len()));
+    log_debug!("[encrypt_file] Starting file encryption...");
+    log_debug!("[encrypt_file] File size: {} bytes", file_data.len());
 
     // Validate recipient's public key length
-    if recipient_public_key.len() != 32 {
-        log(&format!("[encrypt_file] Invalid public key length: {}", recipient_public_key.len()));
-        return EncryptedFileResult {
-            success: false,
-            encrypted_data: vec![],
-            file_nonce_hex: String::new(),
-            encrypted_dek: vec![],
-            dek_nonce_hex: String::new(),
-            ephemeral_public_key: vec![],
-            original_hash_hex: String::new(),
-            error_message: format!("Public key must be 32 bytes, got {}", recipient_public_key.len()),
-        };
+    if let Err(e) = check_len(32, recipient_public_key, "Public key") {
+        log_warn!("[encrypt_file] Invalid public key length: {}", recipient_public_key.len());
+        return EncryptedFileResult::failed(e);
     }
 
-    let recipient_public_key_array: [u8; 32] = recipient_public_key.try_into().unwrap();
-    let recipient_public = PublicKey::from(recipient_public_key_array);
+    encrypt_with_digest(file_data, recipient_public_key, wrap_scheme, digest_key)
+}
 
-    // Generate a random DEK
-    log("[encrypt_file] Generating random DEK...");
-    let dek_secret = StaticSecret::random_from_rng(OsRng);
-    let dek: [u8; 32] = dek_secret.to_bytes();
-    log(&format!("[encrypt_file] DEK generated: {}", bytes_to_hex(&dek)));
+/// Encrypts file data once for several recipients
+///
+/// The payload is encrypted under a single DEK, which is then wrapped
+/// separately for every recipient. Any one of them can decrypt the
+/// resulting container with their own key.
+///
+/// # Arguments
+/// * `file_data` - The raw file bytes to encrypt
+/// * `recipient_public_keys` - The recipients' X25519 public keys, concatenated (32 bytes each)
+/// * `wrap_scheme` - Optional [`KeyWrapScheme`] ID, as for [`encrypt_file`]
+/// * `digest_key` - The uploader's file digest key, as for [`encrypt_file`]
+///
+/// # Returns
+/// EncryptedFileResult with one entry per recipient in `recipients`. The
+/// single-recipient getters (`encrypted_dek`, `dek_nonce_hex`, ...) describe
+/// the first recipient.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn encrypt_file_for_recipients(
+    file_data: &[u8],
+    recipient_public_keys: &[u8],
+    wrap_scheme: Option<u8>,
+    digest_key: &[u8],
+) -> EncryptedFileResult {
+    log_debug!("[encrypt_file_for_recipients] Starting file encryption...");
+    log_debug!("[encrypt_file_for_recipients] File size: {} bytes", file_data.len());
+    encrypt_with_digest(file_data, recipient_public_keys, wrap_scheme, digest_key)
+}
 
-    log("[encrypt_file] Computing original file hash...");
-    let original_hash = hash_file(file_data);
+/// Anonymous encryption with the digest key passed in from JavaScript
+fn encrypt_with_digest(
+    file_data: &[u8],
+    recipient_public_keys: &[u8],
+    wrap_scheme: Option<u8>,
+    digest_key: &[u8],
+) -> EncryptedFileResult {
+    let scheme = match KeyWrapScheme::from_option(wrap_scheme, KeyWrapScheme::EcdhHkdf) {
+        Ok(scheme) => scheme,
+        Err(e) => return EncryptedFileResult::failed(e),
+    };
+    let digest_key = match SecretKey::from_slice(digest_key, "File digest key") {
+        Ok(key) => key,
+        Err(e) => return EncryptedFileResult::failed(e),
+    };
+    encrypt_payload(file_data, recipient_public_keys, scheme, None).with_digest(file_data, &digest_key)
+}
+
+/// Encrypts the payload once and wraps the DEK for every recipient
+///
+/// `sender` is the sender's private key for [`KeyWrapScheme::HpkeAuth`]; it
+/// also seals a sender tag over the ciphertext to every recipient.
+pub(crate) fn encrypt_payload(
+    file_data: &[u8],
+    recipient_public_keys: &[u8],
+    scheme: KeyWrapScheme,
+    sender: Option<&SecretKey>,
+) -> EncryptedFileResult {
+    // Generate a random DEK
+    log_debug!("[encrypt_file] Generating random DEK...");
+    let dek = generate_dek();
+    log_debug!("[encrypt_file] DEK generated: {}", Redacted(dek.expose()));
 
     // Encrypt the file using the DEK
-    log("[encrypt_file] Encrypting file with DEK...");
+    log_debug!("[encrypt_file] Encrypting file with DEK...");
     let file_nonce = generate_nonce();
     let file_nonce_hex = bytes_to_hex(file_nonce.as_slice());
-    log(&format!("[encrypt_file] File nonce: {}", file_nonce_hex));
+    log_debug!("[encrypt_file] File nonce: {}", file_nonce_hex);
 
-    let dek_key = GenericArray::from_slice(&dek);
+    let dek_key = GenericArray::from_slice(dek.expose());
     let file_cipher = Aes256Gcm::new(dek_key);
     let file_nonce_ga = Nonce::from_slice(file_nonce.as_slice());
 
     let encrypted_file_data = match file_cipher.encrypt(file_nonce_ga, file_data) {
         Ok(encrypted) => {
-            log(&format!("[encrypt_file] File encrypted! Size: {} bytes", encrypted.len()));
+            log_debug!("[encrypt_file] File encrypted! Size: {} bytes", encrypted.len());
             encrypted
         }
         Err(e) => {
-            log(&format!("[encrypt_file] File encryption failed: {}", e));
-            return EncryptedFileResult {
-                success: false,
-                encrypted_data: vec![],
-                file_nonce_hex: String::new(),
-                encrypted_dek: vec![],
-                dek_nonce_hex: String::new(),
-                ephemeral_public_key: vec![],
-                original_hash_hex: String::new(),
-                error_message: format!("File encryption failed: {}", e),
-            };
+            log_warn!("[encrypt_file] File encryption failed: {}", e);
+            return EncryptedFileResult::failed(NovaultError::EncryptionFailed(format!("File encryption failed: {}", e)));
         }
     };
 
-    // Generate ephemeral key pair and perform ECDH
-    log("[encrypt_file] Generating ephemeral key pair for ECDH...");
-    let ephemeral_secret = StaticSecret::random_from_rng(OsRng);
-    let ephemeral_public = PublicKey::from(&ephemeral_secret);
-    log(&format!("[encrypt_file] Ephemeral public key: {}", bytes_to_hex(ephemeral_public.as_bytes())));
-
-    // Derive shared secret using ECDH
-    let shared_secret = ephemeral_secret.diffie_hellman(&recipient_public);
-    log("[encrypt_file] Shared secret derived via ECDH");
-
-    // Encrypt the DEK using the shared secret
-    log("[encrypt_file] Encrypting DEK with shared secret...");
-    let dek_nonce = generate_nonce();
-    let dek_nonce_hex = bytes_to_hex(dek_nonce.as_slice());
-    log(&format!("[encrypt_file] DEK nonce: {}", dek_nonce_hex));
-
-    let shared_key = GenericArray::from_slice(shared_secret.as_bytes());
-    let dek_cipher = Aes256Gcm::new(shared_key);
-    let dek_nonce_ga = Nonce::from_slice(dek_nonce.as_slice());
-
-    let encrypted_dek = match dek_cipher.encrypt(dek_nonce_ga, dek.as_ref()) {
-        Ok(encrypted) => {
-            log(&format!("[encrypt_file] DEK encrypted! Size: {} bytes", encrypted.len()));
-            encrypted
+    // Wrap the DEK for each recipient via ephemeral ECDH
+    log_debug!("[encrypt_file] Encrypting DEK with shared secret...");
+    let mut header = ContainerHeader {
+        version: CONTAINER_VERSION,
+        algorithm: ALG_X25519_AES256GCM,
+        file_nonce: file_nonce.to_vec(),
+        chunk_size: None,
+        recipients: Vec::new(),
+        sender_tag_count: 0,
+    };
+    let sealed = wrap_dek_for_recipients(&dek, recipient_public_keys, scheme, sender, &header.context()).and_then(|recipients| {
+        header.recipients = recipients;
+        match sender {
+            Some(sender) => {
+                header.sender_tag_count = header.recipients.len() as u16;
+                seal_sender_tags(recipient_public_keys, sender, &ContentCommitment::of(&header, &encrypted_file_data))
+            }
+            None => Ok(Vec::new()),
         }
+    });
+    let sender_tags = match sealed {
+        Ok(sender_tags) => sender_tags,
         Err(e) => {
-            log(&format!("[encrypt_file] DEK encryption failed: {}", e));
-            return EncryptedFileResult {
-                success: false,
-                encrypted_data: vec![],
-                file_nonce_hex: String::new(),
-                encrypted_dek: vec![],
-                dek_nonce_hex: String::new(),
-                ephemeral_public_key: vec![],
-                original_hash_hex: String::new(),
-                error_message: format!("DEK encryption failed: {}", e),
-            };
+            log_warn!("[encrypt_file] {}", e);
+            return EncryptedFileResult::failed(e);
         }
     };
 
-    log("[encrypt_file] Encryption complete!");
+    log_info!("[encrypt_file] Encryption complete!");
     EncryptedFileResult {
         success: true,
         encrypted_data: encrypted_file_data,
-        file_nonce_hex,
-        encrypted_dek,
-        dek_nonce_hex,
-        ephemeral_public_key: ephemeral_public.as_bytes().to_vec(),
-        original_hash_hex: original_hash,
-        error_message: String::new(),
+        file_nonce: file_nonce.to_vec(),
+        recipients: header.recipients,
+        sender_tags,
+        original_hash_hex: String::new(),
+        error: None,
     }
 }
 
-#[wasm_bindgen]
 impl EncryptedFileResult {
-    #[wasm_bindgen(getter)]
+    pub(crate) fn failed(error: NovaultError) -> Self {
+        EncryptedFileResult {
+            success: false,
+            encrypted_data: vec![],
+            file_nonce: vec![],
+            recipients: vec![],
+            sender_tags: vec![],
+            original_hash_hex: String::new(),
+            error: Some(error),
+        }
+    }
+
+    /// Sets `original_hash_hex` to the keyed digest of `file_data`
+    pub(crate) fn with_digest(mut self, file_data: &[u8], digest_key: &SecretKey) -> Self {
+        if self.success {
+            self.original_hash_hex = file_digest_hex(digest_key, file_data);
+        }
+        self
+    }
+
+    /// The container, or the error encryption failed with
+    pub(crate) fn into_container(self) -> Result<Vec<u8>, NovaultError> {
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok(self.container()),
+        }
+    }
+
+    /// The first recipient's wrapped DEK, used by the single-recipient getters
+    fn first_recipient(&self) -> WrappedDek {
+        self.recipients.first().cloned().unwrap_or_default()
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl EncryptedFileResult {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
     pub fn success(&self) -> bool {
         self.success
     }
 
-    #[wasm_bindgen(getter)]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
     pub fn encrypted_data(&self) -> Vec<u8> {
         self.encrypted_data.clone()
     }
 
-    #[wasm_bindgen(getter)]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
     pub fn file_nonce_hex(&self) -> String {
-        self.file_nonce_hex.clone()
+        bytes_to_hex(&self.file_nonce)
     }
 
-    #[wasm_bindgen(getter)]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
     pub fn encrypted_dek(&self) -> Vec<u8> {
-        self.encrypted_dek.clone()
+        self.first_recipient().encrypted_dek
     }
 
-    #[wasm_bindgen(getter)]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
     pub fn encrypted_dek_hex(&self) -> String {
-        bytes_to_hex(&self.encrypted_dek)
+        self.first_recipient().encrypted_dek_hex()
     }
 
-    #[wasm_bindgen(getter)]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
     pub fn dek_nonce_hex(&self) -> String {
-        self.dek_nonce_hex.clone()
+        self.first_recipient().dek_nonce_hex()
     }
 
-    #[wasm_bindgen(getter)]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
     pub fn ephemeral_public_key(&self) -> Vec<u8> {
-        self.ephemeral_public_key.clone()
+        self.first_recipient().ephemeral_public_key
     }
 
-    #[wasm_bindgen(getter)]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
     pub fn ephemeral_public_key_hex(&self) -> String {
-        bytes_to_hex(&self.ephemeral_public_key)
+        self.first_recipient().ephemeral_public_key_hex()
+    }
+
+    /// ID of the key wrap scheme used for the DEK; store it with the DEK fields
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn wrap_scheme(&self) -> u8 {
+        self.first_recipient().wrap_scheme()
+    }
+
+    /// Key ID of the recipient's public key; store it with the DEK fields
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn key_id(&self) -> Vec<u8> {
+        self.first_recipient().key_id
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn key_id_hex(&self) -> String {
+        self.first_recipient().key_id_hex()
+    }
+
+    /// One wrapped DEK per recipient, in the order the keys were given
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn recipients(&self) -> Vec<WrappedDek> {
+        self.recipients.clone()
     }
 
-    #[wasm_bindgen(getter)]
+    /// The encrypted file packed into a single self-describing container
+    ///
+    /// Returns an empty buffer if encryption failed.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn container(&self) -> Vec<u8> {
+        if !self.success {
+            return vec![];
+        }
+        let header = ContainerHeader {
+            version: CONTAINER_VERSION,
+            algorithm: ALG_X25519_AES256GCM,
+            file_nonce: self.file_nonce.clone(),
+            chunk_size: None,
+            recipients: self.recipients.clone(),
+            sender_tag_count: (self.sender_tags.len() / container::SENDER_TAG_LEN) as u16,
+        };
+        container::build_container(&header, &self.encrypted_data, &self.sender_tags)
+    }
+
+    /// Keyed digest of the plaintext (see [`crate::file_digest`]); store it
+    /// as `file_hash`. Empty if encryption failed
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
     pub fn original_hash_hex(&self) -> String {
         self.original_hash_hex.clone()
     }
 
-    #[wasm_bindgen(getter)]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
     pub fn error_message(&self) -> String {
-        self.error_message.clone()
+        self.error.as_ref().map(ToString::to_string).unwrap_or_default()
+    }
+
+    /// Stable code of the failure (see [`crate::error::ErrorCode`]), or an empty string on success
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn error_code(&self) -> String {
+        self.error.as_ref().map(|e| e.code().as_str().to_string()).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_digest::file_digest_key;
+    use crate::key_wrap::public_key_of;
+
+    #[test]
+    fn encryption_requires_a_digest_key() {
+        let private_key = generate_dek();
+        let public_key = public_key_of(&private_key);
+        let digest_key = file_digest_key(&private_key);
+
+        let result = encrypt_file(b"minutes", &public_key, None, digest_key.expose());
+        assert!(result.success());
+        assert_eq!(result.original_hash_hex(), file_digest_hex(&digest_key, b"minutes"));
+
+        for digest_key in [&[][..], &[7; 31]] {
+            let result = encrypt_file(b"minutes", &public_key, None, digest_key);
+            assert_eq!(result.error_code(), "INVALID_LENGTH");
+            assert!(result.original_hash_hex().is_empty());
+            let result = encrypt_file_for_recipients(b"minutes", &public_key, None, digest_key);
+            assert_eq!(result.error_code(), "INVALID_LENGTH");
+        }
+    }
+
+    #[test]
+    fn new_files_never_use_raw_ecdh() {
+        let private_key = generate_dek();
+        let public_key = public_key_of(&private_key);
+        let digest_key = file_digest_key(&private_key);
+
+        let result = encrypt_file(b"minutes", &public_key, None, digest_key.expose());
+        assert_eq!(result.wrap_scheme(), KeyWrapScheme::EcdhHkdf.id());
+        let result = encrypt_file_for_recipients(b"minutes", &public_key, None, digest_key.expose());
+        assert_eq!(result.wrap_scheme(), KeyWrapScheme::EcdhHkdf.id());
+        let result = encrypt_file(b"minutes", &public_key, Some(KeyWrapScheme::RawEcdh.id()), digest_key.expose());
+        assert_eq!(result.error_code(), "INVALID_INPUT");
     }
 }
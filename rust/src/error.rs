@@ -0,0 +1,207 @@
+//! Typed errors with stable codes.
+//!
+//! Every fallible operation in the crate returns [`NovaultError`]. Callers
+//! branch on [`NovaultError::code`] rather than on the message text, which is
+//! meant for humans and may change.
+//!
+//! In JavaScript, functions that return `Result` throw a `NovaultError`, an
+//! `Error` subclass carrying the same code:
+//!
+//! ```javascript
+//! try {
+//!   vault = new wasm.UnlockedVault(password, salt, encryptedPrivateKey, pkNonce);
+//! } catch (e) {
+//!   if (e.code === "WRONG_PASSWORD") showPasswordError();
+//!   else throw e;
+//! }
+//! ```
+//!
+//! The older result structs (`DecryptedFileResult` and friends) still report
+//! `success` and `error_message`, and additionally expose `error_code`.
+
+use std::fmt;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+/// Stable error codes; the numeric values never change once released
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    InvalidLength = 1,
+    InvalidInput = 2,
+    WrongPassword = 3,
+    DekUnwrapFailed = 4,
+    PayloadAuthFailed = 5,
+    Truncated = 6,
+    InvalidContainer = 7,
+    UnsupportedVersion = 8,
+    UnsupportedAlgorithm = 9,
+    VaultLocked = 10,
+    EncryptionFailed = 11,
+    InvalidState = 12,
+    NotRecipient = 13,
+    PepperUnavailable = 14,
+    DigestMismatch = 15,
+    SenderAuthFailed = 16,
+}
+
+impl ErrorCode {
+    /// The code as a string, e.g. `"WRONG_PASSWORD"`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::InvalidLength => "INVALID_LENGTH",
+            ErrorCode::InvalidInput => "INVALID_INPUT",
+            ErrorCode::WrongPassword => "WRONG_PASSWORD",
+            ErrorCode::DekUnwrapFailed => "DEK_UNWRAP_FAILED",
+            ErrorCode::PayloadAuthFailed => "PAYLOAD_AUTH_FAILED",
+            ErrorCode::Truncated => "TRUNCATED",
+            ErrorCode::InvalidContainer => "INVALID_CONTAINER",
+            ErrorCode::UnsupportedVersion => "UNSUPPORTED_VERSION",
+            ErrorCode::UnsupportedAlgorithm => "UNSUPPORTED_ALGORITHM",
+            ErrorCode::VaultLocked => "VAULT_LOCKED",
+            ErrorCode::EncryptionFailed => "ENCRYPTION_FAILED",
+            ErrorCode::InvalidState => "INVALID_STATE",
+            ErrorCode::NotRecipient => "NOT_RECIPIENT",
+            ErrorCode::PepperUnavailable => "PEPPER_UNAVAILABLE",
+            ErrorCode::DigestMismatch => "DIGEST_MISMATCH",
+            ErrorCode::SenderAuthFailed => "SENDER_AUTH_FAILED",
+        }
+    }
+}
+
+/// Every way a NoVault operation can fail
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NovaultError {
+    /// A key, nonce or other fixed-size input has the wrong length
+    InvalidLength {
+        name: &'static str,
+        expected: usize,
+        actual: usize,
+    },
+    /// An argument is malformed (bad hex, zero chunk size, empty range, ...)
+    InvalidInput(String),
+    /// The private key could not be decrypted with the given password
+    WrongPassword,
+    /// The DEK did not decrypt with this private key
+    DekUnwrapFailed,
+    /// The file data failed authentication; `chunk` is set for chunked containers
+    PayloadAuthFailed { chunk: Option<u32> },
+    /// Input ended early
+    Truncated(String),
+    /// The bytes are not a well-formed container
+    InvalidContainer(String),
+    /// The container format version is not supported by this build
+    UnsupportedVersion(u8),
+    /// The container algorithm ID is not supported by this build
+    UnsupportedAlgorithm(u8),
+    /// The vault was locked before the call
+    VaultLocked,
+    /// An AEAD seal operation failed
+    EncryptionFailed(String),
+    /// The call is not valid in the object's current state
+    InvalidState(String),
+    /// The file has no wrapped DEK for this key
+    NotRecipient,
+    /// The pepper version the key was derived with has not been registered
+    PepperUnavailable(u32),
+    /// A stored file digest does not match the decrypted file
+    DigestMismatch,
+    /// The sender of a sender-authenticated file did not seal its content
+    SenderAuthFailed,
+}
+
+impl NovaultError {
+    /// The stable code for this error
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            NovaultError::InvalidLength { .. } => ErrorCode::InvalidLength,
+            NovaultError::InvalidInput(_) => ErrorCode::InvalidInput,
+            NovaultError::WrongPassword => ErrorCode::WrongPassword,
+            NovaultError::DekUnwrapFailed => ErrorCode::DekUnwrapFailed,
+            NovaultError::PayloadAuthFailed { .. } => ErrorCode::PayloadAuthFailed,
+            NovaultError::Truncated(_) => ErrorCode::Truncated,
+            NovaultError::InvalidContainer(_) => ErrorCode::InvalidContainer,
+            NovaultError::UnsupportedVersion(_) => ErrorCode::UnsupportedVersion,
+            NovaultError::UnsupportedAlgorithm(_) => ErrorCode::UnsupportedAlgorithm,
+            NovaultError::VaultLocked => ErrorCode::VaultLocked,
+            NovaultError::EncryptionFailed(_) => ErrorCode::EncryptionFailed,
+            NovaultError::InvalidState(_) => ErrorCode::InvalidState,
+            NovaultError::NotRecipient => ErrorCode::NotRecipient,
+            NovaultError::PepperUnavailable(_) => ErrorCode::PepperUnavailable,
+            NovaultError::DigestMismatch => ErrorCode::DigestMismatch,
+            NovaultError::SenderAuthFailed => ErrorCode::SenderAuthFailed,
+        }
+    }
+}
+
+impl fmt::Display for NovaultError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NovaultError::InvalidLength { name, expected, actual } => {
+                write!(f, "{} must be {} bytes, got {}", name, expected, actual)
+            }
+            NovaultError::WrongPassword => f.write_str("Decryption failed. Please check your password."),
+            NovaultError::DekUnwrapFailed => {
+                f.write_str("DEK decryption failed. Invalid private key or corrupted data.")
+            }
+            NovaultError::PayloadAuthFailed { chunk: None } => {
+                f.write_str("File decryption failed. Invalid DEK or corrupted data.")
+            }
+            NovaultError::PayloadAuthFailed { chunk: Some(index) } => write!(
+                f,
+                "Chunk {} failed authentication. The stream is corrupted, truncated or reordered.",
+                index
+            ),
+            NovaultError::UnsupportedVersion(version) => write!(f, "Unsupported container version: {}", version),
+            NovaultError::UnsupportedAlgorithm(algorithm) => write!(f, "Unsupported algorithm ID: {}", algorithm),
+            NovaultError::VaultLocked => f.write_str("Vault is locked"),
+            NovaultError::NotRecipient => f.write_str("File is not encrypted to this key"),
+            NovaultError::PepperUnavailable(version) => write!(f, "Pepper version {} is not registered", version),
+            NovaultError::DigestMismatch => f.write_str("Stored file digest does not match the file"),
+            NovaultError::SenderAuthFailed => {
+                f.write_str("File content is not sealed by its sender. It may have been altered by another recipient.")
+            }
+            NovaultError::InvalidContainer(message) => write!(f, "Invalid container: {}", message),
+            NovaultError::InvalidInput(message)
+            | NovaultError::Truncated(message)
+            | NovaultError::EncryptionFailed(message)
+            | NovaultError::InvalidState(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for NovaultError {}
+
+impl From<NovaultError> for String {
+    fn from(error: NovaultError) -> Self {
+        error.to_string()
+    }
+}
+
+#[cfg(feature = "wasm")]
+#[wasm_bindgen(inline_js = r#"
+export class NovaultError extends Error {
+    constructor(message, code, codeNumber) {
+        super(message);
+        this.name = "NovaultError";
+        this.code = code;
+        this.codeNumber = codeNumber;
+    }
+}
+
+export function new_novault_error(message, code, codeNumber) {
+    return new NovaultError(message, code, codeNumber);
+}
+"#)]
+extern "C" {
+    fn new_novault_error(message: &str, code: &str, code_number: u32) -> JsValue;
+}
+
+/// Thrown into JavaScript as a `NovaultError` with `code` and `codeNumber` set
+#[cfg(feature = "wasm")]
+impl From<NovaultError> for JsValue {
+    fn from(error: NovaultError) -> Self {
+        let code = error.code();
+        new_novault_error(&error.to_string(), code.as_str(), code as u32)
+    }
+}
@@ -0,0 +1,178 @@
+//! Keyed integrity digests of file plaintexts.
+//!
+//! The web app stores a digest of every file's plaintext next to its
+//! ciphertext. A plain SHA-256 would let the server confirm whether a user
+//! holds a known file, so the digest is HMAC-SHA256 under a per-user key
+//! instead. The key is derived from the user's X25519 private key with
+//! HKDF-SHA256 and never leaves the client; without it, the digest reveals
+//! nothing about the plaintext. It has the same length as a SHA-256, so it
+//! fits the existing `file_hash` column.
+//!
+//! Decryption computes the digest automatically. Encrypting with only a
+//! public key needs the key from `UnlockedVault.file_digest_key`.
+//!
+//! Digests stored before this change, and digests from before a key
+//! rotation, are recomputed with `UnlockedVault.migrate_file_digest`:
+//!
+//! ```javascript
+//! const result = vault.decrypt_file(ciphertext, epk, encryptedDek, dekNonce, fileNonce, scheme, keyId);
+//! const digest = vault.migrate_file_digest(result.decrypted_data, row.file_hash);
+//! if (digest) await saveFileHash(row.file_id, digest);
+//! ```
+
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use crate::{bytes_to_hex, hex_to_bytes};
+use crate::error::NovaultError;
+use crate::secret::SecretKey;
+use crate::logger::{log_debug, log_info, log_warn};
+
+/// Length of a file digest key in bytes
+pub const FILE_DIGEST_KEY_LEN: usize = 32;
+
+/// HKDF info label for the file digest key
+const FILE_DIGEST_KEY_INFO: &[u8] = b"novault/file-digest/v1";
+
+/// Incremental HMAC-SHA256 over a file's plaintext
+pub(crate) struct FileDigest {
+    mac: Hmac<Sha256>,
+}
+
+/// Derives the user's file digest key from their private key
+pub(crate) fn file_digest_key(private_key: &SecretKey) -> SecretKey {
+    let mut key = SecretKey::new([0u8; FILE_DIGEST_KEY_LEN]);
+    Hkdf::<Sha256>::new(None, private_key.expose())
+        .expand(FILE_DIGEST_KEY_INFO, key.expose_mut())
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    key
+}
+
+/// Keyed digest of a whole plaintext, as hex
+pub(crate) fn file_digest_hex(digest_key: &SecretKey, data: &[u8]) -> String {
+    let mut digest = FileDigest::new(digest_key);
+    digest.update(data);
+    digest.finalize_hex()
+}
+
+/// Recomputes a stored digest under the current key
+///
+/// The stored digest must be the current key's digest, a digest under one of
+/// the `previous` keys, or a legacy unkeyed SHA-256 of `plaintext`. An empty
+/// stored digest is replaced without a check.
+///
+/// # Returns
+/// The new digest, or `None` if the stored one is already current
+pub(crate) fn migrate_digest(
+    current: &SecretKey,
+    previous: &[SecretKey],
+    plaintext: &[u8],
+    stored_digest_hex: &str,
+) -> Result<Option<String>, NovaultError> {
+    let current_key = file_digest_key(current);
+    let stored = hex_to_bytes(stored_digest_hex.trim())?;
+    if stored.is_empty() {
+        log_debug!("[migrate_digest] No stored digest, computing one");
+        return Ok(Some(file_digest_hex(&current_key, plaintext)));
+    }
+    if FileDigest::new(&current_key).verify(plaintext, &stored) {
+        return Ok(None);
+    }
+
+    let legacy = Sha256::digest(plaintext).as_slice() == stored.as_slice();
+    if !legacy && !previous.iter().any(|key| FileDigest::new(&file_digest_key(key)).verify(plaintext, &stored)) {
+        log_warn!("[migrate_digest] Stored digest does not match the file");
+        return Err(NovaultError::DigestMismatch);
+    }
+    log_info!(
+        "[migrate_digest] Replacing {} digest",
+        if legacy { "an unkeyed" } else { "a previous key's" }
+    );
+    Ok(Some(file_digest_hex(&current_key, plaintext)))
+}
+
+impl FileDigest {
+    pub(crate) fn new(digest_key: &SecretKey) -> FileDigest {
+        FileDigest {
+            mac: Hmac::<Sha256>::new_from_slice(digest_key.expose()).expect("HMAC accepts any key length"),
+        }
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        self.mac.update(data);
+    }
+
+    pub(crate) fn finalize_hex(self) -> String {
+        bytes_to_hex(&self.mac.finalize().into_bytes())
+    }
+
+    /// Checks `data` against an expected digest in constant time
+    fn verify(mut self, data: &[u8], expected: &[u8]) -> bool {
+        self.update(data);
+        self.mac.verify_slice(expected).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash_file;
+    use crate::masterkey_generator::generate_private_key;
+
+    const PLAINTEXT: &[u8] = b"file contents";
+
+    #[test]
+    fn digest_keys_differ_per_private_key() {
+        let (one, two) = (generate_private_key(), generate_private_key());
+        assert_eq!(file_digest_key(&one).expose(), file_digest_key(&one).expose());
+        assert_ne!(file_digest_key(&one).expose(), file_digest_key(&two).expose());
+        assert_ne!(file_digest_key(&one).expose(), one.expose());
+
+        let digest = file_digest_hex(&file_digest_key(&one), PLAINTEXT);
+        assert_ne!(digest, file_digest_hex(&file_digest_key(&two), PLAINTEXT));
+        assert_ne!(digest, hash_file(PLAINTEXT));
+
+        let mut incremental = FileDigest::new(&file_digest_key(&one));
+        incremental.update(&PLAINTEXT[..4]);
+        incremental.update(&PLAINTEXT[4..]);
+        assert_eq!(incremental.finalize_hex(), digest);
+    }
+
+    #[test]
+    fn current_digests_are_kept() {
+        let current = generate_private_key();
+        let digest = file_digest_hex(&file_digest_key(&current), PLAINTEXT);
+        assert_eq!(migrate_digest(&current, &[], PLAINTEXT, &digest).unwrap(), None);
+        assert_eq!(migrate_digest(&current, &[], PLAINTEXT, &format!(" {}\n", digest.to_uppercase())).unwrap(), None);
+    }
+
+    #[test]
+    fn old_digests_are_recomputed() {
+        let (current, previous) = (generate_private_key(), generate_private_key());
+        let expected = Some(file_digest_hex(&file_digest_key(&current), PLAINTEXT));
+        let previous_digest = file_digest_hex(&file_digest_key(&previous), PLAINTEXT);
+        let keyring = std::slice::from_ref(&previous);
+
+        assert_eq!(migrate_digest(&current, keyring, PLAINTEXT, &previous_digest).unwrap(), expected);
+        assert_eq!(migrate_digest(&current, &[], PLAINTEXT, &hash_file(PLAINTEXT)).unwrap(), expected);
+        assert_eq!(migrate_digest(&current, &[], PLAINTEXT, "").unwrap(), expected);
+        assert_eq!(migrate_digest(&current, &[], PLAINTEXT, "  ").unwrap(), expected);
+    }
+
+    #[test]
+    fn mismatched_digests_are_rejected() {
+        let (current, previous) = (generate_private_key(), generate_private_key());
+        let keyring = std::slice::from_ref(&previous);
+        let mismatch = |stored: &str| migrate_digest(&current, keyring, PLAINTEXT, stored).unwrap_err();
+
+        assert_eq!(mismatch(&hash_file(b"another file")).code().as_str(), "DIGEST_MISMATCH");
+        assert_eq!(mismatch(&"0".repeat(64)).code().as_str(), "DIGEST_MISMATCH");
+        // A previous key's digest of another file, or an unknown key's digest of this one
+        let other_file = file_digest_hex(&file_digest_key(&previous), b"another file");
+        assert_eq!(mismatch(&other_file).code().as_str(), "DIGEST_MISMATCH");
+        let unknown_key = file_digest_hex(&file_digest_key(&generate_private_key()), PLAINTEXT);
+        assert_eq!(mismatch(&unknown_key).code().as_str(), "DIGEST_MISMATCH");
+        assert_eq!(mismatch("not hex").code().as_str(), "INVALID_INPUT");
+    }
+}
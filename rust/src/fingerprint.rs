@@ -0,0 +1,140 @@
+//! Human-comparable fingerprints of public keys.
+//!
+//! Before trusting a public key received over the network (to share a file,
+//! or to check a signed device list), users can compare its fingerprint with
+//! the owner over another channel. A fingerprint is the first
+//! [`FINGERPRINT_LEN`] bytes of SHA-256 over a key-type label and the key, so
+//! an X25519 and an Ed25519 key with the same bytes never share one. It is
+//! shown either as eight groups of four hex digits or as 12 BIP39 English
+//! words, whose checksum word catches most typos when the words are read back.
+//!
+//! Fingerprints are for people. Records carry the shorter
+//! [`crate::key_wrap::key_id`] instead.
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+use bip39::Mnemonic;
+use sha2::{Digest, Sha256};
+
+use crate::bytes_to_hex;
+use crate::error::NovaultError;
+use crate::key_wrap::check_len;
+
+/// Length of a fingerprint in bytes
+pub const FINGERPRINT_LEN: usize = 16;
+
+/// Hash label for X25519 encryption keys
+const X25519_LABEL: &[u8] = b"novault/fingerprint/x25519";
+
+/// Hash label for Ed25519 signing keys
+const ED25519_LABEL: &[u8] = b"novault/fingerprint/ed25519";
+
+/// The fingerprint of a public key
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fingerprint {
+    bytes: [u8; FINGERPRINT_LEN],
+}
+
+/// Fingerprint of an X25519 public key, such as `EncryptedMasterKey.public_key`
+///
+/// # Arguments
+/// * `public_key` - The X25519 public key (32 bytes)
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn fingerprint_public_key(public_key: &[u8]) -> Result<Fingerprint, NovaultError> {
+    check_len(32, public_key, "Public key")?;
+    Ok(Fingerprint::of(X25519_LABEL, public_key))
+}
+
+/// Fingerprint of an Ed25519 public key, such as `EncryptedMasterKey.signing_public_key`
+///
+/// # Arguments
+/// * `signing_public_key` - The Ed25519 public key (32 bytes)
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn fingerprint_signing_key(signing_public_key: &[u8]) -> Result<Fingerprint, NovaultError> {
+    check_len(32, signing_public_key, "Signing public key")?;
+    Ok(Fingerprint::of(ED25519_LABEL, signing_public_key))
+}
+
+impl Fingerprint {
+    fn of(label: &[u8], public_key: &[u8]) -> Fingerprint {
+        let digest = Sha256::new().chain_update(label).chain_update(public_key).finalize();
+        let mut bytes = [0u8; FINGERPRINT_LEN];
+        bytes.copy_from_slice(&digest[..FINGERPRINT_LEN]);
+        Fingerprint { bytes }
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Fingerprint {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn bytes(&self) -> Vec<u8> {
+        self.bytes.to_vec()
+    }
+
+    /// The fingerprint as 8 space-separated groups of 4 hex digits
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn hex(&self) -> String {
+        self.bytes
+            .chunks(2)
+            .map(bytes_to_hex)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// The fingerprint as 12 space-separated BIP39 English words
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn words(&self) -> String {
+        Mnemonic::from_entropy(&self.bytes)
+            .expect("16 bytes is valid BIP39 entropy")
+            .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The bytes 0, 1, ..., 31
+    fn sample_key() -> Vec<u8> {
+        (0..32).collect()
+    }
+
+    #[test]
+    fn fingerprints_are_stable() {
+        let fingerprint = fingerprint_public_key(&sample_key()).unwrap();
+        assert_eq!(fingerprint.hex(), "ba9b 501f db08 0395 80c9 b540 6ff9 56af");
+        assert_eq!(fingerprint.words(), "ritual surround among render lens skull add cute document lemon fiber future");
+        assert_eq!(
+            fingerprint_signing_key(&sample_key()).unwrap().hex(),
+            "b3dc 72a2 de92 611e 5e18 c4da 6448 dcf8"
+        );
+    }
+
+    #[test]
+    fn key_types_are_kept_apart() {
+        let key = sample_key();
+        assert_ne!(fingerprint_public_key(&key).unwrap(), fingerprint_signing_key(&key).unwrap());
+        let mut other = key.clone();
+        other[31] ^= 1;
+        assert_ne!(fingerprint_public_key(&key).unwrap(), fingerprint_public_key(&other).unwrap());
+    }
+
+    #[test]
+    fn words_encode_the_fingerprint() {
+        let fingerprint = fingerprint_signing_key(&[0xA5; 32]).unwrap();
+        let words = fingerprint.words();
+        assert_eq!(words.split(' ').count(), 12);
+        let (entropy, len) = Mnemonic::parse_normalized(&words).unwrap().to_entropy_array();
+        assert_eq!(entropy[..len], fingerprint.bytes()[..]);
+        assert_eq!(fingerprint.bytes().len(), FINGERPRINT_LEN);
+    }
+
+    #[test]
+    fn keys_must_be_32_bytes() {
+        for len in [0, 31, 33] {
+            assert_eq!(fingerprint_public_key(&vec![0; len]).unwrap_err().code().as_str(), "INVALID_LENGTH");
+            assert_eq!(fingerprint_signing_key(&vec![0; len]).unwrap_err().code().as_str(), "INVALID_LENGTH");
+        }
+    }
+}
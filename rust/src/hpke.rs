@@ -0,0 +1,394 @@
+//! HPKE (RFC 9180) single-shot encryption, used to wrap DEKs.
+//!
+//! Base mode only hides the DEK from everyone but the recipient. Auth mode
+//! additionally mixes the sender's static X25519 key into the KEM, so a
+//! message opens only with the public key of the sender who sealed it.
+//!
+//! Implements the one cipher suite NoVault uses:
+//!
+//! - KEM: DHKEM(X25519, HKDF-SHA256), `0x0020`
+//! - KDF: HKDF-SHA256, `0x0001`
+//! - AEAD: AES-256-GCM, `0x0002`
+//!
+//! Only the single-shot API is provided: each context seals exactly one
+//! message with sequence number 0, so the AEAD nonce is the derived
+//! `base_nonce` and nothing besides the encapsulated key (`enc`) and the
+//! ciphertext needs to be stored.
+//!
+//! RFC 9180 has no test vectors for this exact suite. The tests run the KEM
+//! and key schedule against the Appendix A.1 vectors instead, which use the
+//! same KEM and KDF with AES-128-GCM.
+
+use aes_gcm::{
+    Aes256Gcm, Nonce, aead::{Aead, KeyInit, OsRng, Payload, generic_array::GenericArray}
+};
+use hkdf::{Hkdf, HkdfExtract};
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::error::NovaultError;
+use crate::key_wrap::{check_len, public_key_of};
+use crate::secret::{SecretBytes, SecretKey};
+use crate::logger::{log_debug, log_warn};
+
+/// KEM ID of DHKEM(X25519, HKDF-SHA256)
+pub const KEM_X25519_HKDF_SHA256: u16 = 0x0020;
+
+/// KDF ID of HKDF-SHA256
+pub const KDF_HKDF_SHA256: u16 = 0x0001;
+
+/// AEAD ID of AES-256-GCM
+pub const AEAD_AES256GCM: u16 = 0x0002;
+
+/// Length of the encapsulated key (an X25519 public key)
+pub const ENC_LEN: usize = 32;
+
+/// HPKE mode byte for Base mode
+const MODE_BASE: u8 = 0x00;
+
+/// HPKE mode byte for Auth mode
+const MODE_AUTH: u8 = 0x02;
+
+/// Prefix of every labeled HKDF input
+const VERSION_LABEL: &[u8] = b"HPKE-v1";
+
+/// `suite_id` used inside the KEM: "KEM" || I2OSP(kem_id, 2)
+const KEM_SUITE_ID: [u8; 5] = [b'K', b'E', b'M', 0x00, 0x20];
+
+/// `suite_id` used by the key schedule: "HPKE" || kem_id || kdf_id || aead_id
+const HPKE_SUITE_ID: [u8; 10] = [b'H', b'P', b'K', b'E', 0x00, 0x20, 0x00, 0x01, 0x00, 0x02];
+
+/// Encrypts `plaintext` to the recipient with HPKE Base mode
+///
+/// # Arguments
+/// * `recipient_public_key` - The recipient's X25519 public key (32 bytes)
+/// * `info` - Application context bound into the key schedule
+/// * `aad` - Additional authenticated data
+/// * `plaintext` - The message to seal
+///
+/// # Returns
+/// The encapsulated key `enc` and the ciphertext (with tag)
+pub fn seal_base(
+    recipient_public_key: &[u8],
+    info: &[u8],
+    aad: &[u8],
+    plaintext: &[u8],
+) -> Result<([u8; ENC_LEN], Vec<u8>), NovaultError> {
+    check_len(32, recipient_public_key, "Public key")?;
+    let recipient_public_key: [u8; 32] = recipient_public_key.try_into().unwrap();
+
+    log_debug!("[seal_base] Generating ephemeral key pair...");
+    let ephemeral_secret = StaticSecret::random_from_rng(OsRng);
+    seal(&ephemeral_secret, &recipient_public_key, None, info, aad, plaintext)
+}
+
+/// Encrypts `plaintext` to the recipient with HPKE Auth mode
+///
+/// # Arguments
+/// * `recipient_public_key` - The recipient's X25519 public key (32 bytes)
+/// * `sender_private_key` - The sender's static X25519 private key
+/// * `info` - Application context bound into the key schedule
+/// * `aad` - Additional authenticated data
+/// * `plaintext` - The message to seal
+///
+/// # Returns
+/// The encapsulated key `enc` and the ciphertext (with tag)
+pub fn seal_auth(
+    recipient_public_key: &[u8],
+    sender_private_key: &SecretKey,
+    info: &[u8],
+    aad: &[u8],
+    plaintext: &[u8],
+) -> Result<([u8; ENC_LEN], Vec<u8>), NovaultError> {
+    check_len(32, recipient_public_key, "Public key")?;
+    let recipient_public_key: [u8; 32] = recipient_public_key.try_into().unwrap();
+
+    log_debug!("[seal_auth] Generating ephemeral key pair...");
+    let ephemeral_secret = StaticSecret::random_from_rng(OsRng);
+    seal(&ephemeral_secret, &recipient_public_key, Some(sender_private_key), info, aad, plaintext)
+}
+
+/// Decrypts a message sealed with [`seal_base`]
+///
+/// HPKE is only used here to wrap DEKs, so any failure to open is reported as
+/// [`NovaultError::DekUnwrapFailed`].
+///
+/// # Arguments
+/// * `private_key` - The recipient's X25519 private key
+/// * `enc` - The encapsulated key returned by [`seal_base`] (32 bytes)
+/// * `info` - The same context that was passed to [`seal_base`]
+/// * `aad` - The same additional authenticated data
+/// * `ciphertext` - The sealed message
+pub fn open_base(
+    private_key: &SecretKey,
+    enc: &[u8],
+    info: &[u8],
+    aad: &[u8],
+    ciphertext: &[u8],
+) -> Result<SecretBytes, NovaultError> {
+    check_len(ENC_LEN, enc, "Encapsulated key")?;
+    let enc: [u8; ENC_LEN] = enc.try_into().unwrap();
+    open(private_key, &enc, None, info, aad, ciphertext)
+}
+
+/// Decrypts a message sealed with [`seal_auth`], proving who sealed it
+///
+/// Succeeds only if the message was sealed with the private key belonging to
+/// `sender_public_key`. Failures are reported as
+/// [`NovaultError::DekUnwrapFailed`], as for [`open_base`].
+///
+/// # Arguments
+/// * `private_key` - The recipient's X25519 private key
+/// * `enc` - The encapsulated key returned by [`seal_auth`] (32 bytes)
+/// * `sender_public_key` - The sender's static X25519 public key (32 bytes)
+/// * `info` - The same context that was passed to [`seal_auth`]
+/// * `aad` - The same additional authenticated data
+/// * `ciphertext` - The sealed message
+pub fn open_auth(
+    private_key: &SecretKey,
+    enc: &[u8],
+    sender_public_key: &[u8],
+    info: &[u8],
+    aad: &[u8],
+    ciphertext: &[u8],
+) -> Result<SecretBytes, NovaultError> {
+    check_len(ENC_LEN, enc, "Encapsulated key")?;
+    check_len(32, sender_public_key, "Sender public key")?;
+    let enc: [u8; ENC_LEN] = enc.try_into().unwrap();
+    let sender_public_key: [u8; 32] = sender_public_key.try_into().unwrap();
+    open(private_key, &enc, Some(&sender_public_key), info, aad, ciphertext)
+}
+
+/// Encap (Base) or AuthEncap (Auth, when `sender` is set), then a single seal
+fn seal(
+    ephemeral_secret: &StaticSecret,
+    recipient_public_key: &[u8; 32],
+    sender: Option<&SecretKey>,
+    info: &[u8],
+    aad: &[u8],
+    plaintext: &[u8],
+) -> Result<([u8; ENC_LEN], Vec<u8>), NovaultError> {
+    let (enc, shared_secret) = encap(ephemeral_secret, recipient_public_key, sender)?;
+    let mode = if sender.is_some() { MODE_AUTH } else { MODE_BASE };
+    let mut key = SecretKey::new([0u8; 32]);
+    let base_nonce = key_schedule(&HPKE_SUITE_ID, mode, &shared_secret, info, key.expose_mut());
+    log_debug!("[seal] Key schedule complete (mode {})", mode);
+
+    let cipher = Aes256Gcm::new(GenericArray::from_slice(key.expose()));
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&base_nonce), Payload { msg: plaintext, aad })
+        .map_err(|e| NovaultError::EncryptionFailed(format!("HPKE seal failed: {}", e)))?;
+    Ok((enc, ciphertext))
+}
+
+/// Decap (Base) or AuthDecap (Auth, when `sender_public_key` is set), then a single open
+fn open(
+    private_key: &SecretKey,
+    enc: &[u8; ENC_LEN],
+    sender_public_key: Option<&[u8; 32]>,
+    info: &[u8],
+    aad: &[u8],
+    ciphertext: &[u8],
+) -> Result<SecretBytes, NovaultError> {
+    let shared_secret = decap(private_key, enc, sender_public_key)?;
+    let mode = if sender_public_key.is_some() { MODE_AUTH } else { MODE_BASE };
+    let mut key = SecretKey::new([0u8; 32]);
+    let base_nonce = key_schedule(&HPKE_SUITE_ID, mode, &shared_secret, info, key.expose_mut());
+    log_debug!("[open] Key schedule complete (mode {})", mode);
+
+    let cipher = Aes256Gcm::new(GenericArray::from_slice(key.expose()));
+    match cipher.decrypt(Nonce::from_slice(&base_nonce), Payload { msg: ciphertext, aad }) {
+        Ok(plaintext) => Ok(SecretBytes::from(plaintext)),
+        Err(e) => {
+            log_warn!("[open] HPKE open failed: {}", e);
+            Err(NovaultError::DekUnwrapFailed)
+        }
+    }
+}
+
+/// Encap, or AuthEncap when `sender` is set, with a given ephemeral key
+///
+/// # Returns
+/// The encapsulated key and the KEM shared secret
+fn encap(
+    ephemeral_secret: &StaticSecret,
+    recipient_public_key: &[u8; 32],
+    sender: Option<&SecretKey>,
+) -> Result<([u8; ENC_LEN], SecretKey), NovaultError> {
+    let enc = PublicKey::from(ephemeral_secret).to_bytes();
+    let recipient_public = PublicKey::from(*recipient_public_key);
+
+    let mut dh = SecretBytes::with_capacity(64);
+    let dh_ephemeral = ephemeral_secret.diffie_hellman(&recipient_public);
+    if !dh_ephemeral.was_contributory() {
+        return Err(NovaultError::InvalidInput("Recipient public key is a low-order point".to_string()));
+    }
+    dh.extend_from_slice(dh_ephemeral.as_bytes());
+
+    let sender_public_key = sender.map(public_key_of);
+    if let Some(sender) = sender {
+        let dh_static = StaticSecret::from(*sender.expose()).diffie_hellman(&recipient_public);
+        dh.extend_from_slice(dh_static.as_bytes());
+    }
+
+    let mut kem_context: Vec<&[u8]> = vec![&enc, recipient_public_key];
+    kem_context.extend(sender_public_key.as_ref().map(|key| key.as_slice()));
+    Ok((enc, extract_and_expand(dh.expose(), &kem_context)))
+}
+
+/// Decap, or AuthDecap when `sender_public_key` is set
+///
+/// # Returns
+/// The KEM shared secret
+fn decap(
+    private_key: &SecretKey,
+    enc: &[u8; ENC_LEN],
+    sender_public_key: Option<&[u8; 32]>,
+) -> Result<SecretKey, NovaultError> {
+    let recipient_public_key = public_key_of(private_key);
+    let recipient_secret = StaticSecret::from(*private_key.expose());
+
+    let mut dh = SecretBytes::with_capacity(64);
+    let dh_ephemeral = recipient_secret.diffie_hellman(&PublicKey::from(*enc));
+    if !dh_ephemeral.was_contributory() {
+        log_warn!("[open] Encapsulated key is a low-order point");
+        return Err(NovaultError::DekUnwrapFailed);
+    }
+    dh.extend_from_slice(dh_ephemeral.as_bytes());
+
+    if let Some(sender_public_key) = sender_public_key {
+        let dh_static = recipient_secret.diffie_hellman(&PublicKey::from(*sender_public_key));
+        if !dh_static.was_contributory() {
+            log_warn!("[open] Sender public key is a low-order point");
+            return Err(NovaultError::DekUnwrapFailed);
+        }
+        dh.extend_from_slice(dh_static.as_bytes());
+    }
+
+    let mut kem_context: Vec<&[u8]> = vec![enc, &recipient_public_key];
+    kem_context.extend(sender_public_key.map(|key| key.as_slice()));
+    Ok(extract_and_expand(dh.expose(), &kem_context))
+}
+
+/// LabeledExtract(salt, label, ikm) from RFC 9180 section 4
+fn labeled_extract(suite_id: &[u8], salt: &[u8], label: &[u8], ikm: &[u8]) -> (SecretKey, Hkdf<Sha256>) {
+    let mut extract = HkdfExtract::<Sha256>::new(Some(salt));
+    extract.input_ikm(VERSION_LABEL);
+    extract.input_ikm(suite_id);
+    extract.input_ikm(label);
+    extract.input_ikm(ikm);
+    let (prk, hkdf) = extract.finalize();
+    (SecretKey::new(prk.into()), hkdf)
+}
+
+/// LabeledExpand(prk, label, info, L) from RFC 9180 section 4; `L` is `out.len()`
+fn labeled_expand(prk: &Hkdf<Sha256>, suite_id: &[u8], label: &[u8], info: &[&[u8]], out: &mut [u8]) {
+    let length = (out.len() as u16).to_be_bytes();
+    let mut parts: Vec<&[u8]> = vec![&length, VERSION_LABEL, suite_id, label];
+    parts.extend_from_slice(info);
+    prk.expand_multi_info(&parts, out)
+        .expect("HPKE output lengths are far below the HKDF-SHA256 limit");
+}
+
+/// ExtractAndExpand(dh, kem_context) from the DHKEM definition
+///
+/// `kem_context` is passed in parts (enc, recipient public key and, in Auth
+/// mode, sender public key) to avoid concatenating them. `dh` is the single
+/// DH output in Base mode, or both outputs concatenated in Auth mode.
+fn extract_and_expand(dh: &[u8], kem_context: &[&[u8]]) -> SecretKey {
+    let (_, eae_prk) = labeled_extract(&KEM_SUITE_ID, b"", b"eae_prk", dh);
+    let mut shared_secret = SecretKey::new([0u8; 32]);
+    labeled_expand(&eae_prk, &KEM_SUITE_ID, b"shared_secret", kem_context, shared_secret.expose_mut());
+    shared_secret
+}
+
+/// KeySchedule(mode, shared_secret, info) without a PSK
+///
+/// Writes the AEAD key into `key` (its length is Nk) and returns the base nonce.
+fn key_schedule(suite_id: &[u8], mode: u8, shared_secret: &SecretKey, info: &[u8], key: &mut [u8]) -> [u8; 12] {
+    let (psk_id_hash, _) = labeled_extract(suite_id, b"", b"psk_id_hash", b"");
+    let (info_hash, _) = labeled_extract(suite_id, b"", b"info_hash", info);
+    let context: [&[u8]; 3] = [&[mode], psk_id_hash.expose(), info_hash.expose()];
+
+    let (_, secret) = labeled_extract(suite_id, shared_secret.expose(), b"secret", b"");
+    labeled_expand(&secret, suite_id, b"key", &context, key);
+    let mut base_nonce = [0u8; 12];
+    labeled_expand(&secret, suite_id, b"base_nonce", &context, &mut base_nonce);
+    base_nonce
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes_gcm::Aes128Gcm;
+
+    /// Suite ID of the Appendix A.1 suite: the same KEM and KDF, with AES-128-GCM
+    const A1_SUITE_ID: [u8; 10] = [b'H', b'P', b'K', b'E', 0x00, 0x20, 0x00, 0x01, 0x00, 0x01];
+
+    const INFO: &[u8] = b"Ode on a Grecian Urn";
+
+    fn hex(s: &str) -> Vec<u8> {
+        crate::hex_to_bytes(s).unwrap()
+    }
+
+    fn secret(s: &str) -> SecretKey {
+        SecretKey::from_slice(&hex(s), "Test key").unwrap()
+    }
+
+    /// Runs the A.1 key schedule and seals the first message of the vectors
+    fn seal_first_message(mode: u8, shared_secret: &SecretKey) -> ([u8; 16], [u8; 12], Vec<u8>) {
+        let mut key = [0u8; 16];
+        let base_nonce = key_schedule(&A1_SUITE_ID, mode, shared_secret, INFO, &mut key);
+        let ciphertext = Aes128Gcm::new(GenericArray::from_slice(&key))
+            .encrypt(Nonce::from_slice(&base_nonce), Payload { msg: b"Beauty is truth, truth beauty", aad: b"Count-0" })
+            .unwrap();
+        (key, base_nonce, ciphertext)
+    }
+
+    #[test]
+    fn rfc9180_a1_1_base_mode() {
+        let ephemeral = StaticSecret::from(*secret("52c4a758a802cd8b936eceea314432798d5baf2d7e9235dc084ab1b9cfa2f736").expose());
+        let recipient = secret("4612c550263fc8ad58375df3f557aac531d26850903e55a9f23f21d8534e8ac8");
+        let recipient_public_key = public_key_of(&recipient);
+        assert_eq!(recipient_public_key.to_vec(), hex("3948cfe0ad1ddb695d780e59077195da6c56506b027329794ab02bca80815c4d"));
+
+        let (enc, shared_secret) = encap(&ephemeral, &recipient_public_key, None).unwrap();
+        assert_eq!(enc.to_vec(), hex("37fda3567bdbd628e88668c3c8d7e97d1d1253b6d4ea6d44c150f741f1bf4431"));
+        assert_eq!(shared_secret.expose().to_vec(), hex("fe0e18c9f024ce43799ae393c7e8fe8fce9d218875e8227b0187c04e7d2ea1fc"));
+        assert_eq!(decap(&recipient, &enc, None).unwrap().expose(), shared_secret.expose());
+
+        let (key, base_nonce, ciphertext) = seal_first_message(MODE_BASE, &shared_secret);
+        assert_eq!(key.to_vec(), hex("4531685d41d65f03dc48f6b8302c05b0"));
+        assert_eq!(base_nonce.to_vec(), hex("56d890e5accaaf011cff4b7d"));
+        assert_eq!(ciphertext, hex("f938558b5d72f1a23810b4be2ab4f84331acc02fc97babc53a52ae8218a355a96d8770ac83d07bea87e13c512a"));
+    }
+
+    #[test]
+    fn rfc9180_a1_3_auth_mode() {
+        let ephemeral = StaticSecret::from(*secret("ff4442ef24fbc3c1ff86375b0be1e77e88a0de1e79b30896d73411c5ff4c3518").expose());
+        let recipient = secret("fdea67cf831f1ca98d8e27b1f6abeb5b7745e9d35348b80fa407ff6958f9137e");
+        let sender = secret("dc4a146313cce60a278a5323d321f051c5707e9c45ba21a3479fecdf76fc69dd");
+        let (recipient_public_key, sender_public_key) = (public_key_of(&recipient), public_key_of(&sender));
+        assert_eq!(recipient_public_key.to_vec(), hex("1632d5c2f71c2b38d0a8fcc359355200caa8b1ffdf28618080466c909cb69b2e"));
+        assert_eq!(sender_public_key.to_vec(), hex("8b0c70873dc5aecb7f9ee4e62406a397b350e57012be45cf53b7105ae731790b"));
+
+        let (enc, shared_secret) = encap(&ephemeral, &recipient_public_key, Some(&sender)).unwrap();
+        assert_eq!(enc.to_vec(), hex("23fb952571a14a25e3d678140cd0e5eb47a0961bb18afcf85896e5453c312e76"));
+        assert_eq!(shared_secret.expose().to_vec(), hex("2d6db4cf719dc7293fcbf3fa64690708e44e2bebc81f84608677958c0d4448a7"));
+        assert_eq!(decap(&recipient, &enc, Some(&sender_public_key)).unwrap().expose(), shared_secret.expose());
+
+        let (key, base_nonce, ciphertext) = seal_first_message(MODE_AUTH, &shared_secret);
+        assert_eq!(key.to_vec(), hex("b062cb2c4dd4bca0ad7c7a12bbc341e6"));
+        assert_eq!(base_nonce.to_vec(), hex("a1bc314c1942ade7051ffed0"));
+        assert_eq!(ciphertext, hex("5fd92cc9d46dbf8943e72a07e42f363ed5f721212cd90bcfd072bfd9f44e06b80fd17824947496e21b680c141b"));
+    }
+
+    #[test]
+    fn auth_mode_opens_only_with_the_sender_key() {
+        let (recipient, sender) = (crate::key_wrap::generate_dek(), crate::key_wrap::generate_dek());
+        let (enc, ciphertext) = seal_auth(&public_key_of(&recipient), &sender, INFO, b"", b"dek").unwrap();
+        assert_eq!(open_auth(&recipient, &enc, &public_key_of(&sender), INFO, b"", &ciphertext).unwrap().expose(), b"dek");
+        assert!(open_auth(&recipient, &enc, &public_key_of(&recipient), INFO, b"", &ciphertext).is_err());
+        assert!(open_base(&recipient, &enc, INFO, b"", &ciphertext).is_err());
+    }
+}
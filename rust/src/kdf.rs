@@ -0,0 +1,473 @@
+//! Self-describing Argon2id parameters.
+//!
+//! The KEK used to be derived with fixed parameters from a bare salt, so the
+//! cost could never be raised without locking out existing users. The salt
+//! stored with a master key (`pk_salt`) may now be a PHC-style string that
+//! also carries the algorithm, version and cost:
+//!
+//! ```text
+//! $argon2id$v=19$m=131072,t=3,p=1$<salt>
+//! ```
+//!
+//! A bare salt still means [`KdfParams::LEGACY`], so existing rows unlock
+//! unchanged. New master keys always store the PHC form. As before, the bytes
+//! of the salt string (not its Base64 decoding) are the Argon2 salt.
+//!
+//! Keys derived under a pepper newer than the legacy one (see
+//! [`crate::pepper`]) also carry its version as the PHC key ID, Base64 of the
+//! 4-byte big-endian version:
+//!
+//! ```text
+//! $argon2id$v=19$m=131072,t=3,p=1,keyid=AAAAAQ$<salt>
+//! ```
+//!
+//! To upgrade a user at their next unlock:
+//!
+//! ```javascript
+//! vault = new wasm.UnlockedVault(password, salt, encryptedPrivateKey, pkNonce, esk, signingNonce);
+//! if (wasm.needs_kdf_upgrade(salt)) {
+//!   const key = wasm.upgrade_kdf(password, salt, encryptedPrivateKey, pkNonce, esk, signingNonce, !esk);
+//!   await saveUserSecrets(key); // same public key, new salt, nonces and ciphertexts
+//! }
+//! ```
+//!
+//! The cost can be fitted to the device with [`calibrate_kdf`], which never
+//! goes below [`KdfParams::MINIMUM`]:
+//!
+//! ```javascript
+//! const params = wasm.calibrate_kdf(1000); // about one second to unlock
+//! const key = wasm.encrypt_master_key(password, params);
+//! ```
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+use argon2::password_hash::ParamsString;
+use argon2::{Algorithm, Argon2, KeyId, Params, ParamsBuilder, PasswordHash, Version};
+
+use crate::error::NovaultError;
+use crate::pepper::{current_pepper_version, pepper, LEGACY_PEPPER_VERSION};
+use crate::secret::{SecretBytes, SecretKey};
+use crate::logger::{log_debug, log_info};
+
+/// Argon2id cost parameters
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KdfParams {
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+}
+
+impl KdfParams {
+    /// The parameters every bare salt was derived with (64 MiB, 3 iterations, 1 lane)
+    pub const LEGACY: KdfParams = KdfParams {
+        memory_kib: 65536,
+        iterations: 3,
+        parallelism: 1,
+    };
+
+    /// The parameters new master keys are created with unless calibrated, and
+    /// that keys at [`KdfParams::LEGACY`] are raised to (128 MiB, 3 iterations, 1 lane)
+    pub const DEFAULT: KdfParams = KdfParams {
+        memory_kib: 131072,
+        iterations: 3,
+        parallelism: 1,
+    };
+
+    /// The weakest parameters accepted for a new KEK (19 MiB, 2 iterations, 1 lane)
+    pub const MINIMUM: KdfParams = KdfParams {
+        memory_kib: 19456,
+        iterations: 2,
+        parallelism: 1,
+    };
+
+    /// The most memory calibration recommends, to stay within browser limits
+    pub const MAX_MEMORY_KIB: u32 = 262144;
+
+    /// The most iterations calibration recommends
+    pub const MAX_ITERATIONS: u32 = 10;
+
+    /// The stronger of two parameter sets, field by field
+    pub fn max(self, other: KdfParams) -> KdfParams {
+        KdfParams {
+            memory_kib: self.memory_kib.max(other.memory_kib),
+            iterations: self.iterations.max(other.iterations),
+            parallelism: self.parallelism.max(other.parallelism),
+        }
+    }
+
+    fn argon2_params(&self) -> Result<Params, NovaultError> {
+        Params::new(self.memory_kib, self.iterations, self.parallelism, Some(32)).map_err(invalid_params)
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl KdfParams {
+    /// Validates a set of Argon2id parameters
+    ///
+    /// # Arguments
+    /// * `memory_kib` - Memory cost in KiB
+    /// * `iterations` - Number of passes
+    /// * `parallelism` - Number of lanes
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(memory_kib: u32, iterations: u32, parallelism: u32) -> Result<KdfParams, NovaultError> {
+        let params = KdfParams {
+            memory_kib,
+            iterations,
+            parallelism,
+        };
+        params.argon2_params()?;
+        Ok(params)
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn memory_kib(&self) -> u32 {
+        self.memory_kib
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn iterations(&self) -> u32 {
+        self.iterations
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn parallelism(&self) -> u32 {
+        self.parallelism
+    }
+
+    /// Whether these parameters cost less memory or time than `other`
+    pub fn is_weaker_than(&self, other: &KdfParams) -> bool {
+        self.memory_kib < other.memory_kib || self.iterations < other.iterations
+    }
+}
+
+/// Reads the KDF parameters from a stored salt
+///
+/// # Arguments
+/// * `salt` - A bare salt, or a PHC string with the parameters and salt
+///
+/// # Returns
+/// The parameters; [`KdfParams::LEGACY`] for a bare salt
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn kdf_params_of(salt: &str) -> Result<KdfParams, NovaultError> {
+    parse_salt(salt).map(|stored| stored.params)
+}
+
+/// Whether a stored salt should be re-derived at the next unlock
+///
+/// True for bare salts, which do not record their parameters, for
+/// [`KdfParams::LEGACY`], the default before it was raised, for parameters
+/// weaker than [`KdfParams::MINIMUM`], and for keys derived under an older
+/// pepper than the newest registered one. Other calibrated parameters
+/// between the minimum and the default are left alone.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn needs_kdf_upgrade(salt: &str) -> Result<bool, NovaultError> {
+    if !is_phc(salt) {
+        return Ok(true);
+    }
+    let stored = parse_salt(salt)?;
+    let old_pepper = current_pepper_version().is_some_and(|current| stored.pepper_version < current);
+    let outdated = stored.params == KdfParams::LEGACY || stored.params.is_weaker_than(&KdfParams::MINIMUM);
+    Ok(outdated || old_pepper)
+}
+
+/// The parameters for a new KEK: `params`, or [`KdfParams::DEFAULT`]
+///
+/// Rejects parameters weaker than [`KdfParams::MINIMUM`].
+pub(crate) fn params_for_new_key(params: Option<KdfParams>) -> Result<KdfParams, NovaultError> {
+    let params = params.unwrap_or(KdfParams::DEFAULT);
+    if params.is_weaker_than(&KdfParams::MINIMUM) {
+        return Err(NovaultError::InvalidInput(
+            "KDF parameters are below the minimum".to_string(),
+        ));
+    }
+    Ok(params)
+}
+
+/// Benchmarks Argon2id on this device and recommends parameters
+///
+/// Memory is raised first, since it is what makes GPU attacks expensive,
+/// then iterations fill the remaining time. The result never goes below
+/// [`KdfParams::MINIMUM`], so a slow device gets the floor even if that takes
+/// longer than the target; it never exceeds [`KdfParams::MAX_MEMORY_KIB`] or
+/// [`KdfParams::MAX_ITERATIONS`].
+///
+/// Blocks for roughly 100 ms to a second while measuring.
+///
+/// # Arguments
+/// * `target_ms` - The desired time to derive the KEK, in milliseconds
+///
+/// # Returns
+/// The recommended parameters
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn calibrate_kdf(target_ms: u32) -> Result<KdfParams, NovaultError> {
+    if target_ms == 0 {
+        return Err(NovaultError::InvalidInput("Target time must be greater than 0".to_string()));
+    }
+
+    // Time passes at the floor's memory until the measurement is long enough
+    // to be above the timer's resolution
+    let floor = KdfParams::MINIMUM;
+    let mut passes = 1;
+    let elapsed = loop {
+        let probe = KdfParams::new(floor.memory_kib, passes, floor.parallelism)?;
+        let mut scratch = SecretKey::new([0u8; 32]);
+        let elapsed = time_ms(|| hash(&probe, b"calibration", None, "calibration-salt", &mut scratch))?;
+        if elapsed >= CALIBRATION_MIN_MS || passes >= CALIBRATION_MAX_PASSES {
+            break elapsed.max(1.0);
+        }
+        passes *= 2;
+    };
+    // Argon2 time grows linearly in memory * passes
+    let ms_per_kib_pass = elapsed / (f64::from(floor.memory_kib) * f64::from(passes));
+    let budget = f64::from(target_ms) / ms_per_kib_pass;
+    log_debug!("[calibrate_kdf] {:.1} ms for {} passes at {} KiB", elapsed, passes, floor.memory_kib);
+
+    let memory_kib = (budget / f64::from(floor.iterations))
+        .clamp(f64::from(floor.memory_kib), f64::from(KdfParams::MAX_MEMORY_KIB)) as u32;
+    // Whole MiB keep the stored strings readable
+    let memory_kib = (memory_kib / 1024 * 1024).max(floor.memory_kib);
+    let iterations = (budget / f64::from(memory_kib))
+        .clamp(f64::from(floor.iterations), f64::from(KdfParams::MAX_ITERATIONS)) as u32;
+
+    let params = KdfParams::new(memory_kib, iterations, floor.parallelism)?;
+    log_info!(
+        "[calibrate_kdf] Recommending m={} t={} p={} for {} ms",
+        params.memory_kib,
+        params.iterations,
+        params.parallelism,
+        target_ms
+    );
+    Ok(params)
+}
+
+/// Shortest benchmark run that calibration trusts
+const CALIBRATION_MIN_MS: f64 = 100.0;
+
+/// Upper bound on benchmark passes, for very fast machines
+const CALIBRATION_MAX_PASSES: u32 = 16;
+
+/// Runs `f` and returns how long it took, in milliseconds
+#[cfg(feature = "wasm")]
+fn time_ms<T>(f: impl FnOnce() -> Result<T, NovaultError>) -> Result<f64, NovaultError> {
+    let start = js_sys::Date::now();
+    f()?;
+    Ok(js_sys::Date::now() - start)
+}
+
+#[cfg(not(feature = "wasm"))]
+fn time_ms<T>(f: impl FnOnce() -> Result<T, NovaultError>) -> Result<f64, NovaultError> {
+    let start = std::time::Instant::now();
+    f()?;
+    Ok(start.elapsed().as_secs_f64() * 1000.0)
+}
+
+/// A stored salt, split into its parts
+pub(crate) struct StoredSalt<'a> {
+    pub params: KdfParams,
+    pub pepper_version: u32,
+    /// The Argon2 salt
+    pub salt: &'a str,
+}
+
+/// Splits a stored salt into its parameters, pepper version and Argon2 salt
+pub(crate) fn parse_salt(salt: &str) -> Result<StoredSalt<'_>, NovaultError> {
+    if !is_phc(salt) {
+        return Ok(StoredSalt {
+            params: KdfParams::LEGACY,
+            pepper_version: LEGACY_PEPPER_VERSION,
+            salt,
+        });
+    }
+
+    let invalid = |message: String| NovaultError::InvalidInput(format!("Invalid KDF parameters: {}", message));
+    let hash = PasswordHash::new(salt).map_err(|e| invalid(e.to_string()))?;
+    if hash.algorithm != argon2::ARGON2ID_IDENT {
+        return Err(invalid(format!("unsupported algorithm {}", hash.algorithm)));
+    }
+    if hash.version != Some(Version::V0x13.into()) {
+        return Err(invalid("unsupported or missing version".to_string()));
+    }
+    if hash.hash.is_some() {
+        return Err(invalid("unexpected hash output".to_string()));
+    }
+    let salt = hash.salt.ok_or_else(|| invalid("missing salt".to_string()))?;
+    let argon2_params = Params::try_from(&hash).map_err(|e| invalid(e.to_string()))?;
+    if !argon2_params.data().is_empty() {
+        return Err(invalid("unexpected associated data".to_string()));
+    }
+    // A key ID is the 4-byte big-endian pepper version; none means the legacy pepper
+    let pepper_version = match argon2_params.keyid() {
+        [] => LEGACY_PEPPER_VERSION,
+        keyid => u32::from_be_bytes(
+            keyid
+                .try_into()
+                .map_err(|_| invalid("key ID must be 4 bytes".to_string()))?,
+        ),
+    };
+    let params = KdfParams::new(argon2_params.m_cost(), argon2_params.t_cost(), argon2_params.p_cost())?;
+    log_debug!(
+        "[parse_salt] m={} t={} p={} pepper={}",
+        params.memory_kib,
+        params.iterations,
+        params.parallelism,
+        pepper_version
+    );
+    Ok(StoredSalt {
+        params,
+        pepper_version,
+        salt: salt.as_str(),
+    })
+}
+
+/// Encodes parameters, pepper version and salt as the PHC string stored with a master key
+pub(crate) fn encode_salt(params: &KdfParams, pepper_version: u32, salt: &str) -> Result<String, NovaultError> {
+    let mut builder = ParamsBuilder::new();
+    builder
+        .m_cost(params.memory_kib)
+        .t_cost(params.iterations)
+        .p_cost(params.parallelism);
+    if pepper_version != LEGACY_PEPPER_VERSION {
+        let keyid = KeyId::new(&pepper_version.to_be_bytes()).map_err(invalid_params)?;
+        builder.keyid(keyid);
+    }
+    let encoded = ParamsString::try_from(&builder.build().map_err(invalid_params)?).map_err(invalid_params)?;
+    Ok(format!(
+        "${}$v={}${}${}",
+        argon2::ARGON2ID_IDENT,
+        u32::from(Version::V0x13),
+        encoded,
+        salt
+    ))
+}
+
+/// Derives a 32-byte key with the registered pepper of `pepper_version`
+pub(crate) fn derive_key(
+    params: &KdfParams,
+    pepper_version: u32,
+    input: &str,
+    salt: &str,
+) -> Result<SecretKey, NovaultError> {
+    let pepper = pepper(pepper_version)?;
+    let mut derived_key = SecretKey::new([0u8; 32]);
+
+    if pepper_version == LEGACY_PEPPER_VERSION {
+        // combine input with pepper
+        let mut input_with_pepper = SecretBytes::with_capacity(input.len() + pepper.len());
+        input_with_pepper.extend_from_slice(input.as_bytes());
+        input_with_pepper.extend_from_slice(pepper.expose());
+        hash(params, input_with_pepper.expose(), None, salt, &mut derived_key)?;
+    } else {
+        hash(params, input.as_bytes(), Some(pepper.expose()), salt, &mut derived_key)?;
+    }
+
+    Ok(derived_key)
+}
+
+/// Runs Argon2id, with `secret` as its secret key if given
+fn hash(
+    params: &KdfParams,
+    password: &[u8],
+    secret: Option<&[u8]>,
+    salt: &str,
+    output: &mut SecretKey,
+) -> Result<(), NovaultError> {
+    let params = params.argon2_params()?;
+    let argon2 = match secret {
+        Some(secret) => Argon2::new_with_secret(secret, Algorithm::Argon2id, Version::V0x13, params)
+            .map_err(invalid_params)?,
+        None => Argon2::new(Algorithm::Argon2id, Version::V0x13, params),
+    };
+    argon2
+        .hash_password_into(password, salt.as_bytes(), output.expose_mut())
+        .map_err(|e| NovaultError::InvalidInput(format!("Key derivation failed: {}", e)))
+}
+
+fn invalid_params(e: impl std::fmt::Display) -> NovaultError {
+    NovaultError::InvalidInput(format!("Invalid KDF parameters: {}", e))
+}
+
+fn is_phc(salt: &str) -> bool {
+    salt.starts_with('$')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pepper::register_pepper;
+
+    const SALT: &str = "c29tZS1zYWx0LWJ5dGVz";
+
+    #[test]
+    fn salts_round_trip_through_phc_strings() {
+        let params = KdfParams::new(47104, 4, 2).unwrap();
+        let legacy = encode_salt(&params, LEGACY_PEPPER_VERSION, SALT).unwrap();
+        assert_eq!(legacy, format!("$argon2id$v=19$m=47104,t=4,p=2${}", SALT));
+        let peppered = encode_salt(&params, 7, SALT).unwrap();
+        assert_eq!(peppered, format!("$argon2id$v=19$m=47104,t=4,p=2,keyid=AAAABw${}", SALT));
+
+        for (encoded, pepper_version) in [(&legacy, LEGACY_PEPPER_VERSION), (&peppered, 7)] {
+            let stored = parse_salt(encoded).unwrap();
+            assert_eq!(stored.params, params);
+            assert_eq!(stored.pepper_version, pepper_version);
+            assert_eq!(stored.salt, SALT);
+        }
+
+        let bare = parse_salt("plain-old-salt").unwrap();
+        assert_eq!(bare.params, KdfParams::LEGACY);
+        assert_eq!(bare.pepper_version, LEGACY_PEPPER_VERSION);
+        assert_eq!(bare.salt, "plain-old-salt");
+    }
+
+    #[test]
+    fn malformed_salts_are_rejected() {
+        for salt in [
+            "$argon2i$v=19$m=65536,t=3,p=1$c29tZS1zYWx0LWJ5dGVz",
+            "$argon2d$v=19$m=65536,t=3,p=1$c29tZS1zYWx0LWJ5dGVz",
+            "$scrypt$ln=15,r=8,p=1$c29tZS1zYWx0LWJ5dGVz",
+            "$argon2id$v=16$m=65536,t=3,p=1$c29tZS1zYWx0LWJ5dGVz",
+            "$argon2id$m=65536,t=3,p=1$c29tZS1zYWx0LWJ5dGVz",
+            "$argon2id$v=19$m=65536,t=3,p=1",
+            "$argon2id$v=19$m=65536,t=3,p=1$c29tZS1zYWx0LWJ5dGVz$aGFzaC1vdXRwdXQtYnl0ZXM",
+            "$argon2id$v=19$m=65536,t=3,p=1,keyid=AAAA$c29tZS1zYWx0LWJ5dGVz",
+            "$argon2id$v=19$m=65536,t=3,p=1,data=AAAA$c29tZS1zYWx0LWJ5dGVz",
+            "$argon2id$v=19$m=1,t=3,p=1$c29tZS1zYWx0LWJ5dGVz",
+            "$argon2id$v=19$m=65536,t=0,p=1$c29tZS1zYWx0LWJ5dGVz",
+            "$argon2id$v=19$m=lots,t=3,p=1$c29tZS1zYWx0LWJ5dGVz",
+            "$",
+            "$$$$",
+        ] {
+            let error = parse_salt(salt).err().unwrap_or_else(|| panic!("accepted {:?}", salt));
+            assert_eq!(error.code().as_str(), "INVALID_INPUT", "{:?}", salt);
+            assert!(needs_kdf_upgrade(salt).is_err(), "{:?}", salt);
+        }
+    }
+
+    #[test]
+    fn outdated_salts_need_an_upgrade() {
+        register_pepper(1, b"kdf-test-pepper-one").unwrap();
+        register_pepper(2, b"kdf-test-pepper-two").unwrap();
+        let encoded = |params: KdfParams, pepper_version| encode_salt(&params, pepper_version, SALT).unwrap();
+
+        assert!(KdfParams::LEGACY.is_weaker_than(&KdfParams::DEFAULT));
+        assert!(needs_kdf_upgrade("plain-old-salt").unwrap());
+        assert!(needs_kdf_upgrade(&encoded(KdfParams::LEGACY, 2)).unwrap());
+        assert!(needs_kdf_upgrade(&encoded(KdfParams::new(8192, 2, 1).unwrap(), 2)).unwrap());
+        assert!(needs_kdf_upgrade(&encoded(KdfParams::new(19456, 1, 1).unwrap(), 2)).unwrap());
+        assert!(needs_kdf_upgrade(&encoded(KdfParams::DEFAULT, 1)).unwrap());
+        assert!(needs_kdf_upgrade(&encoded(KdfParams::DEFAULT, LEGACY_PEPPER_VERSION)).unwrap());
+
+        assert!(!needs_kdf_upgrade(&encoded(KdfParams::DEFAULT, 2)).unwrap());
+        // Calibrated for a slow device: left alone
+        assert!(!needs_kdf_upgrade(&encoded(KdfParams::MINIMUM, 2)).unwrap());
+        assert!(!needs_kdf_upgrade(&encoded(KdfParams::new(32768, 4, 1).unwrap(), 2)).unwrap());
+    }
+
+    #[test]
+    fn new_keys_are_never_below_the_minimum() {
+        assert_eq!(params_for_new_key(None).unwrap(), KdfParams::DEFAULT);
+        assert_eq!(params_for_new_key(Some(KdfParams::MINIMUM)).unwrap(), KdfParams::MINIMUM);
+        let weak = KdfParams::new(8192, 8, 1).unwrap();
+        assert_eq!(params_for_new_key(Some(weak)).unwrap_err().code().as_str(), "INVALID_INPUT");
+    }
+}
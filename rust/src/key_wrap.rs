@@ -0,0 +1,661 @@
+//! DEK wrapping shared by every file encryption path.
+//!
+//! A fresh ephemeral X25519 key pair is generated per wrap, and the DEK is
+//! encrypted with AES-256-GCM under a key derived from the ECDH shared secret
+//! with the recipient's public key. How that key is derived is selected by a
+//! [`KeyWrapScheme`]:
+//!
+//! - [`KeyWrapScheme::RawEcdh`] (1) uses the raw shared secret as the AES key.
+//!   This is what every file stored before scheme IDs existed uses. It is only
+//!   unwrapped; new and re-wrapped entries use one of the schemes below.
+//! - [`KeyWrapScheme::EcdhHkdf`] (2) runs HKDF-SHA256 over the shared secret,
+//!   binding the ephemeral public key, the recipient public key and a context
+//!   label, as ECIES/HPKE do.
+//! - [`KeyWrapScheme::HpkeBase`] (3) seals the DEK with HPKE Base mode (see
+//!   [`crate::hpke`]). The AEAD nonce is derived, so the entry has no DEK nonce.
+//! - [`KeyWrapScheme::HpkeAuth`] (4) seals the DEK with HPKE Auth mode under
+//!   the sender's static key. The entry records the sender's public key, and
+//!   unwrapping it proves the DEK came from the holder of that key. The
+//!   content is proven by the container's sender tags (see [`crate::container`]).
+//!
+//! A file encrypted for several recipients carries one [`WrappedDek`] per
+//! recipient, each tagged with the recipient's [`key_id`] so the reader can
+//! pick its own entry without trying them all.
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+use aes_gcm::{
+    Aes256Gcm, Nonce, aead::{Aead, KeyInit, OsRng, generic_array::GenericArray}
+};
+use hkdf::Hkdf;
+use sha2::{Digest, Sha256};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+pub use crate::{generate_nonce, bytes_to_hex};
+use crate::container::SENDER_TAG_LEN;
+use crate::error::NovaultError;
+use crate::hpke;
+use crate::secret::{SecretBytes, SecretKey};
+use crate::logger::{log_debug, log_warn};
+
+/// Length of a recipient key ID
+pub const KEY_ID_LEN: usize = 8;
+
+/// HKDF info label for [`KeyWrapScheme::EcdhHkdf`]
+const HKDF_WRAP_LABEL: &[u8] = b"novault/dek-wrap/v2";
+
+/// HPKE `info` for [`KeyWrapScheme::HpkeBase`]
+const HPKE_WRAP_INFO: &[u8] = b"novault/dek-wrap/hpke";
+
+/// HPKE `info` for sender tags
+const SENDER_TAG_INFO: &[u8] = b"novault/sender-tag";
+
+/// How the DEK-wrapping key is derived from the ECDH shared secret
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyWrapScheme {
+    /// Raw X25519 shared secret used as the AES-256-GCM key
+    #[default]
+    RawEcdh = 1,
+    /// HKDF-SHA256(shared secret, info = label || ephemeral pk || recipient pk)
+    EcdhHkdf = 2,
+    /// HPKE Base mode, DHKEM(X25519, HKDF-SHA256) / HKDF-SHA256 / AES-256-GCM
+    HpkeBase = 3,
+    /// HPKE Auth mode with the sender's static X25519 key, same cipher suite
+    HpkeAuth = 4,
+}
+
+impl KeyWrapScheme {
+    /// Scheme used for new containers, which record the scheme per entry
+    pub const LATEST: KeyWrapScheme = KeyWrapScheme::HpkeBase;
+
+    /// Looks up a scheme by its stored ID
+    pub fn from_id(id: u8) -> Result<KeyWrapScheme, NovaultError> {
+        match id {
+            1 => Ok(KeyWrapScheme::RawEcdh),
+            2 => Ok(KeyWrapScheme::EcdhHkdf),
+            3 => Ok(KeyWrapScheme::HpkeBase),
+            4 => Ok(KeyWrapScheme::HpkeAuth),
+            _ => Err(NovaultError::UnsupportedAlgorithm(id)),
+        }
+    }
+
+    /// Resolves an optional scheme ID passed from JavaScript, defaulting to `default`
+    pub(crate) fn from_option(id: Option<u8>, default: KeyWrapScheme) -> Result<KeyWrapScheme, NovaultError> {
+        id.map_or(Ok(default), KeyWrapScheme::from_id)
+    }
+
+    /// The ID stored in containers and database rows
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    /// Length of the stored DEK nonce; HPKE derives its nonce and stores none
+    pub fn nonce_len(self) -> usize {
+        match self {
+            KeyWrapScheme::RawEcdh | KeyWrapScheme::EcdhHkdf => 12,
+            KeyWrapScheme::HpkeBase | KeyWrapScheme::HpkeAuth => 0,
+        }
+    }
+
+    /// Whether entries carry a sender public key that unwrapping verifies
+    pub fn is_authenticated(self) -> bool {
+        self == KeyWrapScheme::HpkeAuth
+    }
+
+    /// The scheme a re-wrapped copy of an entry in this scheme gets
+    ///
+    /// Raw ECDH is only ever read, so its entries move to HKDF, which keeps
+    /// the same stored fields. Sender-authenticated entries become HPKE Base:
+    /// whoever re-wraps did not write the content, and the original sender's
+    /// key is not available to seal a sender tag for the new recipient.
+    pub(crate) fn for_rewrap(self) -> KeyWrapScheme {
+        match self {
+            KeyWrapScheme::RawEcdh => KeyWrapScheme::EcdhHkdf,
+            KeyWrapScheme::HpkeAuth => KeyWrapScheme::HpkeBase,
+            scheme => scheme,
+        }
+    }
+}
+
+/// A DEK encrypted to a single recipient
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug, Default)]
+pub struct WrappedDek {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(skip))]
+    pub scheme: KeyWrapScheme,
+    /// [`key_id`] of the recipient's public key; empty for entries stored
+    /// before key IDs were recorded
+    #[cfg_attr(feature = "wasm", wasm_bindgen(skip))]
+    pub key_id: Vec<u8>,
+    #[cfg_attr(feature = "wasm", wasm_bindgen(skip))]
+    pub ephemeral_public_key: Vec<u8>,
+    #[cfg_attr(feature = "wasm", wasm_bindgen(skip))]
+    pub dek_nonce: Vec<u8>,
+    #[cfg_attr(feature = "wasm", wasm_bindgen(skip))]
+    pub encrypted_dek: Vec<u8>,
+    /// Sender's static X25519 public key; set only for [`KeyWrapScheme::HpkeAuth`]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(skip))]
+    pub sender_public_key: Vec<u8>,
+}
+
+impl WrappedDek {
+    /// Builds an entry from the loose fields the web app stores per recipient
+    ///
+    /// `key_id` is the recipient's [`key_id`], if it was stored with the fields.
+    pub(crate) fn from_fields(
+        ephemeral_public_key: &[u8],
+        encrypted_dek: &[u8],
+        dek_nonce: &[u8],
+        wrap_scheme: Option<u8>,
+        key_id: Option<&[u8]>,
+    ) -> Result<WrappedDek, NovaultError> {
+        let scheme = KeyWrapScheme::from_option(wrap_scheme, KeyWrapScheme::RawEcdh)?;
+        if scheme.is_authenticated() {
+            return Err(NovaultError::InvalidInput(
+                "Sender-authenticated DEKs must be read from a container".to_string(),
+            ));
+        }
+        if let Some(key_id) = key_id {
+            check_len(KEY_ID_LEN, key_id, "Key ID")?;
+        }
+        Ok(WrappedDek {
+            scheme,
+            key_id: key_id.map(<[u8]>::to_vec).unwrap_or_default(),
+            ephemeral_public_key: ephemeral_public_key.to_vec(),
+            dek_nonce: dek_nonce.to_vec(),
+            encrypted_dek: encrypted_dek.to_vec(),
+            sender_public_key: Vec::new(),
+        })
+    }
+
+    /// [`key_id`] of the sender, or empty if the entry is not sender-authenticated
+    pub fn sender_key_id(&self) -> Vec<u8> {
+        if self.sender_public_key.is_empty() {
+            Vec::new()
+        } else {
+            key_id(&self.sender_public_key)
+        }
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl WrappedDek {
+    /// ID of the [`KeyWrapScheme`] this entry was wrapped with
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn wrap_scheme(&self) -> u8 {
+        self.scheme.id()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn key_id_hex(&self) -> String {
+        bytes_to_hex(&self.key_id)
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn ephemeral_public_key_hex(&self) -> String {
+        bytes_to_hex(&self.ephemeral_public_key)
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn dek_nonce_hex(&self) -> String {
+        bytes_to_hex(&self.dek_nonce)
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn encrypted_dek_hex(&self) -> String {
+        bytes_to_hex(&self.encrypted_dek)
+    }
+
+    /// The sender's public key, or an empty string if not sender-authenticated
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn sender_public_key_hex(&self) -> String {
+        bytes_to_hex(&self.sender_public_key)
+    }
+}
+
+/// Short identifier of an X25519 public key: the first 8 bytes of its SHA-256
+pub fn key_id(public_key: &[u8]) -> Vec<u8> {
+    Sha256::digest(public_key)[..KEY_ID_LEN].to_vec()
+}
+
+/// The X25519 public key belonging to a private key
+pub(crate) fn public_key_of(private_key: &SecretKey) -> [u8; 32] {
+    let secret = StaticSecret::from(*private_key.expose());
+    PublicKey::from(&secret).to_bytes()
+}
+
+/// Derives the AES-256-GCM key that wraps the DEK
+fn wrapping_key(
+    scheme: KeyWrapScheme,
+    shared_secret: &[u8; 32],
+    ephemeral_public_key: &[u8; 32],
+    recipient_public_key: &[u8; 32],
+) -> SecretKey {
+    match scheme {
+        KeyWrapScheme::RawEcdh => SecretKey::new(*shared_secret),
+        KeyWrapScheme::HpkeBase | KeyWrapScheme::HpkeAuth => unreachable!("HPKE derives its own AEAD key"),
+        KeyWrapScheme::EcdhHkdf => {
+            let mut info = Vec::with_capacity(HKDF_WRAP_LABEL.len() + 64);
+            info.extend_from_slice(HKDF_WRAP_LABEL);
+            info.extend_from_slice(ephemeral_public_key);
+            info.extend_from_slice(recipient_public_key);
+
+            let mut key = SecretKey::new([0u8; 32]);
+            Hkdf::<Sha256>::new(None, shared_secret)
+                .expand(&info, key.expose_mut())
+                .expect("32 bytes is a valid HKDF-SHA256 output length");
+            key
+        }
+    }
+}
+
+/// Generates a random 32-byte DEK
+pub fn generate_dek() -> SecretKey {
+    let dek_secret = StaticSecret::random_from_rng(OsRng);
+    SecretKey::new(dek_secret.to_bytes())
+}
+
+/// Encrypts a DEK to the recipient's X25519 public key
+///
+/// # Arguments
+/// * `dek` - The data encryption key to wrap
+/// * `recipient_public_key` - The recipient's X25519 public key (32 bytes)
+/// * `scheme` - How to derive the wrapping key from the shared secret; raw
+///   ECDH is rejected, as it is only kept for reading old files
+/// * `sender` - The sender's X25519 private key; required for
+///   [`KeyWrapScheme::HpkeAuth`] and rejected by every other scheme
+/// * `context` - The container's [`crate::container::ContainerHeader::context`],
+///   bound into [`KeyWrapScheme::HpkeAuth`] entries; other schemes ignore it
+pub fn wrap_dek(
+    dek: &SecretKey,
+    recipient_public_key: &[u8],
+    scheme: KeyWrapScheme,
+    sender: Option<&SecretKey>,
+    context: &[u8],
+) -> Result<WrappedDek, NovaultError> {
+    check_len(32, recipient_public_key, "Public key")?;
+    let (ephemeral_public_key, dek_nonce, encrypted_dek) = match (scheme, sender) {
+        (KeyWrapScheme::RawEcdh, _) => {
+            return Err(NovaultError::InvalidInput(
+                "Raw ECDH key wrapping is only supported for decryption".to_string(),
+            ));
+        }
+        (KeyWrapScheme::EcdhHkdf, None) => wrap_with_ecdh(dek, recipient_public_key, scheme)?,
+        (KeyWrapScheme::HpkeBase, None) => {
+            log_debug!("[wrap_dek] Sealing DEK with HPKE Base mode...");
+            let (enc, encrypted_dek) = hpke::seal_base(recipient_public_key, HPKE_WRAP_INFO, &[], dek.expose())?;
+            (enc.to_vec(), Vec::new(), encrypted_dek)
+        }
+        (KeyWrapScheme::HpkeAuth, Some(sender)) => {
+            log_debug!("[wrap_dek] Sealing DEK with HPKE Auth mode...");
+            let (enc, encrypted_dek) =
+                hpke::seal_auth(recipient_public_key, sender, HPKE_WRAP_INFO, context, dek.expose())?;
+            (enc.to_vec(), Vec::new(), encrypted_dek)
+        }
+        (KeyWrapScheme::HpkeAuth, None) => {
+            return Err(NovaultError::InvalidInput(
+                "Sender-authenticated wrapping needs the sender's private key".to_string(),
+            ));
+        }
+        (_, Some(_)) => {
+            return Err(NovaultError::InvalidInput(format!(
+                "Key wrap scheme {} does not authenticate a sender",
+                scheme.id()
+            )));
+        }
+    };
+
+    Ok(WrappedDek {
+        scheme,
+        key_id: key_id(recipient_public_key),
+        ephemeral_public_key,
+        dek_nonce,
+        encrypted_dek,
+        sender_public_key: sender.map(|sender| public_key_of(sender).to_vec()).unwrap_or_default(),
+    })
+}
+
+/// Ephemeral public key (or HPKE `enc`), DEK nonce and encrypted DEK
+type WrapOutput = (Vec<u8>, Vec<u8>, Vec<u8>);
+
+/// Wraps a DEK under a key derived from an ephemeral ECDH shared secret
+fn wrap_with_ecdh(
+    dek: &SecretKey,
+    recipient_public_key: &[u8],
+    scheme: KeyWrapScheme,
+) -> Result<WrapOutput, NovaultError> {
+    let recipient_public_key_array: [u8; 32] = recipient_public_key.try_into().unwrap();
+    let recipient_public = PublicKey::from(recipient_public_key_array);
+
+    // Generate ephemeral key pair and perform ECDH
+    log_debug!("[wrap_with_ecdh] Generating ephemeral key pair for ECDH...");
+    let ephemeral_secret = StaticSecret::random_from_rng(OsRng);
+    let ephemeral_public = PublicKey::from(&ephemeral_secret);
+    log_debug!("[wrap_with_ecdh] Ephemeral public key: {}", bytes_to_hex(ephemeral_public.as_bytes()));
+
+    // Derive shared secret using ECDH
+    let shared_secret = ephemeral_secret.diffie_hellman(&recipient_public);
+    if scheme != KeyWrapScheme::RawEcdh && !shared_secret.was_contributory() {
+        return Err(NovaultError::InvalidInput("Recipient public key is a low-order point".to_string()));
+    }
+    log_debug!("[wrap_with_ecdh] Shared secret derived via ECDH");
+    let wrapping_key = wrapping_key(
+        scheme,
+        shared_secret.as_bytes(),
+        ephemeral_public.as_bytes(),
+        recipient_public.as_bytes(),
+    );
+
+    // Encrypt the DEK using the derived wrapping key
+    let dek_nonce = generate_nonce();
+    log_debug!("[wrap_with_ecdh] DEK nonce: {}", bytes_to_hex(dek_nonce.as_slice()));
+
+    let dek_cipher = Aes256Gcm::new(GenericArray::from_slice(wrapping_key.expose()));
+
+    let encrypted_dek = dek_cipher
+        .encrypt(&dek_nonce, dek.expose().as_ref())
+        .map_err(|e| NovaultError::EncryptionFailed(format!("DEK encryption failed: {}", e)))?;
+    log_debug!("[wrap_with_ecdh] DEK encrypted! Size: {} bytes", encrypted_dek.len());
+
+    Ok((ephemeral_public.as_bytes().to_vec(), dek_nonce.to_vec(), encrypted_dek))
+}
+
+/// Encrypts a DEK to every key in a list of recipients
+///
+/// # Arguments
+/// * `dek` - The data encryption key to wrap
+/// * `recipient_public_keys` - One or more X25519 public keys, concatenated (32 bytes each)
+/// * `scheme` - How to derive the wrapping keys from the shared secrets
+/// * `sender` - The sender's X25519 private key, as for [`wrap_dek`]
+/// * `context` - The container context, as for [`wrap_dek`]
+///
+/// # Returns
+/// One wrapped DEK per recipient, in the same order
+pub fn wrap_dek_for_recipients(
+    dek: &SecretKey,
+    recipient_public_keys: &[u8],
+    scheme: KeyWrapScheme,
+    sender: Option<&SecretKey>,
+    context: &[u8],
+) -> Result<Vec<WrappedDek>, NovaultError> {
+    if recipient_public_keys.is_empty() || !recipient_public_keys.len().is_multiple_of(32) {
+        return Err(NovaultError::InvalidInput(format!(
+            "Recipient public keys must be a non-empty list of 32-byte keys, got {} bytes",
+            recipient_public_keys.len()
+        )));
+    }
+    let count = recipient_public_keys.len() / 32;
+    if count > u16::MAX as usize {
+        return Err(NovaultError::InvalidInput(format!("Too many recipients: {}", count)));
+    }
+    log_debug!("[wrap_dek_for_recipients] Wrapping DEK for {} recipients", count);
+
+    recipient_public_keys
+        .chunks_exact(32)
+        .map(|public_key| wrap_dek(dek, public_key, scheme, sender, context))
+        .collect()
+}
+
+/// Finds the entry addressed to this private key and unwraps the DEK
+///
+/// Entries whose key ID matches the private key's public key are tried
+/// first, then entries without a key ID.
+///
+/// # Returns
+/// The DEK and the entry it was unwrapped from. If the entry is
+/// sender-authenticated, its sender wrapped the DEK; whether they also wrote
+/// the content is up to [`open_sender_tag`].
+///
+/// # Errors
+/// [`NovaultError::NotRecipient`] if no entry can belong to this key
+pub fn unwrap_dek_for_recipient<'a>(
+    private_key: &SecretKey,
+    entries: &'a [WrappedDek],
+    context: &[u8],
+) -> Result<(SecretKey, &'a WrappedDek), NovaultError> {
+    let own_key_id = key_id(&public_key_of(private_key));
+    let mut candidates = entries
+        .iter()
+        .filter(|entry| entry.key_id == own_key_id)
+        .chain(entries.iter().filter(|entry| entry.key_id.is_empty()))
+        .peekable();
+    if candidates.peek().is_none() {
+        log_warn!("[unwrap_dek_for_recipient] No entry for key ID {} among {} entries", bytes_to_hex(&own_key_id), entries.len());
+        return Err(NovaultError::NotRecipient);
+    }
+
+    let mut last_error = NovaultError::NotRecipient;
+    for entry in candidates {
+        match unwrap_dek(private_key, entry, context) {
+            Ok(dek) => return Ok((dek, entry)),
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+/// Decrypts a wrapped DEK with the recipient's X25519 private key
+///
+/// # Arguments
+/// * `private_key` - The recipient's X25519 private key (32 bytes)
+/// * `wrapped` - The wrapped DEK produced by [`wrap_dek`]
+/// * `context` - The context the entry was wrapped with (see [`wrap_dek`])
+///
+/// # Errors
+/// [`NovaultError::NotRecipient`], without any decryption attempt, if the
+/// entry's key ID belongs to another key
+pub fn unwrap_dek(private_key: &SecretKey, wrapped: &WrappedDek, context: &[u8]) -> Result<SecretKey, NovaultError> {
+    if !wrapped.key_id.is_empty() && wrapped.key_id != key_id(&public_key_of(private_key)) {
+        log_debug!("[unwrap_dek] Entry is for key ID {}", bytes_to_hex(&wrapped.key_id));
+        return Err(NovaultError::NotRecipient);
+    }
+    check_len(32, &wrapped.ephemeral_public_key, "Ephemeral Public Key")?;
+    check_len(wrapped.scheme.nonce_len(), &wrapped.dek_nonce, "DEK Nonce")?;
+
+    match wrapped.scheme {
+        KeyWrapScheme::HpkeBase => {
+            log_debug!("[unwrap_dek] Opening DEK with HPKE Base mode...");
+            let dek = hpke::open_base(private_key, &wrapped.ephemeral_public_key, HPKE_WRAP_INFO, &[], &wrapped.encrypted_dek)?;
+            return SecretKey::from_slice(dek.expose(), "Decrypted DEK");
+        }
+        KeyWrapScheme::HpkeAuth => {
+            log_debug!("[unwrap_dek] Opening DEK with HPKE Auth mode...");
+            let dek = hpke::open_auth(
+                private_key,
+                &wrapped.ephemeral_public_key,
+                &wrapped.sender_public_key,
+                HPKE_WRAP_INFO,
+                context,
+                &wrapped.encrypted_dek,
+            )?;
+            return SecretKey::from_slice(dek.expose(), "Decrypted DEK");
+        }
+        KeyWrapScheme::RawEcdh | KeyWrapScheme::EcdhHkdf => {}
+    }
+
+    log_debug!("[unwrap_dek] Performing ECDH to derive shared secret...");
+    let ephemeral_public_array: [u8; 32] = wrapped.ephemeral_public_key.as_slice().try_into().unwrap();
+
+    let recipient_public = public_key_of(private_key);
+    let private_key = StaticSecret::from(*private_key.expose());
+    let ephemeral_public = PublicKey::from(ephemeral_public_array);
+
+    let shared_secret = private_key.diffie_hellman(&ephemeral_public);
+    log_debug!("[unwrap_dek] Shared secret derived via ECDH");
+    let wrapping_key = wrapping_key(
+        wrapped.scheme,
+        shared_secret.as_bytes(),
+        &ephemeral_public_array,
+        &recipient_public,
+    );
+
+    // Decrypt the DEK using the derived wrapping key
+    let dek_cipher = Aes256Gcm::new(GenericArray::from_slice(wrapping_key.expose()));
+    let dek_nonce = Nonce::from_slice(&wrapped.dek_nonce);
+
+    let dek = match dek_cipher.decrypt(dek_nonce, wrapped.encrypted_dek.as_slice()) {
+        Ok(decrypted) => {
+            log_debug!("[unwrap_dek] DEK decrypted! Size: {} bytes", decrypted.len());
+            SecretBytes::from(decrypted)
+        }
+        Err(e) => {
+            log_warn!("[unwrap_dek] DEK decryption failed: {}", e);
+            return Err(NovaultError::DekUnwrapFailed);
+        }
+    };
+
+    SecretKey::from_slice(dek.expose(), "Decrypted DEK")
+}
+
+/// Wraps an existing file's DEK for another recipient
+///
+/// Only the DEK is re-encrypted; the file payload is untouched, so sharing a
+/// file costs one new wrapped-DEK record instead of a re-upload. The new
+/// entry's [`KeyWrapScheme`] follows `wrapped`'s, except that raw ECDH
+/// entries are re-wrapped with HKDF and sender-authenticated ones in HPKE
+/// Base mode; store the new entry's `wrap_scheme` with it.
+///
+/// # Arguments
+/// * `private_key` - The current recipient's X25519 private key
+/// * `wrapped` - The current recipient's wrapped DEK
+/// * `context` - The context `wrapped` was wrapped with (see [`wrap_dek`])
+/// * `recipient_public_key` - The new recipient's X25519 public key (32 bytes)
+pub fn rewrap_dek(
+    private_key: &SecretKey,
+    wrapped: &WrappedDek,
+    context: &[u8],
+    recipient_public_key: &[u8],
+) -> Result<WrappedDek, NovaultError> {
+    check_len(32, recipient_public_key, "Public key")?;
+    let dek = unwrap_dek(private_key, wrapped, context)?;
+    log_debug!("[rewrap_dek] DEK unwrapped, wrapping for new recipient...");
+    wrap_dek(&dek, recipient_public_key, wrapped.scheme.for_rewrap(), None, &[])
+}
+
+/// Seals a sender tag over a content commitment to every recipient
+///
+/// # Arguments
+/// * `recipient_public_keys` - The recipients' X25519 public keys, concatenated (32 bytes each)
+/// * `sender` - The sender's X25519 private key
+/// * `commitment` - The commitment to the container's context and ciphertext
+///
+/// # Returns
+/// The tags as stored after the ciphertext, each holding the recipient key
+/// ID, encapsulated key and AEAD tag ([`SENDER_TAG_LEN`] bytes)
+pub(crate) fn seal_sender_tags(
+    recipient_public_keys: &[u8],
+    sender: &SecretKey,
+    commitment: &[u8; 32],
+) -> Result<Vec<u8>, NovaultError> {
+    let mut out = Vec::with_capacity(recipient_public_keys.len() / 32 * SENDER_TAG_LEN);
+    for public_key in recipient_public_keys.chunks_exact(32) {
+        let (enc, tag) = hpke::seal_auth(public_key, sender, SENDER_TAG_INFO, commitment, &[])?;
+        out.extend_from_slice(&key_id(public_key));
+        out.extend_from_slice(&enc);
+        out.extend_from_slice(&tag);
+    }
+    Ok(out)
+}
+
+/// Checks that the sender of `entry` sealed a tag over `commitment` to this key
+///
+/// # Arguments
+/// * `private_key` - The recipient's X25519 private key
+/// * `entry` - The recipient's sender-authenticated entry
+/// * `sender_tags` - All sender tags of the container
+/// * `commitment` - The commitment to the container's context and ciphertext
+///
+/// # Errors
+/// [`NovaultError::SenderAuthFailed`] if there is no tag for this key or it
+/// does not open
+pub(crate) fn open_sender_tag(
+    private_key: &SecretKey,
+    entry: &WrappedDek,
+    sender_tags: &[u8],
+    commitment: &[u8; 32],
+) -> Result<(), NovaultError> {
+    let own_key_id = key_id(&public_key_of(private_key));
+    let tag = sender_tags
+        .chunks_exact(SENDER_TAG_LEN)
+        .find(|tag| tag[..KEY_ID_LEN] == own_key_id[..])
+        .ok_or_else(|| {
+            log_warn!("[open_sender_tag] No sender tag for key ID {}", bytes_to_hex(&own_key_id));
+            NovaultError::SenderAuthFailed
+        })?;
+    let (enc, sealed) = tag[KEY_ID_LEN..].split_at(hpke::ENC_LEN);
+    hpke::open_auth(private_key, enc, &entry.sender_public_key, SENDER_TAG_INFO, commitment, sealed)
+        .map(|_| ())
+        .map_err(|_| {
+            log_warn!("[open_sender_tag] Sender tag does not match the content");
+            NovaultError::SenderAuthFailed
+        })
+}
+
+pub(crate) fn check_len(max: usize, input: &[u8], name: &'static str) -> Result<(), NovaultError> {
+    if input.len() != max {
+        Err(NovaultError::InvalidLength {
+            name,
+            expected: max,
+            actual: input.len(),
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// An entry as stored before wrap schemes existed, with no scheme or key ID
+#[cfg(test)]
+pub(crate) fn legacy_raw_ecdh_entry(dek: &SecretKey, recipient_public_key: &[u8]) -> WrappedDek {
+    let (ephemeral_public_key, dek_nonce, encrypted_dek) =
+        wrap_with_ecdh(dek, recipient_public_key, KeyWrapScheme::RawEcdh).unwrap();
+    WrappedDek::from_fields(&ephemeral_public_key, &encrypted_dek, &dek_nonce, None, None).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::masterkey_generator::generate_private_key;
+
+    #[test]
+    fn raw_ecdh_entries_still_unwrap() {
+        let (private_key, dek) = (generate_private_key(), generate_dek());
+        let legacy = legacy_raw_ecdh_entry(&dek, &public_key_of(&private_key));
+        assert_eq!(legacy.scheme, KeyWrapScheme::RawEcdh);
+        assert_eq!(unwrap_dek(&private_key, &legacy, &[]).unwrap().expose(), dek.expose());
+
+        // The same fields read as HKDF derive a different wrapping key
+        let misread = WrappedDek { scheme: KeyWrapScheme::EcdhHkdf, ..legacy };
+        assert_eq!(unwrap_dek(&private_key, &misread, &[]).unwrap_err(), NovaultError::DekUnwrapFailed);
+    }
+
+    #[test]
+    fn hkdf_entries_do_not_unwrap_as_raw_ecdh() {
+        let (private_key, dek) = (generate_private_key(), generate_dek());
+        let wrapped = wrap_dek(&dek, &public_key_of(&private_key), KeyWrapScheme::EcdhHkdf, None, &[]).unwrap();
+        assert_eq!(unwrap_dek(&private_key, &wrapped, &[]).unwrap().expose(), dek.expose());
+
+        let misread = WrappedDek { scheme: KeyWrapScheme::RawEcdh, ..wrapped };
+        assert_eq!(unwrap_dek(&private_key, &misread, &[]).unwrap_err(), NovaultError::DekUnwrapFailed);
+    }
+
+    #[test]
+    fn raw_ecdh_is_never_used_to_wrap() {
+        let (private_key, dek) = (generate_private_key(), generate_dek());
+        let result = wrap_dek(&dek, &public_key_of(&private_key), KeyWrapScheme::RawEcdh, None, &[]);
+        assert_eq!(result.unwrap_err().code().as_str(), "INVALID_INPUT");
+    }
+
+    #[test]
+    fn rewrapping_moves_raw_ecdh_entries_to_hkdf() {
+        let (owner, friend, dek) = (generate_private_key(), generate_private_key(), generate_dek());
+        let legacy = legacy_raw_ecdh_entry(&dek, &public_key_of(&owner));
+
+        let shared = rewrap_dek(&owner, &legacy, &[], &public_key_of(&friend)).unwrap();
+        assert_eq!(shared.scheme, KeyWrapScheme::EcdhHkdf);
+        assert_eq!(shared.dek_nonce.len(), 12);
+        assert_eq!(unwrap_dek(&friend, &shared, &[]).unwrap().expose(), dek.expose());
+
+        let hpke = wrap_dek(&dek, &public_key_of(&owner), KeyWrapScheme::HpkeBase, None, &[]).unwrap();
+        assert_eq!(rewrap_dek(&owner, &hpke, &[], &public_key_of(&friend)).unwrap().scheme, KeyWrapScheme::HpkeBase);
+    }
+}
@@ -1,67 +1,65 @@
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
-use argon2::{Argon2, Algorithm, Version, Params, password_hash::SaltString};
 use aes_gcm::{
     Aes256Gcm, aead::{AeadCore, OsRng, generic_array::GenericArray, consts::U12}
 };
 use sha2::{Sha256, Digest};
+use crate::error::NovaultError;
+use crate::secret::SecretKey;
+use crate::logger::log_debug;
 
 pub mod masterkey_generator;
 pub mod masterkey_decryptor;
 pub mod encrypt_file;
 pub mod decrypt_file;
+pub mod container;
+pub mod key_wrap;
+pub mod hpke;
+pub mod kdf;
+pub mod pepper;
+pub mod signing;
+pub mod recovery;
+pub mod shamir;
+pub mod device;
+pub mod rotation;
+pub mod fingerprint;
+pub mod file_digest;
+pub mod stream;
+pub mod range_reader;
+pub mod vault;
+pub mod secret;
+pub mod logger;
+pub mod error;
 
 // Nonce type alias for AES-256-GCM (12 bytes)
 pub type Nonce = GenericArray<u8, U12>;
+#[cfg(feature = "wasm")]
 #[wasm_bindgen]
 extern "C" {
     pub fn alert(s: &str);
-    #[wasm_bindgen(js_namespace = console)]
-    pub fn log(s: &str);
 }
 
-/// Derives a 32-byte key with pepper
-pub fn get_key_encryption_key(input: &str, salt: &str) -> Vec<u8> {
-    let paminta = get_paminta();
- 
-    // combine input with pepper
-    let mut input_with_pepper = input.as_bytes().to_vec();
-    input_with_pepper.extend_from_slice(&paminta);
-
-    // argon2id config
-    let params = Params::new(
-        65536,  // 64 MB memory cost
-        3,      // 3 iterations
-        1,      // 1 degree of parallelism
-        Some(32) // 32 bytes output
-    ).unwrap();
-
-    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
-
-    let mut derived_key = vec![0u8; 32];
-    argon2
-        .hash_password_into(&input_with_pepper, salt.as_bytes(), &mut derived_key)
-        .expect("Failed to hash password");
-
-    derived_key 
-}
-
-// - 4rD^grSXyRwJ~Wuc5vcHL5
-fn get_paminta() -> Vec<u8> {
-    vec![
-        52, 114, 68, 94, 103, 114, 83, 88,   // 4rD^grSX
-        121, 82, 119, 74, 126, 87, 117, 99,  // yRwJ~Wuc
-        53, 118, 99, 72, 76, 53              // 5vcHL5
-    ]
+/// Derives a 32-byte key with the registered pepper (see [`pepper`])
+///
+/// # Arguments
+/// * `input` - The password
+/// * `salt` - The stored salt: bare (legacy parameters) or a PHC string (see [`kdf`])
+pub fn get_key_encryption_key(input: &str, salt: &str) -> Result<SecretKey, NovaultError> {
+    let stored = kdf::parse_salt(salt)?;
+    kdf::derive_key(&stored.params, stored.pepper_version, input, stored.salt)
 }
 
 /// Computes SHA-256 hash of the given data
+///
+/// The hash is unkeyed; file digests stored next to ciphertext use
+/// [`file_digest`] instead.
 pub fn hash_file(data: &[u8]) -> String {
-    log("[hash_file] Computing SHA-256 hash...");
+    log_debug!("[hash_file] Computing SHA-256 hash...");
     let mut hasher = Sha256::new();
     hasher.update(data);
     let result = hasher.finalize();
     let hash_hex = bytes_to_hex(&result);
-    log(&format!("[hash_file] Hash computed: {}", hash_hex));
+    log_debug!("[hash_file] Hash computed: {}", hash_hex);
     hash_hex
 }
 
@@ -70,16 +68,21 @@ pub fn bytes_to_hex(data: &[u8]) -> String {
     data.iter().map(|b| format!("{:02x}", b)).collect()
 }
 
-pub fn hex_to_bytes(hex: &str) -> Result<Vec<u8>, String> {
-    if hex.len() % 2 != 0 {
-        return Err("Invalid hex string length".to_string());
+pub fn hex_to_bytes(hex: &str) -> Result<Vec<u8>, NovaultError> {
+    if !hex.len().is_multiple_of(2) {
+        return Err(NovaultError::InvalidInput("Invalid hex string length".to_string()));
     }
 
-    (0..hex.len())
-        .step_by(2)
-        .map(|i| {
-            u8::from_str_radix(&hex[i..i + 2], 16)
-                .map_err(|_| format!("Invalid hex character at position {}", i))
+    // Work on bytes, so non-ASCII input is an error rather than a bad slice
+    hex.as_bytes()
+        .chunks(2)
+        .enumerate()
+        .map(|(i, pair)| {
+            let digit = |c: u8| (c as char).to_digit(16);
+            match (digit(pair[0]), digit(pair[1])) {
+                (Some(high), Some(low)) => Ok((high * 16 + low) as u8),
+                _ => Err(NovaultError::InvalidInput(format!("Invalid hex character at position {}", i * 2))),
+            }
         })
         .collect()
 }
@@ -89,13 +92,7 @@ pub fn generate_nonce() -> Nonce {
     Aes256Gcm::generate_nonce(&mut OsRng)
 }
 
-#[wasm_bindgen]
-pub fn master_key_bytes_to_hex(input: &str, salt: &str) -> String {
-    let key = get_key_encryption_key(input, salt);
-    bytes_to_hex(&key)
-}
-
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub fn generate_nonce_hex() -> String {
     let nonce = generate_nonce();
     bytes_to_hex(nonce.as_slice())
@@ -0,0 +1,219 @@
+//! Leveled logging facade for the crypto code.
+//!
+//! All diagnostics go through the `log_trace!`, `log_debug!`, `log_info!`,
+//! `log_warn!` and `log_error!` macros instead of calling the console directly. Messages are sent
+//! to `console.*` by default, or to a sink registered from JavaScript with
+//! [`set_log_sink`]. Native builds write to stderr when the `native` feature is
+//! enabled and drop messages otherwise; they can register a Rust closure as
+//! the sink instead. The `wasm` feature built for a native target (e.g.
+//! `cargo test` with default features) has no console and drops messages.
+//!
+//! `log_trace!` and `log_debug!` are compiled out of release builds
+//! (`debug_assertions` off), so `wasm-pack build --release` ships without them.
+//!
+//! ## Redaction policy
+//!
+//! Key material must never reach a log sink:
+//!
+//! - Keys, DEKs, shared secrets and passwords are only ever held in
+//!   [`crate::secret`] types, whose `Debug` output is redacted and which do not
+//!   implement `Display`.
+//! - Raw byte buffers are logged through [`Redacted`], which prints only their
+//!   length. Never pass `bytes_to_hex(...)` of a secret to a log macro.
+
+use std::cell::{Cell, RefCell};
+use std::fmt;
+#[cfg(not(feature = "wasm"))]
+use std::rc::Rc;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = console, js_name = debug)]
+    fn console_debug(s: &str);
+    #[wasm_bindgen(js_namespace = console, js_name = info)]
+    fn console_info(s: &str);
+    #[wasm_bindgen(js_namespace = console, js_name = warn)]
+    fn console_warn(s: &str);
+    #[wasm_bindgen(js_namespace = console, js_name = error)]
+    fn console_error(s: &str);
+}
+
+/// Severity of a log message, in increasing order
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Trace = 0,
+    Debug = 1,
+    Info = 2,
+    Warn = 3,
+    Error = 4,
+}
+
+/// Closure receiving `(level, message)` for every log message
+#[cfg(not(feature = "wasm"))]
+pub type Sink = Box<dyn Fn(LogLevel, &str)>;
+
+/// Sink as held in `SINK`, cheap to clone out before calling it
+#[cfg(feature = "wasm")]
+type SharedSink = js_sys::Function;
+#[cfg(not(feature = "wasm"))]
+type SharedSink = Rc<dyn Fn(LogLevel, &str)>;
+
+thread_local! {
+    static SINK: RefCell<Option<SharedSink>> = const { RefCell::new(None) };
+    static MIN_LEVEL: Cell<LogLevel> = const { Cell::new(LogLevel::Trace) };
+}
+
+/// Registers a JavaScript function that receives every log message
+///
+/// The sink is called as `sink(level, message)`, where `level` is a
+/// [`LogLevel`] value. Pass `undefined` to go back to the console.
+///
+/// # Example (JavaScript)
+///
+/// ```javascript
+/// wasm.set_log_sink((level, message) => collector.push({ level, message }));
+/// ```
+#[cfg(feature = "wasm")]
+#[wasm_bindgen]
+pub fn set_log_sink(sink: Option<js_sys::Function>) {
+    SINK.with(|s| *s.borrow_mut() = sink);
+}
+
+/// Registers a closure that receives every log message
+///
+/// Pass `None` to go back to the default output.
+#[cfg(not(feature = "wasm"))]
+pub fn set_log_sink(sink: Option<Sink>) {
+    SINK.with(|s| *s.borrow_mut() = sink.map(Rc::from));
+}
+
+/// Drops every message below `level`
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn set_log_level(level: LogLevel) {
+    MIN_LEVEL.with(|l| l.set(level));
+}
+
+/// Dispatches a message to the registered sink, or to the console
+///
+/// Use the logging macros rather than calling this directly.
+pub fn write(level: LogLevel, args: fmt::Arguments) {
+    if level < MIN_LEVEL.with(Cell::get) {
+        return;
+    }
+    let message = args.to_string();
+
+    // Clone the sink out so it may call `set_log_sink` while it runs
+    match SINK.with(|s| s.borrow().clone()) {
+        Some(sink) => call_sink(&sink, level, &message),
+        None => default_output(level, &message),
+    }
+}
+
+#[cfg(feature = "wasm")]
+fn call_sink(sink: &SharedSink, level: LogLevel, message: &str) {
+    // A throwing sink must not break the crypto operation being logged
+    let _ = sink.call2(&JsValue::NULL, &JsValue::from(level as u32), &JsValue::from_str(message));
+}
+
+#[cfg(not(feature = "wasm"))]
+fn call_sink(sink: &SharedSink, level: LogLevel, message: &str) {
+    sink(level, message);
+}
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+fn default_output(level: LogLevel, message: &str) {
+    match level {
+        LogLevel::Trace | LogLevel::Debug => console_debug(message),
+        LogLevel::Info => console_info(message),
+        LogLevel::Warn => console_warn(message),
+        LogLevel::Error => console_error(message),
+    }
+}
+
+#[cfg(all(not(feature = "wasm"), feature = "native"))]
+fn default_output(level: LogLevel, message: &str) {
+    eprintln!("[{:?}] {}", level, message);
+}
+
+#[cfg(any(all(not(feature = "wasm"), not(feature = "native")), all(feature = "wasm", not(target_arch = "wasm32"))))]
+fn default_output(_level: LogLevel, _message: &str) {}
+
+/// Logs a buffer by length only, never by content
+pub struct Redacted<'a>(pub &'a [u8]);
+
+impl fmt::Display for Redacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<{} bytes redacted>", self.0.len())
+    }
+}
+
+#[allow(unused_macros)]
+macro_rules! log_trace {
+    ($($arg:tt)*) => {
+        if cfg!(debug_assertions) {
+            $crate::logger::write($crate::logger::LogLevel::Trace, format_args!($($arg)*))
+        }
+    };
+}
+
+macro_rules! log_debug {
+    ($($arg:tt)*) => {
+        if cfg!(debug_assertions) {
+            $crate::logger::write($crate::logger::LogLevel::Debug, format_args!($($arg)*))
+        }
+    };
+}
+
+macro_rules! log_info {
+    ($($arg:tt)*) => {
+        $crate::logger::write($crate::logger::LogLevel::Info, format_args!($($arg)*))
+    };
+}
+
+macro_rules! log_warn {
+    ($($arg:tt)*) => {
+        $crate::logger::write($crate::logger::LogLevel::Warn, format_args!($($arg)*))
+    };
+}
+
+#[allow(unused_macros)]
+macro_rules! log_error {
+    ($($arg:tt)*) => {
+        $crate::logger::write($crate::logger::LogLevel::Error, format_args!($($arg)*))
+    };
+}
+
+#[allow(unused_imports)]
+pub(crate) use {log_debug, log_error, log_info, log_trace, log_warn};
+
+#[cfg(all(test, not(feature = "wasm")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sink_can_replace_itself() {
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let collector = seen.clone();
+        set_log_sink(Some(Box::new(move |level, message: &str| {
+            collector.borrow_mut().push((level, message.to_string()));
+            let collector = collector.clone();
+            set_log_sink(Some(Box::new(move |level, message: &str| {
+                collector.borrow_mut().push((level, format!("replaced: {}", message)));
+            })));
+        })));
+
+        log_info!("first");
+        log_warn!("second");
+        set_log_sink(None);
+        log_info!("dropped");
+
+        assert_eq!(
+            *seen.borrow(),
+            vec![(LogLevel::Info, "first".to_string()), (LogLevel::Warn, "replaced: second".to_string())]
+        );
+    }
+}
@@ -1,15 +1,21 @@
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 use aes_gcm::{
     Aes256Gcm, Nonce, aead::{Aead, KeyInit, generic_array::GenericArray}
 };
 
-pub use crate::{get_key_encryption_key, bytes_to_hex, log};
+pub use crate::{get_key_encryption_key, bytes_to_hex};
+use crate::error::NovaultError;
+use crate::key_wrap::check_len;
+use crate::secret::{SecretBytes, SecretKey};
+use x25519_dalek::{PublicKey, StaticSecret};
+use crate::logger::{log_debug, log_info, log_warn};
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub struct DecryptedPrivateKey {
     success: bool,
-    private_key: Vec<u8>,
-    error_message: String,
+    private_key: SecretBytes,
+    error: Option<NovaultError>,
 }
 
 /// Decrypts the user's private key using password-derived key
@@ -22,91 +28,174 @@ pub struct DecryptedPrivateKey {
 /// 
 /// # Returns
 /// DecryptedPrivateKey containing the decrypted private key or error message
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub fn decrypt_private_key(
     password: &str,
     salt: &str,
     encrypted_key: &[u8],
     nonce: &[u8],
 ) -> DecryptedPrivateKey {
-    log("Starting private key decryption...");
+    match decrypt_private_key_bytes(password, salt, encrypted_key, nonce) {
+        Ok(private_key) => DecryptedPrivateKey {
+            success: true,
+            private_key,
+            error: None,
+        },
+        Err(e) => DecryptedPrivateKey {
+            success: false,
+            private_key: SecretBytes::default(),
+            error: Some(e),
+        },
+    }
+}
+
+/// Same as [`decrypt_private_key`], returning a typed error
+pub(crate) fn decrypt_private_key_bytes(
+    password: &str,
+    salt: &str,
+    encrypted_key: &[u8],
+    nonce: &[u8],
+) -> Result<SecretBytes, NovaultError> {
+    log_debug!("Starting private key decryption...");
+    check_sealed_key(encrypted_key, nonce)?;
+
+    // Derive the encryption key from password and salt (includes the registered pepper)
+    log_debug!("Deriving encryption key from password...");
+    let encryption_key = get_key_encryption_key(password, salt)?;
+
+    open_key(&encryption_key, encrypted_key, nonce)
+}
+
+/// Decrypts the private key and, if given, the signing key
+///
+/// The KEK is derived once for both. The signing key and its nonce must be
+/// given together.
+///
+/// # Returns
+/// The X25519 private key and the Ed25519 signing seed, if one was given
+pub(crate) fn unlock_keys(
+    password: &str,
+    salt: &str,
+    encrypted_private_key: &[u8],
+    nonce: &[u8],
+    encrypted_signing_key: Option<&[u8]>,
+    signing_nonce: Option<&[u8]>,
+) -> Result<(SecretKey, Option<SecretKey>), NovaultError> {
+    open_keys_with(encrypted_private_key, nonce, encrypted_signing_key, signing_nonce, || {
+        log_debug!("[unlock_keys] Deriving encryption key from password...");
+        get_key_encryption_key(password, salt)
+    })
+}
 
+/// Same as [`unlock_keys`], with the KEK from `encryption_key`
+///
+/// The lengths are checked before `encryption_key` is called, so malformed
+/// input fails without running an expensive KDF.
+pub(crate) fn open_keys_with(
+    encrypted_private_key: &[u8],
+    nonce: &[u8],
+    encrypted_signing_key: Option<&[u8]>,
+    signing_nonce: Option<&[u8]>,
+    encryption_key: impl FnOnce() -> Result<SecretKey, NovaultError>,
+) -> Result<(SecretKey, Option<SecretKey>), NovaultError> {
+    check_sealed_key(encrypted_private_key, nonce)?;
+    let signing = match (encrypted_signing_key, signing_nonce) {
+        (Some(encrypted_signing_key), Some(signing_nonce)) => {
+            check_sealed_key(encrypted_signing_key, signing_nonce)?;
+            Some((encrypted_signing_key, signing_nonce))
+        }
+        (None, None) => None,
+        _ => {
+            return Err(NovaultError::InvalidInput(
+                "The signing key and its nonce must be given together".to_string(),
+            ));
+        }
+    };
+
+    let encryption_key = encryption_key()?;
+    let private_key = open_key(&encryption_key, encrypted_private_key, nonce)?;
+    let private_key = SecretKey::from_slice(private_key.expose(), "Private Key")?;
+    let signing_key = match signing {
+        Some((encrypted_signing_key, signing_nonce)) => {
+            let seed = open_key(&encryption_key, encrypted_signing_key, signing_nonce)?;
+            Some(SecretKey::from_slice(seed.expose(), "Signing key")?)
+        }
+        None => None,
+    };
+    Ok((private_key, signing_key))
+}
+
+/// Validates the lengths of a KEK-encrypted key and its nonce
+///
+/// Call before deriving the KEK, so malformed input fails without running Argon2.
+pub(crate) fn check_sealed_key(encrypted_key: &[u8], nonce: &[u8]) -> Result<(), NovaultError> {
     // Validate nonce length
-    if nonce.len() != 12 {
-        log(&format!("Invalid nonce length: {}", nonce.len()));
-        return DecryptedPrivateKey {
-            success: false,
-            private_key: vec![],
-            error_message: format!("Nonce must be 12 bytes, got {}", nonce.len()),
-        };
+    if let Err(e) = check_len(12, nonce, "Nonce") {
+        log_warn!("Invalid nonce length: {}", nonce.len());
+        return Err(e);
     }
 
     // The encrypted_key should be 48 bytes (32 bytes key + 16 bytes auth tag)
-    if encrypted_key.len() != 48 {
-        log(&format!("Invalid encrypted key length: {}", encrypted_key.len()));
-        return DecryptedPrivateKey {
-            success: false,
-            private_key: vec![],
-            error_message: format!("Encrypted key must be 48 bytes, got {}", encrypted_key.len()),
-        };
+    if let Err(e) = check_len(48, encrypted_key, "Encrypted key") {
+        log_warn!("Invalid encrypted key length: {}", encrypted_key.len());
+        return Err(e);
     }
+    Ok(())
+}
 
-    // Derive the encryption key from password and salt (includes paminta internally)
-    log("Deriving encryption key from password...");
-    let encryption_key = get_key_encryption_key(password, salt);
-
+/// Decrypts a key sealed with [`crate::masterkey_generator::seal_key`]
+///
+/// The lengths must already have been checked with [`check_sealed_key`].
+pub(crate) fn open_key(encryption_key: &SecretKey, encrypted_key: &[u8], nonce: &[u8]) -> Result<SecretBytes, NovaultError> {
     // Create the cipher
-    let key = GenericArray::from_slice(&encryption_key);
+    let key = GenericArray::from_slice(encryption_key.expose());
     let cipher = Aes256Gcm::new(key);
     let nonce = Nonce::from_slice(nonce);
 
     // Decrypt the private key
-    log("Attempting decryption...");
+    log_debug!("Attempting decryption...");
     match cipher.decrypt(nonce, encrypted_key) {
         Ok(decrypted) => {
-            log("Decryption successful!");
+            log_info!("Decryption successful!");
             // Private key is intentionally not logged for security
-            DecryptedPrivateKey {
-                success: true,
-                private_key: decrypted,
-                error_message: String::new(),
-            }
+            Ok(SecretBytes::from(decrypted))
         }
         Err(_) => {
-            log("Decryption failed - invalid password or corrupted data");
-            DecryptedPrivateKey {
-                success: false,
-                private_key: vec![],
-                error_message: "Decryption failed. Please check your password.".to_string(),
-            }
+            log_warn!("Decryption failed - invalid password or corrupted data");
+            Err(NovaultError::WrongPassword)
         }
     }
 }
 
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 impl DecryptedPrivateKey {
-    #[wasm_bindgen(getter)]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
     pub fn success(&self) -> bool {
         self.success
     }
 
-    #[wasm_bindgen(getter)]
-    pub fn private_key(&self) -> Vec<u8> {
-        self.private_key.clone()
+    /// The X25519 public key matching the decrypted private key
+    ///
+    /// The private key itself is not exposed to JavaScript; compare this
+    /// against the stored public key to verify the password.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn public_key_hex(&self) -> String {
+        let Ok(bytes) = <[u8; 32]>::try_from(self.private_key.expose()) else {
+            return String::new();
+        };
+        let secret = StaticSecret::from(bytes);
+        bytes_to_hex(PublicKey::from(&secret).as_bytes())
     }
 
-    #[wasm_bindgen(getter)]
-    pub fn private_key_hex(&self) -> String {
-        if self.success {
-            bytes_to_hex(&self.private_key)
-        } else {
-            String::new()
-        }
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn error_message(&self) -> String {
+        self.error.as_ref().map(ToString::to_string).unwrap_or_default()
     }
 
-    #[wasm_bindgen(getter)]
-    pub fn error_message(&self) -> String {
-        self.error_message.clone()
+    /// Stable code of the failure (see [`crate::error::ErrorCode`]), or an empty string on success
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn error_code(&self) -> String {
+        self.error.as_ref().map(|e| e.code().as_str().to_string()).unwrap_or_default()
     }
 }
\ No newline at end of file
@@ -1,10 +1,20 @@
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 use aes_gcm::{
     Aes256Gcm, aead::{Aead, KeyInit, OsRng, generic_array::GenericArray}
 };
-use x25519_dalek::{PublicKey, StaticSecret};
+use x25519_dalek::StaticSecret;
 use argon2::{password_hash::SaltString};
-pub use crate::{generate_nonce, get_key_encryption_key, bytes_to_hex, alert, log};
+pub use crate::{generate_nonce, get_key_encryption_key, bytes_to_hex};
+use crate::error::NovaultError;
+use crate::kdf::{derive_key, encode_salt, kdf_params_of, params_for_new_key, KdfParams};
+use crate::fingerprint::{fingerprint_public_key, fingerprint_signing_key, Fingerprint};
+use crate::key_wrap::{key_id, public_key_of};
+use crate::masterkey_decryptor::unlock_keys;
+use crate::pepper::{current_pepper_version, LEGACY_PEPPER_VERSION};
+use crate::secret::SecretKey;
+use crate::signing::{existing_or_new_signing_key, generate_signing_key, signing_public_key_of};
+use crate::logger::{log_debug, log_info};
 
 
 /// Encrypts a master key using AES-256-GCM
@@ -15,81 +25,349 @@ pub use crate::{generate_nonce, get_key_encryption_key, bytes_to_hex, alert, log
 /// 
 /// # Returns
 /// A struct containing the nonce, authentication tag, and encrypted master key
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone)]
 pub struct EncryptedMasterKey {
     nonce: Vec<u8>,
     salt: String,
     encrypted_private_key: Vec<u8>,
     public_key: Vec<u8>,
+    signing_nonce: Vec<u8>,
+    encrypted_signing_key: Vec<u8>,
+    signing_public_key: Vec<u8>,
+    signing_key_created: bool,
 }
 
 
-#[wasm_bindgen]
-pub fn encrypt_master_key(input: &str) -> EncryptedMasterKey {
+/// Generates the user's key pairs and encrypts them under a password-derived KEK
+///
+/// # Arguments
+/// * `input` - The new master password
+/// * `params` - Argon2id parameters, e.g. from [`crate::kdf::calibrate_kdf`];
+///   defaults to [`KdfParams::DEFAULT`]. Must not be weaker than
+///   [`KdfParams::MINIMUM`]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn encrypt_master_key(input: &str, params: Option<KdfParams>) -> Result<EncryptedMasterKey, NovaultError> {
+    let params = params_for_new_key(params)?;
+
+    let private_key = generate_private_key();
+    log_info!("Generated X25519 key pair");
+
+    let signing_key = generate_signing_key();
+    log_info!("Generated Ed25519 signing key");
+
+    seal_master_key(input, &params, &private_key, &signing_key, true)
+}
+
+/// Generates a random X25519 private key
+pub(crate) fn generate_private_key() -> SecretKey {
+    SecretKey::new(StaticSecret::random_from_rng(OsRng).to_bytes())
+}
+
+/// Re-encrypts the user's private keys under a new master password
+///
+/// The X25519 key pair is kept, so every file encrypted to the old public key
+/// stays readable. The keys are decrypted with the old KEK and sealed again
+/// under a fresh salt, KEK and nonces. The signing key is carried over the
+/// same way. A master key created before signing keys existed has none; only
+/// then pass `create_signing_key`, and check `signing_key_created` on the
+/// result, since signatures under any earlier signing key stop verifying.
+///
+/// The new KEK keeps the stored KDF parameters, raised to at least
+/// [`KdfParams::MINIMUM`], and uses the newest registered pepper.
+///
+/// # Arguments
+/// * `old_password` - The current master password
+/// * `old_salt` - Salt the current KEK was derived with
+/// * `encrypted_private_key` - The encrypted private key bytes (48 bytes)
+/// * `nonce` - The nonce used for private key encryption (12 bytes)
+/// * `new_password` - The new master password
+/// * `encrypted_signing_key` - The encrypted Ed25519 signing key (48 bytes), if any
+/// * `signing_nonce` - The nonce used for signing key encryption (12 bytes)
+/// * `create_signing_key` - Generate a signing key if none is given, instead
+///   of failing with `INVALID_INPUT`
+///
+/// # Returns
+/// The new encrypted master key, with an unchanged public key
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[allow(clippy::too_many_arguments)]
+pub fn rewrap_private_key(
+    old_password: &str,
+    old_salt: &str,
+    encrypted_private_key: &[u8],
+    nonce: &[u8],
+    new_password: &str,
+    encrypted_signing_key: Option<Vec<u8>>,
+    signing_nonce: Option<Vec<u8>>,
+    create_signing_key: bool,
+) -> Result<EncryptedMasterKey, NovaultError> {
+    reseal_master_key(
+        old_password,
+        old_salt,
+        encrypted_private_key,
+        nonce,
+        new_password,
+        encrypted_signing_key,
+        signing_nonce,
+        create_signing_key,
+        KdfParams::MINIMUM,
+    )
+}
+
+/// Re-encrypts the user's private keys with stronger KDF parameters
+///
+/// Call after a successful unlock when [`crate::kdf::needs_kdf_upgrade`]
+/// returns true, and save the result in place of the old fields. The password
+/// and public key stay the same. The stored parameters are never lowered,
+/// and the key moves to the newest registered pepper.
+///
+/// # Arguments
+/// Same as [`rewrap_private_key`] without the new password, plus
+/// * `params` - The parameters to raise to, e.g. from
+///   [`crate::kdf::calibrate_kdf`]; defaults to [`KdfParams::DEFAULT`]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[allow(clippy::too_many_arguments)]
+pub fn upgrade_kdf(
+    password: &str,
+    salt: &str,
+    encrypted_private_key: &[u8],
+    nonce: &[u8],
+    encrypted_signing_key: Option<Vec<u8>>,
+    signing_nonce: Option<Vec<u8>>,
+    create_signing_key: bool,
+    params: Option<KdfParams>,
+) -> Result<EncryptedMasterKey, NovaultError> {
+    reseal_master_key(
+        password,
+        salt,
+        encrypted_private_key,
+        nonce,
+        password,
+        encrypted_signing_key,
+        signing_nonce,
+        create_signing_key,
+        params.unwrap_or(KdfParams::DEFAULT).max(KdfParams::MINIMUM),
+    )
+}
+
+/// Opens the private keys with the old password and seals them under the new
+/// one, with the stored KDF parameters raised to at least `floor`
+#[allow(clippy::too_many_arguments)]
+fn reseal_master_key(
+    old_password: &str,
+    old_salt: &str,
+    encrypted_private_key: &[u8],
+    nonce: &[u8],
+    new_password: &str,
+    encrypted_signing_key: Option<Vec<u8>>,
+    signing_nonce: Option<Vec<u8>>,
+    create_signing_key: bool,
+    floor: KdfParams,
+) -> Result<EncryptedMasterKey, NovaultError> {
+    let params = kdf_params_of(old_salt)?.max(floor);
+    let (private_key, signing_key) = unlock_keys(
+        old_password,
+        old_salt,
+        encrypted_private_key,
+        nonce,
+        encrypted_signing_key.as_deref(),
+        signing_nonce.as_deref(),
+    )?;
+    log_debug!("[reseal_master_key] Old KEK opened the private key");
+    let (signing_key, signing_key_created) = existing_or_new_signing_key(signing_key, create_signing_key)?;
+
+    let key = seal_master_key(new_password, &params, &private_key, &signing_key, signing_key_created)?;
+    log_info!("[reseal_master_key] Private key re-encrypted");
+    Ok(key)
+}
+
+/// Encrypts both private keys under a KEK derived from a fresh salt and the
+/// newest registered pepper
+///
+/// `signing_key_created` records whether `signing_key` was just generated.
+pub(crate) fn seal_master_key(
+    password: &str,
+    params: &KdfParams,
+    private_key: &SecretKey,
+    signing_key: &SecretKey,
+    signing_key_created: bool,
+) -> Result<EncryptedMasterKey, NovaultError> {
+    let pepper_version = current_pepper_version().ok_or(NovaultError::PepperUnavailable(LEGACY_PEPPER_VERSION))?;
     let salt = SaltString::generate(&mut OsRng);
     // Generate the data encryption key from input
-    let encryption_key = get_key_encryption_key(input, salt.as_str());
-    log(&bytes_to_hex(&encryption_key));
+    let encryption_key = derive_key(params, pepper_version, password, salt.as_str())?;
+    log_debug!("[seal_master_key] Key encryption key derived");
 
-    let secret = StaticSecret::random_from_rng(OsRng);
-    let public = PublicKey::from(&secret);
-    log("Generated X25519 key pair");
+    // Encrypt both private keys under the same KEK, each with its own nonce
+    let (nonce, ciphertext) = seal_key(&encryption_key, private_key);
+    let (signing_nonce, encrypted_signing_key) = seal_key(&encryption_key, signing_key);
+
+    Ok(EncryptedMasterKey {
+        encrypted_private_key: ciphertext,
+        public_key: public_key_of(private_key).to_vec(),
+        nonce,
+        salt: encode_salt(params, pepper_version, salt.as_str())?,
+        signing_nonce,
+        encrypted_signing_key,
+        signing_public_key: signing_public_key_of(signing_key).to_vec(),
+        signing_key_created,
+    })
+}
 
-    let key = GenericArray::from_slice(&encryption_key);
+/// Encrypts a 32-byte private key under the KEK with a fresh nonce
+///
+/// # Returns
+/// The nonce and the encrypted key (48 bytes: 32 key + 16 auth tag)
+pub(crate) fn seal_key(encryption_key: &SecretKey, private_key: &SecretKey) -> (Vec<u8>, Vec<u8>) {
+    let key = GenericArray::from_slice(encryption_key.expose());
     let cipher = Aes256Gcm::new(key);
     let nonce = generate_nonce();
 
-    log(&bytes_to_hex(&nonce));
+    log_debug!("[seal_key] Nonce: {}", bytes_to_hex(&nonce));
 
-    // Encrypt the private key
     let ciphertext = cipher
-        .encrypt(&nonce, secret.to_bytes().as_ref())
+        .encrypt(&nonce, private_key.expose().as_ref())
         .expect("Failed to encrypt master key");
-    
-    EncryptedMasterKey {
-        encrypted_private_key: ciphertext,
-        public_key: public.to_bytes().to_vec(),
-        nonce: nonce.to_vec(),
-        salt: salt.as_str().to_string(),
-
-    }
+    (nonce.to_vec(), ciphertext)
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 impl EncryptedMasterKey {
-    #[wasm_bindgen(getter)]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
     pub fn nonce(&self) -> Vec<u8> {
         self.nonce.clone()
     }
 
-    #[wasm_bindgen(getter)]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
     pub fn encrypted_private_key(&self) -> Vec<u8> {
         self.encrypted_private_key.clone()
     }
 
-    #[wasm_bindgen(getter)]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
     pub fn public_key(&self) -> Vec<u8> {
         self.public_key.clone()
     }
 
-    #[wasm_bindgen(getter)]
+    /// The salt and KDF parameters, as a PHC string (see [`crate::kdf`]);
+    /// store it as-is in `pk_salt`
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
     pub fn salt(&self) -> String {
         self.salt.clone()
     }
 
-    #[wasm_bindgen(getter)]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
     pub fn nonce_hex(&self) -> String {
         bytes_to_hex(&self.nonce)
     }
 
-    #[wasm_bindgen(getter)]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
     pub fn encrypted_private_key_hex(&self) -> String {
         bytes_to_hex(&self.encrypted_private_key)
     }
 
-    #[wasm_bindgen(getter)]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
     pub fn public_key_hex(&self) -> String {
         bytes_to_hex(&self.public_key)
     }
 
-}
\ No newline at end of file
+    /// Nonce used to encrypt the Ed25519 signing key
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn signing_nonce(&self) -> Vec<u8> {
+        self.signing_nonce.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn signing_nonce_hex(&self) -> String {
+        bytes_to_hex(&self.signing_nonce)
+    }
+
+    /// The Ed25519 signing seed, encrypted under the same KEK as the private key
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn encrypted_signing_key(&self) -> Vec<u8> {
+        self.encrypted_signing_key.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn encrypted_signing_key_hex(&self) -> String {
+        bytes_to_hex(&self.encrypted_signing_key)
+    }
+
+    /// The Ed25519 public key that verifies this user's signatures
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn signing_public_key(&self) -> Vec<u8> {
+        self.signing_public_key.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn signing_public_key_hex(&self) -> String {
+        bytes_to_hex(&self.signing_public_key)
+    }
+
+    /// Whether the signing key was generated by this call rather than carried
+    /// over; if so, store the new signing public key and re-sign the device list
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn signing_key_created(&self) -> bool {
+        self.signing_key_created
+    }
+
+    /// Key ID of the public key, as recorded with each DEK wrapped to it
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn key_id(&self) -> Vec<u8> {
+        key_id(&self.public_key)
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn key_id_hex(&self) -> String {
+        bytes_to_hex(&self.key_id())
+    }
+
+    /// Fingerprint of the public key, for users to compare out of band
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn fingerprint(&self) -> Result<Fingerprint, NovaultError> {
+        fingerprint_public_key(&self.public_key)
+    }
+
+    /// Fingerprint of the signing public key
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn signing_fingerprint(&self) -> Result<Fingerprint, NovaultError> {
+        fingerprint_signing_key(&self.signing_public_key)
+    }
+
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pepper::register_pepper;
+
+    #[test]
+    fn password_changes_keep_the_signing_key() {
+        register_pepper(1, b"masterkey-test-pepper").unwrap();
+        let key = encrypt_master_key("old password", Some(KdfParams::MINIMUM)).unwrap();
+        assert!(key.signing_key_created());
+        let rewrap = |signing: Option<(Vec<u8>, Vec<u8>)>, create_signing_key| {
+            let (encrypted_signing_key, signing_nonce) = signing.unzip();
+            rewrap_private_key(
+                "old password",
+                &key.salt(),
+                &key.encrypted_private_key(),
+                &key.nonce(),
+                "new password",
+                encrypted_signing_key,
+                signing_nonce,
+                create_signing_key,
+            )
+        };
+
+        let kept = rewrap(Some((key.encrypted_signing_key(), key.signing_nonce())), false).unwrap();
+        assert_eq!(kept.public_key(), key.public_key());
+        assert_eq!(kept.signing_public_key(), key.signing_public_key());
+        assert!(!kept.signing_key_created());
+
+        assert_eq!(rewrap(None, false).err().unwrap().code().as_str(), "INVALID_INPUT");
+
+        let created = rewrap(None, true).unwrap();
+        assert_eq!(created.public_key(), key.public_key());
+        assert_ne!(created.signing_public_key(), key.signing_public_key());
+        assert!(created.signing_key_created());
+    }
+}
@@ -0,0 +1,136 @@
+//! Server-supplied, versioned pepper.
+//!
+//! The pepper used to be compiled into the crate, which put it in the public
+//! WASM binary. It is now registered at runtime, typically right after login
+//! with values fetched from the server:
+//!
+//! ```javascript
+//! for (const { version, secret } of await getPeppers()) {
+//!   wasm.register_pepper(version, HexToUint8Array(secret));
+//! }
+//! ```
+//!
+//! Several versions can be registered at once, so keys derived under an old
+//! pepper keep unlocking while users are moved to the new one. New KEKs always
+//! use the highest registered version, and [`crate::kdf::needs_kdf_upgrade`]
+//! flags keys derived under an older one.
+//!
+//! Version 0 is the pepper that used to be compiled in. It is appended to the
+//! password, as before; later versions are passed to Argon2id as its secret
+//! key, and the version is recorded in the stored salt (see [`crate::kdf`]).
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+use std::collections::BTreeMap;
+#[cfg(test)]
+use std::cell::RefCell;
+#[cfg(not(test))]
+use std::sync::Mutex;
+
+use crate::error::NovaultError;
+use crate::secret::SecretBytes;
+use crate::logger::log_info;
+
+/// The version of the pepper that used to be compiled into the crate
+pub const LEGACY_PEPPER_VERSION: u32 = 0;
+
+#[cfg(not(test))]
+static PEPPERS: Mutex<BTreeMap<u32, SecretBytes>> = Mutex::new(BTreeMap::new());
+
+// Unit tests each run on their own thread; a registry per thread keeps
+// parallel tests from seeing each other's peppers
+#[cfg(test)]
+thread_local! {
+    static PEPPERS: RefCell<BTreeMap<u32, SecretBytes>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+/// Registers a pepper version, replacing any earlier value for it
+///
+/// # Arguments
+/// * `version` - The pepper version ID
+/// * `secret` - The pepper bytes
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn register_pepper(version: u32, secret: &[u8]) -> Result<(), NovaultError> {
+    if secret.is_empty() {
+        return Err(NovaultError::InvalidInput("Pepper must not be empty".to_string()));
+    }
+    with_peppers(|peppers| peppers.insert(version, SecretBytes::from(secret.to_vec())));
+    log_info!("[register_pepper] Registered pepper version {}", version);
+    Ok(())
+}
+
+/// Forgets every registered pepper, e.g. on logout
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn clear_peppers() {
+    with_peppers(BTreeMap::clear);
+}
+
+/// The version new KEKs are derived with: the highest registered one
+///
+/// # Returns
+/// The version, or `undefined` if no pepper is registered
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn current_pepper_version() -> Option<u32> {
+    with_peppers(|peppers| peppers.keys().next_back().copied())
+}
+
+/// The pepper registered for `version`
+pub(crate) fn pepper(version: u32) -> Result<SecretBytes, NovaultError> {
+    with_peppers(|peppers| peppers.get(&version).cloned()).ok_or(NovaultError::PepperUnavailable(version))
+}
+
+#[cfg(not(test))]
+fn with_peppers<R>(f: impl FnOnce(&mut BTreeMap<u32, SecretBytes>) -> R) -> R {
+    // The map is never left half-updated, so a poisoned lock is still usable
+    f(&mut PEPPERS.lock().unwrap_or_else(|e| e.into_inner()))
+}
+
+#[cfg(test)]
+fn with_peppers<R>(f: impl FnOnce(&mut BTreeMap<u32, SecretBytes>) -> R) -> R {
+    PEPPERS.with(|peppers| f(&mut peppers.borrow_mut()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kdf::{derive_key, KdfParams};
+
+    #[test]
+    fn versions_are_kept_apart() {
+        assert_eq!(current_pepper_version(), None);
+        register_pepper(LEGACY_PEPPER_VERSION, b"old pepper").unwrap();
+        register_pepper(7, b"newest pepper").unwrap();
+        register_pepper(3, b"middle pepper").unwrap();
+        assert_eq!(current_pepper_version(), Some(7));
+        assert_eq!(pepper(3).unwrap().expose(), b"middle pepper");
+        assert_eq!(pepper(LEGACY_PEPPER_VERSION).unwrap().expose(), b"old pepper");
+        assert_eq!(register_pepper(8, b"").unwrap_err().code().as_str(), "INVALID_INPUT");
+
+        register_pepper(3, b"replaced").unwrap();
+        assert_eq!(pepper(3).unwrap().expose(), b"replaced");
+        assert_eq!(current_pepper_version(), Some(7));
+    }
+
+    #[test]
+    fn unknown_versions_cannot_derive_keys() {
+        register_pepper(1, b"pepper one").unwrap();
+        let one = derive_key(&KdfParams::MINIMUM, 1, "pw", "salt-salt-salt").unwrap();
+        register_pepper(2, b"pepper two").unwrap();
+        let two = derive_key(&KdfParams::MINIMUM, 2, "pw", "salt-salt-salt").unwrap();
+        assert_ne!(one.expose(), two.expose());
+        assert_eq!(
+            derive_key(&KdfParams::MINIMUM, 5, "pw", "salt-salt-salt").unwrap_err().code().as_str(),
+            "PEPPER_UNAVAILABLE"
+        );
+    }
+
+    #[test]
+    fn clearing_forgets_every_version() {
+        register_pepper(0, b"pepper zero").unwrap();
+        register_pepper(4, b"pepper four").unwrap();
+        clear_peppers();
+        assert_eq!(current_pepper_version(), None);
+        assert_eq!(pepper(0).unwrap_err().code().as_str(), "PEPPER_UNAVAILABLE");
+        assert_eq!(pepper(4).unwrap_err().code().as_str(), "PEPPER_UNAVAILABLE");
+    }
+}
@@ -0,0 +1,362 @@
+//! Random-access decryption of chunked containers.
+//!
+//! Because every chunk of a [`crate::stream`] container except the last holds
+//! exactly `chunk_size` bytes of plaintext, the chunks covering any plaintext
+//! byte range can be computed from the header and the container length alone.
+//! This allows fetching only those chunks (e.g. with an HTTP `Range` request)
+//! and decrypting them without touching the rest of the object.
+//!
+//! A range read never sees the whole ciphertext, so it cannot check a
+//! sender tag and reports no sender; use [`crate::stream::StreamDecryptor`]
+//! to verify who sent a stream.
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::container::ContainerHeader;
+use crate::decrypt_file::unlock_private_key;
+use crate::error::NovaultError;
+use crate::key_wrap::unwrap_dek_for_recipient;
+use crate::secret::SecretKey;
+use crate::stream::{ChunkCipher, TAG_LEN};
+use crate::logger::{log_debug, log_warn};
+
+/// Chunk layout of a chunked container, derived from its header and length
+#[derive(Clone, Copy, Debug)]
+struct ChunkLayout {
+    header_len: u64,
+    chunk_size: u64,
+    chunk_count: u64,
+    final_sealed_len: u64,
+    plaintext_len: u64,
+}
+
+impl ChunkLayout {
+    fn new(header: &ContainerHeader, header_len: usize, container_len: u64) -> Result<Self, NovaultError> {
+        let chunk_size = header.chunk_size.ok_or_else(|| {
+            NovaultError::InvalidInput("Container is not chunked; range reads are not supported".to_string())
+        })? as u64;
+        let header_len = header_len as u64;
+        let tag_len = TAG_LEN as u64;
+
+        let body_len = container_len
+            .checked_sub(header_len + header.sender_tags_len() as u64)
+            .ok_or_else(|| NovaultError::Truncated("Container length is smaller than its header".to_string()))?;
+        let sealed_size = chunk_size + tag_len;
+        let full_chunks = body_len / sealed_size;
+        let final_sealed_len = body_len % sealed_size;
+        if final_sealed_len < tag_len {
+            return Err(NovaultError::Truncated("Container is truncated: final chunk is missing".to_string()));
+        }
+
+        Ok(ChunkLayout {
+            header_len,
+            chunk_size,
+            chunk_count: full_chunks + 1,
+            final_sealed_len,
+            plaintext_len: full_chunks * chunk_size + (final_sealed_len - tag_len),
+        })
+    }
+
+    fn sealed_len(&self, index: u64) -> u64 {
+        if index == self.chunk_count - 1 {
+            self.final_sealed_len
+        } else {
+            self.chunk_size + TAG_LEN as u64
+        }
+    }
+
+    fn chunk_offset(&self, index: u64) -> u64 {
+        self.header_len + index * (self.chunk_size + TAG_LEN as u64)
+    }
+}
+
+/// Which part of a container to fetch for a plaintext byte range
+///
+/// Offsets are absolute positions within the container, so `fetch_start` and
+/// `fetch_end` can be used directly for an HTTP `Range: bytes=start-(end-1)`
+/// request.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RangePlan {
+    first_chunk: u64,
+    last_chunk: u64,
+    fetch_start: u64,
+    fetch_end: u64,
+    skip: u64,
+    length: u64,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl RangePlan {
+    /// Index of the first chunk covering the range
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn first_chunk(&self) -> u64 {
+        self.first_chunk
+    }
+
+    /// Index of the last chunk covering the range
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn last_chunk(&self) -> u64 {
+        self.last_chunk
+    }
+
+    /// Container offset of the first byte to fetch
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn fetch_start(&self) -> u64 {
+        self.fetch_start
+    }
+
+    /// Container offset one past the last byte to fetch
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn fetch_end(&self) -> u64 {
+        self.fetch_end
+    }
+
+    /// Number of plaintext bytes the range will yield
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+}
+
+/// Computes which ciphertext chunks cover a plaintext byte range
+///
+/// Does not need any key material, so it can run before the user unlocks
+/// their vault.
+///
+/// # Arguments
+/// * `header` - The start of the container; must contain at least the full header
+/// * `container_len` - Total size of the stored container in bytes
+/// * `start` - First plaintext byte of the range
+/// * `end` - One past the last plaintext byte of the range; clamped to the plaintext length
+///
+/// # Returns
+/// A RangePlan describing the container bytes to fetch
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn plan_range(header: &[u8], container_len: u64, start: u64, end: u64) -> Result<RangePlan, NovaultError> {
+    let (parsed, header_len) = ContainerHeader::parse(header)?;
+    let layout = ChunkLayout::new(&parsed, header_len, container_len)?;
+    plan(&layout, start, end)
+}
+
+fn plan(layout: &ChunkLayout, start: u64, end: u64) -> Result<RangePlan, NovaultError> {
+    let end = end.min(layout.plaintext_len);
+    if start >= end {
+        return Err(NovaultError::InvalidInput(format!(
+            "Range {}..{} is empty or outside the plaintext ({} bytes)",
+            start, end, layout.plaintext_len
+        )));
+    }
+
+    let first_chunk = start / layout.chunk_size;
+    let last_chunk = (end - 1) / layout.chunk_size;
+
+    Ok(RangePlan {
+        first_chunk,
+        last_chunk,
+        fetch_start: layout.chunk_offset(first_chunk),
+        fetch_end: layout.chunk_offset(last_chunk) + layout.sealed_len(last_chunk),
+        skip: start - first_chunk * layout.chunk_size,
+        length: end - start,
+    })
+}
+
+/// Decrypts byte ranges of a chunked container
+///
+/// The DEK is unwrapped once when the reader is created, so any number of
+/// ranges can then be decrypted without re-deriving the password key.
+///
+/// # Example (JavaScript)
+///
+/// ```javascript
+/// const reader = new wasm.RangeDecryptor(headerBytes, BigInt(size), password, salt, epk, pkNonce);
+/// const plan = reader.plan(BigInt(start), BigInt(end));
+/// const res = await fetch(url, {
+///   headers: { Range: `bytes=${plan.fetch_start}-${plan.fetch_end - 1n}` },
+/// });
+/// const bytes = reader.decrypt(plan, new Uint8Array(await res.arrayBuffer()));
+/// ```
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct RangeDecryptor {
+    cipher: ChunkCipher,
+    layout: ChunkLayout,
+}
+
+impl RangeDecryptor {
+    /// Opens a chunked container with an already decrypted private key
+    pub(crate) fn with_private_key(
+        header: &[u8],
+        container_len: u64,
+        private_key: &SecretKey,
+    ) -> Result<RangeDecryptor, NovaultError> {
+        let (parsed, header_len) = ContainerHeader::parse(header)?;
+        let layout = ChunkLayout::new(&parsed, header_len, container_len)?;
+        log_debug!(
+            "[RangeDecryptor] Opened container: {} chunks, {} plaintext bytes",
+            layout.chunk_count, layout.plaintext_len
+        );
+
+        let (dek, _) = unwrap_dek_for_recipient(private_key, &parsed.recipients, &parsed.context())?;
+
+        Ok(RangeDecryptor {
+            cipher: ChunkCipher::new(&dek, &parsed.file_nonce)?,
+            layout,
+        })
+    }
+
+    /// Recomputes a plan from the range it covers and checks it matches this container's layout
+    fn check_plan(&self, given: &RangePlan) -> Result<RangePlan, NovaultError> {
+        let foreign = || NovaultError::InvalidInput("Plan does not belong to this container".to_string());
+        let start = given
+            .first_chunk
+            .checked_mul(self.layout.chunk_size)
+            .and_then(|offset| offset.checked_add(given.skip))
+            .ok_or_else(foreign)?;
+        let end = start.checked_add(given.length).ok_or_else(foreign)?;
+        match plan(&self.layout, start, end) {
+            Ok(expected) if expected == *given => Ok(expected),
+            _ => {
+                log_warn!("[RangeDecryptor] Rejected a plan for another container");
+                Err(foreign())
+            }
+        }
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl RangeDecryptor {
+    /// Opens a chunked container for random-access decryption
+    ///
+    /// # Arguments
+    /// * `header` - The start of the container; must contain at least the full header
+    /// * `container_len` - Total size of the stored container in bytes
+    /// * `password` - The user's master password
+    /// * `pk_salt` - Salt used for deriving the key encryption key
+    /// * `encrypted_private_key` - The encrypted private key bytes (48 bytes)
+    /// * `pk_nonce` - The nonce used for private key encryption (12 bytes)
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(
+        header: &[u8],
+        container_len: u64,
+        password: &str,
+        pk_salt: &str,
+        encrypted_private_key: &[u8],
+        pk_nonce: &[u8],
+    ) -> Result<RangeDecryptor, NovaultError> {
+        let private_key = unlock_private_key(password, pk_salt, encrypted_private_key, pk_nonce)?;
+        RangeDecryptor::with_private_key(header, container_len, &private_key)
+    }
+
+    /// Total plaintext size of the container
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn plaintext_length(&self) -> u64 {
+        self.layout.plaintext_len
+    }
+
+    /// Computes which ciphertext chunks cover a plaintext byte range
+    ///
+    /// Same as [`plan_range`] for the container this reader was opened with.
+    pub fn plan(&self, start: u64, end: u64) -> Result<RangePlan, NovaultError> {
+        plan(&self.layout, start, end)
+    }
+
+    /// Decrypts the bytes fetched for a plan and returns exactly the requested range
+    ///
+    /// # Arguments
+    /// * `plan` - A plan produced for this container
+    /// * `ciphertext` - The container bytes from `fetch_start` to `fetch_end`
+    ///
+    /// # Errors
+    /// Returns `InvalidInput` if the plan was made for another container
+    pub fn decrypt(&self, plan: &RangePlan, ciphertext: &[u8]) -> Result<Vec<u8>, NovaultError> {
+        let plan = &self.check_plan(plan)?;
+        let expected = plan.fetch_end - plan.fetch_start;
+        if ciphertext.len() as u64 != expected {
+            return Err(NovaultError::InvalidInput(format!(
+                "Expected {} ciphertext bytes, got {}",
+                expected,
+                ciphertext.len()
+            )));
+        }
+
+        let mut plaintext = Vec::with_capacity(((plan.last_chunk - plan.first_chunk + 1) * self.layout.chunk_size) as usize);
+        let mut offset = 0usize;
+        for index in plan.first_chunk..=plan.last_chunk {
+            let sealed_len = self.layout.sealed_len(index) as usize;
+            let chunk = &ciphertext[offset..offset + sealed_len];
+            let last = index == self.layout.chunk_count - 1;
+            let chunk_index = u32::try_from(index)
+                .map_err(|_| NovaultError::InvalidInput("Chunk index out of range".to_string()))?;
+            plaintext.extend(self.cipher.decrypt_chunk(chunk_index, last, chunk)?);
+            offset += sealed_len;
+        }
+
+        let skip = plan.skip as usize;
+        Ok(plaintext[skip..skip + plan.length as usize].to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_digest::file_digest_key;
+    use crate::key_wrap::{generate_dek, public_key_of, KeyWrapScheme};
+    use crate::stream::StreamEncryptor;
+
+    /// A chunked container of `len` bytes with 8-byte chunks, and its reader
+    fn reader(len: u8) -> (Vec<u8>, RangeDecryptor) {
+        let recipient = generate_dek();
+        let mut encryptor = StreamEncryptor::with_sender(
+            &public_key_of(&recipient),
+            Some(8),
+            KeyWrapScheme::HpkeBase,
+            None,
+            &file_digest_key(&recipient),
+        )
+        .unwrap();
+        let mut container = encryptor.header();
+        container.extend(encryptor.push(&(0..len).collect::<Vec<u8>>()).unwrap());
+        container.extend(encryptor.finish().unwrap());
+        let reader = RangeDecryptor::with_private_key(&container, container.len() as u64, &recipient).unwrap();
+        (container, reader)
+    }
+
+    fn fetch<'a>(container: &'a [u8], plan: &RangePlan) -> &'a [u8] {
+        &container[plan.fetch_start as usize..plan.fetch_end as usize]
+    }
+
+    #[test]
+    fn decrypts_any_range() {
+        let (container, reader) = reader(30);
+        for (start, end) in [(0, 30), (0, 1), (7, 9), (8, 16), (29, 30), (3, 100)] {
+            let plan = reader.plan(start, end).unwrap();
+            let expected: Vec<u8> = (start as u8..end.min(30) as u8).collect();
+            assert_eq!(reader.decrypt(&plan, fetch(&container, &plan)).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn rejects_plans_for_other_containers() {
+        let (big_container, big) = reader(60);
+        let (container, small) = reader(20);
+        let foreign = big.plan(10, 50).unwrap();
+        let ciphertext = &big_container[..(foreign.fetch_end - foreign.fetch_start) as usize];
+        assert_eq!(small.decrypt(&foreign, ciphertext).unwrap_err().code().as_str(), "INVALID_INPUT");
+
+        // Edited plans whose chunks exist but whose offsets or lengths do not add up
+        let plan = small.plan(2, 12).unwrap();
+        for edited in [
+            RangePlan { length: 100, ..plan },
+            RangePlan { skip: 100, ..plan },
+            RangePlan { skip: u64::MAX, ..plan },
+            RangePlan { fetch_end: plan.fetch_end + 40, ..plan },
+            RangePlan { fetch_start: plan.fetch_end + 1, ..plan },
+            RangePlan { first_chunk: u64::MAX, ..plan },
+        ] {
+            let fetched = &container[..container.len().min(edited.fetch_end.wrapping_sub(edited.fetch_start) as usize)];
+            assert_eq!(small.decrypt(&edited, fetched).unwrap_err().code().as_str(), "INVALID_INPUT", "{:?}", edited);
+        }
+        assert_eq!(small.decrypt(&plan, fetch(&container, &plan)).unwrap(), (2..12).collect::<Vec<u8>>());
+    }
+}
@@ -0,0 +1,312 @@
+//! Account recovery with a 24-word BIP39 phrase.
+//!
+//! Without a second copy of the private key, a forgotten master password
+//! loses every file. A recovery key is 256 bits of fresh entropy, shown to the
+//! user once as a 24-word English BIP39 phrase. HKDF-SHA256 turns the entropy
+//! into a second KEK, which seals the same X25519 private key and Ed25519
+//! signing key the password KEK does. The entropy is high enough that no
+//! password-hardening KDF is needed.
+//!
+//! The sealed keys are stored next to the password-sealed ones; the phrase
+//! is never stored.
+//!
+//! ```javascript
+//! const kit = vault.create_recovery_key();
+//! showOnce(kit.mnemonic);
+//! await saveRecoveryKey(kit.encrypted_private_key_hex, kit.nonce_hex, ...);
+//!
+//! // Later, after the password is forgotten:
+//! const key = wasm.recover_master_key(phrase, encryptedPrivateKey, nonce, esk, signingNonce, newPassword, false);
+//! ```
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+use aes_gcm::aead::{OsRng, rand_core::RngCore};
+use bip39::Mnemonic;
+use hkdf::Hkdf;
+use sha2::Sha256;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::bytes_to_hex;
+use crate::error::NovaultError;
+use crate::kdf::{params_for_new_key, KdfParams};
+use crate::key_wrap::public_key_of;
+use crate::masterkey_decryptor::open_keys_with;
+use crate::masterkey_generator::{seal_key, seal_master_key, EncryptedMasterKey};
+use crate::secret::SecretKey;
+use crate::signing::existing_or_new_signing_key;
+use crate::logger::{log_debug, log_info};
+
+/// Number of words in a recovery phrase
+pub const RECOVERY_WORDS: usize = 24;
+
+/// HKDF info label for the recovery KEK
+const RECOVERY_KEK_INFO: &[u8] = b"novault/recovery-kek/v1";
+
+/// A recovery phrase and the keys it seals
+///
+/// Show [`RecoveryKey::mnemonic`] to the user once and store the rest.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct RecoveryKey {
+    mnemonic: Zeroizing<String>,
+    nonce: Vec<u8>,
+    encrypted_private_key: Vec<u8>,
+    public_key: Vec<u8>,
+    signing_nonce: Vec<u8>,
+    encrypted_signing_key: Vec<u8>,
+}
+
+/// Generates a recovery phrase and seals the keys under it
+///
+/// Without a signing key, the signing fields are left empty.
+pub(crate) fn create_recovery_key(
+    private_key: &SecretKey,
+    signing_key: Option<&SecretKey>,
+) -> Result<RecoveryKey, NovaultError> {
+    let mut entropy = SecretKey::new([0u8; 32]);
+    OsRng.fill_bytes(entropy.expose_mut());
+    let mnemonic = Mnemonic::from_entropy(entropy.expose())
+        .map_err(|e| NovaultError::InvalidInput(format!("Cannot encode recovery phrase: {}", e)))?;
+
+    let encryption_key = recovery_kek(&entropy);
+    let (nonce, encrypted_private_key) = seal_key(&encryption_key, private_key);
+    let (signing_nonce, encrypted_signing_key) = signing_key
+        .map(|signing_key| seal_key(&encryption_key, signing_key))
+        .unwrap_or_default();
+    log_info!("[create_recovery_key] Recovery key created");
+
+    Ok(RecoveryKey {
+        mnemonic: Zeroizing::new(mnemonic.to_string()),
+        nonce,
+        encrypted_private_key,
+        public_key: public_key_of(private_key).to_vec(),
+        signing_nonce,
+        encrypted_signing_key,
+    })
+}
+
+/// Restores the user's keys from the recovery phrase under a new password
+///
+/// The key pair is unchanged, so every file stays readable. The recovery key
+/// itself stays valid; create a new one if the phrase may have been exposed.
+///
+/// # Arguments
+/// * `recovery_phrase` - The 24 words; case and extra whitespace are ignored
+/// * `encrypted_private_key` - The recovery-sealed private key (48 bytes)
+/// * `nonce` - Its nonce (12 bytes)
+/// * `encrypted_signing_key` - The recovery-sealed signing key (48 bytes), if any
+/// * `signing_nonce` - Its nonce (12 bytes)
+/// * `new_password` - The new master password
+/// * `create_signing_key` - Generate a signing key if the recovery key holds
+///   none, instead of failing with `INVALID_INPUT`; see `rewrap_private_key`
+/// * `params` - Argon2id parameters for the new KEK; defaults to [`KdfParams::DEFAULT`]
+///
+/// # Returns
+/// The keys sealed under the new password, as from `encrypt_master_key`.
+/// Fails with `INVALID_INPUT` for a malformed phrase and `WRONG_PASSWORD` for
+/// a well-formed phrase that does not open the keys.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[allow(clippy::too_many_arguments)]
+pub fn recover_master_key(
+    recovery_phrase: &str,
+    encrypted_private_key: &[u8],
+    nonce: &[u8],
+    encrypted_signing_key: Option<Vec<u8>>,
+    signing_nonce: Option<Vec<u8>>,
+    new_password: &str,
+    create_signing_key: bool,
+    params: Option<KdfParams>,
+) -> Result<EncryptedMasterKey, NovaultError> {
+    let params = params_for_new_key(params)?;
+    let (private_key, signing_key) = open_keys_with(
+        encrypted_private_key,
+        nonce,
+        encrypted_signing_key.as_deref(),
+        signing_nonce.as_deref(),
+        || Ok(recovery_kek(&parse_recovery_phrase(recovery_phrase)?)),
+    )?;
+    log_debug!("[recover_master_key] Recovery phrase opened the private key");
+    let (signing_key, signing_key_created) = existing_or_new_signing_key(signing_key, create_signing_key)?;
+
+    let key = seal_master_key(new_password, &params, &private_key, &signing_key, signing_key_created)?;
+    log_info!("[recover_master_key] Private key re-encrypted under the new password");
+    Ok(key)
+}
+
+/// Decodes a recovery phrase back to its entropy
+fn parse_recovery_phrase(phrase: &str) -> Result<SecretKey, NovaultError> {
+    let normalized = Zeroizing::new(
+        phrase
+            .split_whitespace()
+            .map(str::to_lowercase)
+            .collect::<Vec<_>>()
+            .join(" "),
+    );
+    let mnemonic = Mnemonic::parse_normalized(&normalized)
+        .map_err(|e| NovaultError::InvalidInput(format!("Invalid recovery phrase: {}", e)))?;
+    if mnemonic.word_count() != RECOVERY_WORDS {
+        return Err(NovaultError::InvalidInput(format!(
+            "Recovery phrase must have {} words, got {}",
+            RECOVERY_WORDS,
+            mnemonic.word_count()
+        )));
+    }
+
+    let (mut entropy, len) = mnemonic.to_entropy_array();
+    let key = SecretKey::from_slice(&entropy[..len], "Recovery entropy");
+    entropy.zeroize();
+    key
+}
+
+/// Derives the KEK that seals the keys from the recovery entropy
+fn recovery_kek(entropy: &SecretKey) -> SecretKey {
+    let mut key = SecretKey::new([0u8; 32]);
+    Hkdf::<Sha256>::new(None, entropy.expose())
+        .expand(RECOVERY_KEK_INFO, key.expose_mut())
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    key
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl RecoveryKey {
+    /// The 24-word recovery phrase; show it once and never store it
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn mnemonic(&self) -> String {
+        self.mnemonic.to_string()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn nonce(&self) -> Vec<u8> {
+        self.nonce.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn nonce_hex(&self) -> String {
+        bytes_to_hex(&self.nonce)
+    }
+
+    /// The private key, sealed under the recovery KEK
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn encrypted_private_key(&self) -> Vec<u8> {
+        self.encrypted_private_key.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn encrypted_private_key_hex(&self) -> String {
+        bytes_to_hex(&self.encrypted_private_key)
+    }
+
+    /// The X25519 public key of the sealed private key
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn public_key(&self) -> Vec<u8> {
+        self.public_key.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn public_key_hex(&self) -> String {
+        bytes_to_hex(&self.public_key)
+    }
+
+    /// Empty if the vault had no signing key
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn signing_nonce(&self) -> Vec<u8> {
+        self.signing_nonce.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn signing_nonce_hex(&self) -> String {
+        bytes_to_hex(&self.signing_nonce)
+    }
+
+    /// The signing key, sealed under the recovery KEK; empty if the vault had none
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn encrypted_signing_key(&self) -> Vec<u8> {
+        self.encrypted_signing_key.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn encrypted_signing_key_hex(&self) -> String {
+        bytes_to_hex(&self.encrypted_signing_key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::masterkey_decryptor::unlock_keys;
+    use crate::masterkey_generator::generate_private_key;
+    use crate::pepper::register_pepper;
+    use crate::signing::generate_signing_key;
+
+    /// The BIP39 test vector for all-zero entropy
+    const ZERO_PHRASE: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon \
+        abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon art";
+
+    fn recover(kit: &RecoveryKey, phrase: &str, create_signing_key: bool) -> Result<EncryptedMasterKey, NovaultError> {
+        let signing = |field: Vec<u8>| Some(field).filter(|field| !field.is_empty());
+        recover_master_key(
+            phrase,
+            &kit.encrypted_private_key,
+            &kit.nonce,
+            signing(kit.encrypted_signing_key.clone()),
+            signing(kit.signing_nonce.clone()),
+            "new password",
+            create_signing_key,
+            Some(KdfParams::MINIMUM),
+        )
+    }
+
+    #[test]
+    fn recovered_keys_unlock_with_the_new_password() {
+        register_pepper(1, b"recovery-test-pepper").unwrap();
+        let (private_key, signing_key) = (generate_private_key(), generate_signing_key());
+        let kit = create_recovery_key(&private_key, Some(&signing_key)).unwrap();
+        assert_eq!(kit.mnemonic().split(' ').count(), RECOVERY_WORDS);
+
+        // Case and spacing are ignored
+        let phrase = format!("  {}\n", kit.mnemonic().to_uppercase().replace(' ', "  "));
+        let key = recover(&kit, &phrase, false).unwrap();
+        assert!(!key.signing_key_created());
+        let (unlocked, unlocked_signing_key) = unlock_keys(
+            "new password",
+            &key.salt(),
+            &key.encrypted_private_key(),
+            &key.nonce(),
+            Some(&key.encrypted_signing_key()),
+            Some(&key.signing_nonce()),
+        )
+        .unwrap();
+        assert_eq!(unlocked.expose(), private_key.expose());
+        assert_eq!(unlocked_signing_key.unwrap().expose(), signing_key.expose());
+    }
+
+    #[test]
+    fn keys_without_a_signing_key_need_an_opt_in() {
+        register_pepper(1, b"recovery-test-pepper").unwrap();
+        let kit = create_recovery_key(&generate_private_key(), None).unwrap();
+        let mnemonic = kit.mnemonic();
+        assert_eq!(recover(&kit, &mnemonic, false).err().unwrap().code().as_str(), "INVALID_INPUT");
+        assert!(recover(&kit, &mnemonic, true).unwrap().signing_key_created());
+    }
+
+    #[test]
+    fn malformed_phrases_are_rejected() {
+        assert_eq!(parse_recovery_phrase(ZERO_PHRASE).unwrap().expose(), &[0u8; 32]);
+
+        let bad_checksum = ZERO_PHRASE.replace(" art", " abandon");
+        let twelve_words = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        let unknown_word = ZERO_PHRASE.replace(" art", " novault");
+        let missing_word = ZERO_PHRASE.replacen("abandon ", "", 1);
+        for phrase in [bad_checksum.as_str(), twelve_words, unknown_word.as_str(), missing_word.as_str(), ""] {
+            let err = parse_recovery_phrase(phrase).err().unwrap();
+            assert_eq!(err.code().as_str(), "INVALID_INPUT", "{:?}", phrase);
+        }
+        assert!(parse_recovery_phrase(twelve_words).err().unwrap().to_string().contains("must have 24 words"));
+    }
+
+    #[test]
+    fn another_phrase_does_not_open_the_keys() {
+        let kit = create_recovery_key(&generate_private_key(), None).unwrap();
+        assert_eq!(recover(&kit, ZERO_PHRASE, true).err().unwrap().code().as_str(), "WRONG_PASSWORD");
+    }
+}
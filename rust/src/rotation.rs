@@ -0,0 +1,454 @@
+//! Rotation of the user's X25519 key pair.
+//!
+//! If the private key may have leaked, the user moves to a new key pair. The
+//! old private keys are kept in a keyring, encrypted to the new key, so files
+//! still addressed to them stay readable; the vault picks the key for each
+//! file by its key ID.
+//!
+//! ```javascript
+//! vault.load_keyring(storedKeyring);              // if the user has rotated before
+//! const rotated = vault.rotate_key(newPassword, false);
+//! await saveSecrets(rotated.master_key, rotated.keyring);
+//!
+//! const progress = saved ? wasm.RotationProgress.from_bytes(saved) : new wasm.RotationProgress(deks.length);
+//! for (const dek of deks.slice(progress.processed)) {
+//!   const rewrapped = vault.rotate_wrapped_dek(epk, encryptedDek, dekNonce, scheme, keyId, progress);
+//!   if (rewrapped) await saveDek(dek.id, rewrapped);
+//!   localStorage.setItem("rotation", progress.to_bytes());
+//! }
+//! ```
+//!
+//! Re-wrapping only touches the wrapped DEKs, never the file payloads, and
+//! is idempotent: an entry already addressed to the current key is left
+//! alone. An interrupted rotation can therefore resume from the saved
+//! [`RotationProgress`], or simply start over.
+//!
+//! Sender-authenticated entries are re-wrapped in HPKE Base mode, since the
+//! original sender's key is not available; such files no longer report a
+//! sender. Raw ECDH entries are re-wrapped with HKDF. Device grants, guardian shares and the recovery key still hold the
+//! old key and have to be created again after a rotation.
+//!
+//! File digests are keyed by the private key too (see [`crate::file_digest`]);
+//! refresh them with `migrate_file_digest` while re-wrapping.
+//!
+//! The keyring is a container (see [`crate::container`]) encrypted to the
+//! current key, with the current key as sender. Its plaintext is:
+//!
+//! ```text
+//! version (1) | key count (u16, big-endian) | private keys (32 each, oldest first)
+//! ```
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+use zeroize::Zeroizing;
+
+use crate::container::{ByteReader, ContainerHeader};
+use crate::decrypt_file::{decrypt_with_private_key, read_container};
+use crate::encrypt_file::encrypt_payload;
+use crate::error::NovaultError;
+use crate::key_wrap::{key_id, public_key_of, unwrap_dek, wrap_dek, KeyWrapScheme, WrappedDek};
+use crate::masterkey_generator::EncryptedMasterKey;
+use crate::secret::SecretKey;
+use crate::logger::{log_debug, log_info, log_warn};
+
+/// Version byte of the keyring format
+pub const KEYRING_VERSION: u8 = 1;
+
+/// Version byte of [`RotationProgress::to_bytes`]
+const PROGRESS_VERSION: u8 = 1;
+
+/// The result of a key rotation; store both fields in place of the old ones
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct RotatedKey {
+    master_key: EncryptedMasterKey,
+    keyring: Vec<u8>,
+}
+
+/// How far re-wrapping a user's DEKs has got
+///
+/// Persist it with [`RotationProgress::to_bytes`] after each saved entry to
+/// resume an interrupted rotation.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug, Default)]
+pub struct RotationProgress {
+    total: u32,
+    processed: u32,
+    rewrapped: u32,
+    unchanged: u32,
+    failed: u32,
+}
+
+impl RotatedKey {
+    pub(crate) fn new(master_key: EncryptedMasterKey, keyring: Vec<u8>) -> Self {
+        RotatedKey { master_key, keyring }
+    }
+}
+
+/// Encrypts the previous private keys to the current key
+pub(crate) fn seal_keyring(current: &SecretKey, previous: &[SecretKey]) -> Result<Vec<u8>, NovaultError> {
+    if previous.len() > u16::MAX as usize {
+        return Err(NovaultError::InvalidState("Too many previous keys".to_string()));
+    }
+    let mut plaintext = Zeroizing::new(Vec::with_capacity(3 + previous.len() * 32));
+    plaintext.push(KEYRING_VERSION);
+    plaintext.extend_from_slice(&(previous.len() as u16).to_be_bytes());
+    for key in previous {
+        plaintext.extend_from_slice(key.expose());
+    }
+    let current_public_key = public_key_of(current);
+    encrypt_payload(&plaintext, &current_public_key, KeyWrapScheme::HpkeAuth, Some(current)).into_container()
+}
+
+/// Decrypts a keyring sealed to the current key by [`seal_keyring`]
+///
+/// # Returns
+/// The previous private keys, oldest first
+pub(crate) fn open_keyring(current: &SecretKey, keyring: &[u8]) -> Result<Vec<SecretKey>, NovaultError> {
+    let (header, ciphertext, sender_tags) = read_container(keyring)?;
+    let (plaintext, sender_key_id) = decrypt_with_private_key(ciphertext, current, &header, sender_tags).into_data()?;
+    let plaintext = Zeroizing::new(plaintext);
+    if sender_key_id != key_id(&public_key_of(current)) {
+        log_warn!("[open_keyring] Keyring was not sealed by this key");
+        return Err(NovaultError::InvalidInput("Keyring was not sealed by this key".to_string()));
+    }
+
+    let mut reader = ByteReader::new(&plaintext);
+    let version = reader.u8("Keyring version")?;
+    if version != KEYRING_VERSION {
+        return Err(NovaultError::InvalidInput(format!("Unsupported keyring version: {}", version)));
+    }
+    let count = reader.u16("Keyring key count")?;
+    let keys = (0..count)
+        .map(|_| SecretKey::from_slice(reader.take(32, "Keyring key")?, "Previous private key"))
+        .collect::<Result<Vec<_>, _>>()?;
+    if !reader.is_empty() {
+        return Err(NovaultError::InvalidInput("Trailing bytes after keyring".to_string()));
+    }
+    log_debug!("[open_keyring] Opened {} previous keys", keys.len());
+    Ok(keys)
+}
+
+/// Re-wraps a DEK addressed to a previous key for the current key
+///
+/// `context` is the container context the entry was wrapped with (see
+/// [`crate::key_wrap::wrap_dek`]), or empty for loose fields.
+///
+/// # Returns
+/// The new entry, or `None` if the entry is already addressed to the current key
+pub(crate) fn rotate_entry(
+    current: &SecretKey,
+    previous: &[SecretKey],
+    entry: &WrappedDek,
+    context: &[u8],
+) -> Result<Option<WrappedDek>, NovaultError> {
+    let current_public_key = public_key_of(current);
+    let current_key_id = key_id(&current_public_key);
+    if entry.key_id == current_key_id || (entry.key_id.is_empty() && unwrap_dek(current, entry, context).is_ok()) {
+        return Ok(None);
+    }
+
+    let mut last_error = NovaultError::NotRecipient;
+    for key in previous.iter().rev() {
+        if !entry.key_id.is_empty() && entry.key_id != key_id(&public_key_of(key)) {
+            continue;
+        }
+        match unwrap_dek(key, entry, context) {
+            Ok(dek) => return wrap_dek(&dek, &current_public_key, entry.scheme.for_rewrap(), None, &[]).map(Some),
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+/// Re-wraps the current user's entries in a container header
+///
+/// Entries for other recipients are left as they are.
+///
+/// # Returns
+/// The new header followed by the rest of `data`, or `None` if no entry changed
+pub(crate) fn rotate_container_header(
+    current: &SecretKey,
+    previous: &[SecretKey],
+    data: &[u8],
+) -> Result<Option<Vec<u8>>, NovaultError> {
+    let (mut header, header_len) = ContainerHeader::parse(data)?;
+    let own_key_ids: Vec<Vec<u8>> = std::iter::once(current)
+        .chain(previous)
+        .map(|key| key_id(&public_key_of(key)))
+        .collect();
+
+    let context = header.context();
+    let mut addressed = false;
+    let mut changed = false;
+    for entry in header.recipients.iter_mut() {
+        if !entry.key_id.is_empty() && !own_key_ids.contains(&entry.key_id) {
+            continue;
+        }
+        match rotate_entry(current, previous, entry, &context) {
+            Ok(Some(rewrapped)) => {
+                *entry = rewrapped;
+                addressed = true;
+                changed = true;
+            }
+            Ok(None) => addressed = true,
+            // An entry without a key ID may belong to another recipient
+            Err(_) if entry.key_id.is_empty() => {}
+            Err(e) => return Err(e),
+        }
+    }
+    if !addressed {
+        return Err(NovaultError::NotRecipient);
+    }
+    if !changed {
+        return Ok(None);
+    }
+
+    let mut out = header.to_bytes();
+    out.extend_from_slice(&data[header_len..]);
+    Ok(Some(out))
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl RotatedKey {
+    /// The new key pair, sealed under the new password
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn master_key(&self) -> EncryptedMasterKey {
+        self.master_key.clone()
+    }
+
+    /// The previous private keys, encrypted to the new key
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn keyring(&self) -> Vec<u8> {
+        self.keyring.clone()
+    }
+}
+
+impl RotationProgress {
+    /// Counts the outcome of re-wrapping one entry
+    pub(crate) fn record<T>(&mut self, result: &Result<Option<T>, NovaultError>) {
+        self.processed += 1;
+        match result {
+            Ok(Some(_)) => self.rewrapped += 1,
+            Ok(None) => self.unchanged += 1,
+            Err(e) => {
+                log_warn!("[RotationProgress] Entry {} failed: {}", self.processed, e);
+                self.failed += 1;
+            }
+        }
+        if self.processed == self.total {
+            log_info!(
+                "[RotationProgress] Rotation finished: {} re-wrapped, {} unchanged, {} failed",
+                self.rewrapped, self.unchanged, self.failed
+            );
+        }
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl RotationProgress {
+    /// Starts tracking a rotation over `total` entries
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(total: u32) -> RotationProgress {
+        RotationProgress { total, ..RotationProgress::default() }
+    }
+
+    /// Restores progress saved with [`RotationProgress::to_bytes`]
+    pub fn from_bytes(data: &[u8]) -> Result<RotationProgress, NovaultError> {
+        let mut reader = ByteReader::new(data);
+        let version = reader.u8("Progress version")?;
+        if version != PROGRESS_VERSION {
+            return Err(NovaultError::InvalidInput(format!("Unsupported progress version: {}", version)));
+        }
+        let progress = RotationProgress {
+            total: reader.u32("Total")?,
+            processed: reader.u32("Processed")?,
+            rewrapped: reader.u32("Re-wrapped")?,
+            unchanged: reader.u32("Unchanged")?,
+            failed: reader.u32("Failed")?,
+        };
+        if !reader.is_empty() {
+            return Err(NovaultError::InvalidInput("Trailing bytes after progress".to_string()));
+        }
+        Ok(progress)
+    }
+
+    /// Serializes the progress, to be saved after each stored entry
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(1 + 5 * 4);
+        out.push(PROGRESS_VERSION);
+        for count in [self.total, self.processed, self.rewrapped, self.unchanged, self.failed] {
+            out.extend_from_slice(&count.to_be_bytes());
+        }
+        out
+    }
+
+    /// Number of entries to re-wrap
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn total(&self) -> u32 {
+        self.total
+    }
+
+    /// Entries handled so far; resume at this index
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn processed(&self) -> u32 {
+        self.processed
+    }
+
+    /// Entries moved from a previous key to the current one
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn rewrapped(&self) -> u32 {
+        self.rewrapped
+    }
+
+    /// Entries already addressed to the current key
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn unchanged(&self) -> u32 {
+        self.unchanged
+    }
+
+    /// Entries that no unlocked key could open
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn failed(&self) -> u32 {
+        self.failed
+    }
+
+    /// Whether every entry has been handled
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn is_complete(&self) -> bool {
+        self.processed >= self.total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kdf::KdfParams;
+    use crate::key_wrap::{generate_dek, legacy_raw_ecdh_entry};
+    use crate::masterkey_generator::{encrypt_master_key, generate_private_key};
+    use crate::pepper::register_pepper;
+    use crate::vault::UnlockedVault;
+
+    fn unlock(password: &str, key: &EncryptedMasterKey) -> UnlockedVault {
+        UnlockedVault::new(
+            password,
+            &key.salt(),
+            &key.encrypted_private_key(),
+            &key.nonce(),
+            Some(key.encrypted_signing_key()),
+            Some(key.signing_nonce()),
+        )
+        .unwrap()
+    }
+
+    fn decrypt(key: &SecretKey, container: &[u8]) -> Result<Vec<u8>, NovaultError> {
+        let (header, ciphertext, sender_tags) = read_container(container)?;
+        Ok(decrypt_with_private_key(ciphertext, key, &header, sender_tags).into_data()?.0)
+    }
+
+    #[test]
+    fn progress_survives_a_round_trip() {
+        let mut progress = RotationProgress::new(4);
+        progress.record(&Ok(Some(())));
+        progress.record::<()>(&Ok(None));
+        progress.record::<()>(&Err(NovaultError::NotRecipient));
+        assert!(!progress.is_complete());
+
+        let mut restored = RotationProgress::from_bytes(&progress.to_bytes()).unwrap();
+        assert_eq!(
+            [restored.total(), restored.processed(), restored.rewrapped(), restored.unchanged(), restored.failed()],
+            [4, 3, 1, 1, 1]
+        );
+        restored.record(&Ok(Some(())));
+        assert!(restored.is_complete());
+
+        let bytes = progress.to_bytes();
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        let mut wrong_version = bytes.clone();
+        wrong_version[0] = PROGRESS_VERSION + 1;
+        for data in [&bytes[..bytes.len() - 1], &trailing, &wrong_version, &[]] {
+            assert!(RotationProgress::from_bytes(data).is_err(), "{:?}", data);
+        }
+    }
+
+    #[test]
+    fn keyrings_open_only_with_the_current_key() {
+        let current = generate_private_key();
+        let previous = [generate_private_key(), generate_private_key()];
+        let keyring = seal_keyring(&current, &previous).unwrap();
+
+        let opened = open_keyring(&current, &keyring).unwrap();
+        assert_eq!(opened.len(), 2);
+        for (opened, key) in opened.iter().zip(&previous) {
+            assert_eq!(opened.expose(), key.expose());
+        }
+        assert!(open_keyring(&current, &seal_keyring(&current, &[]).unwrap()).unwrap().is_empty());
+        assert_eq!(open_keyring(&previous[1], &keyring).err().unwrap().code().as_str(), "NOT_RECIPIENT");
+
+        // Addressed to the current key, but sealed by someone else
+        let forged = encrypt_payload(&[KEYRING_VERSION, 0, 0], &public_key_of(&current), KeyWrapScheme::HpkeAuth, Some(&previous[0]))
+            .into_container()
+            .unwrap();
+        assert_eq!(open_keyring(&current, &forged).err().unwrap().code().as_str(), "INVALID_INPUT");
+    }
+
+    #[test]
+    fn entries_are_only_rotated_once() {
+        let (old, new, dek) = (generate_private_key(), generate_private_key(), generate_dek());
+        let entry = wrap_dek(&dek, &public_key_of(&old), KeyWrapScheme::EcdhHkdf, None, &[]).unwrap();
+        let previous = std::slice::from_ref(&old);
+
+        let rotated = rotate_entry(&new, previous, &entry, &[]).unwrap().unwrap();
+        assert_eq!(rotated.key_id, key_id(&public_key_of(&new)));
+        assert_eq!(unwrap_dek(&new, &rotated, &[]).unwrap().expose(), dek.expose());
+        assert!(rotate_entry(&new, previous, &rotated, &[]).unwrap().is_none());
+
+        // A key not in the keyring cannot rotate the entry
+        let err = rotate_entry(&new, &[generate_private_key()], &entry, &[]).err().unwrap();
+        assert_eq!(err.code().as_str(), "NOT_RECIPIENT");
+    }
+
+    #[test]
+    fn rotated_headers_keep_other_recipients() {
+        register_pepper(1, b"rotation-test-pepper").unwrap();
+        let master_key = encrypt_master_key("old password", Some(KdfParams::MINIMUM)).unwrap();
+        let mut vault = unlock("old password", &master_key);
+        let other = generate_private_key();
+        let recipients = [master_key.public_key(), public_key_of(&other).to_vec()].concat();
+        let container = vault.encrypt_file(b"payload", &recipients).into_container().unwrap();
+
+        let rotated = vault.rotate_key("new password", false, Some(KdfParams::MINIMUM)).unwrap();
+        let mut vault = unlock("new password", &rotated.master_key());
+        assert_eq!(vault.load_keyring(&rotated.keyring()).unwrap(), 1);
+        let mut progress = RotationProgress::new(2);
+        let rotated_container = vault.rotate_container_header(&container, &mut progress).unwrap().unwrap();
+        assert!(vault.rotate_container_header(&rotated_container, &mut progress).unwrap().is_none());
+        assert_eq!([progress.rewrapped(), progress.unchanged()], [1, 1]);
+
+        let (before, before_len) = ContainerHeader::parse(&container).unwrap();
+        let (after, after_len) = ContainerHeader::parse(&rotated_container).unwrap();
+        assert_eq!(container[before_len..], rotated_container[after_len..]);
+        assert_eq!(after.recipients[0].key_id, key_id(&rotated.master_key().public_key()));
+        let (kept, original) = (&after.recipients[1], &before.recipients[1]);
+        assert_eq!(
+            (&kept.key_id, &kept.ephemeral_public_key, &kept.dek_nonce, &kept.encrypted_dek),
+            (&original.key_id, &original.ephemeral_public_key, &original.dek_nonce, &original.encrypted_dek)
+        );
+
+        // The new key opens the file without the keyring, and the other recipient still can
+        let vault = unlock("new password", &rotated.master_key());
+        assert_eq!(vault.decrypt_file_container(&rotated_container).into_data().unwrap().0, b"payload");
+        assert_eq!(decrypt(&other, &rotated_container).unwrap(), b"payload");
+    }
+
+    #[test]
+    fn raw_ecdh_entries_are_rotated_to_hkdf() {
+        let (old, new, dek) = (generate_private_key(), generate_private_key(), generate_dek());
+        let legacy = legacy_raw_ecdh_entry(&dek, &public_key_of(&old));
+
+        let rotated = rotate_entry(&new, std::slice::from_ref(&old), &legacy, &[]).unwrap().unwrap();
+        assert_eq!(rotated.scheme, KeyWrapScheme::EcdhHkdf);
+        assert_eq!(unwrap_dek(&new, &rotated, &[]).unwrap().expose(), dek.expose());
+        assert!(rotate_entry(&new, std::slice::from_ref(&old), &rotated, &[]).unwrap().is_none());
+    }
+}
@@ -0,0 +1,158 @@
+//! Secret-holding types that wipe their memory when dropped.
+//!
+//! Every KEK, DEK, shared secret and decrypted private key in the crate is
+//! kept in one of these types so it is zeroed as soon as it goes out of scope,
+//! instead of lingering in WASM linear memory until the allocator reuses it.
+//! Call `zeroize()` to wipe one early. Their `Debug` output never includes
+//! the key bytes.
+
+use std::fmt;
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+use crate::error::NovaultError;
+
+/// A 32-byte key (KEK, DEK or X25519 private key) that is zeroed on drop
+#[derive(Clone)]
+pub struct SecretKey([u8; 32]);
+
+impl SecretKey {
+    /// Takes ownership of raw key bytes
+    pub fn new(bytes: [u8; 32]) -> Self {
+        SecretKey(bytes)
+    }
+
+    /// Copies a key out of a slice
+    ///
+    /// # Errors
+    /// Returns an error naming `name` if `bytes` is not exactly 32 bytes long
+    pub fn from_slice(bytes: &[u8], name: &'static str) -> Result<Self, NovaultError> {
+        let array: [u8; 32] = bytes.try_into().map_err(|_| NovaultError::InvalidLength {
+            name,
+            expected: 32,
+            actual: bytes.len(),
+        })?;
+        Ok(SecretKey(array))
+    }
+
+    /// Borrows the key bytes
+    pub fn expose(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    pub(crate) fn expose_mut(&mut self) -> &mut [u8; 32] {
+        &mut self.0
+    }
+}
+
+impl Zeroize for SecretKey {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl Drop for SecretKey {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl ZeroizeOnDrop for SecretKey {}
+
+impl fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretKey([REDACTED])")
+    }
+}
+
+/// Variable-length secret bytes (e.g. a password combined with the pepper) that are zeroed on drop
+#[derive(Clone, Default)]
+pub struct SecretBytes(Vec<u8>);
+
+impl SecretBytes {
+    /// Allocates an empty buffer with room for `capacity` bytes
+    ///
+    /// Reserve the full size up front: growing the buffer would leave an
+    /// unwiped copy of its old contents behind.
+    pub fn with_capacity(capacity: usize) -> Self {
+        SecretBytes(Vec::with_capacity(capacity))
+    }
+
+    /// Appends bytes; must not exceed the reserved capacity
+    pub fn extend_from_slice(&mut self, bytes: &[u8]) {
+        debug_assert!(self.0.len() + bytes.len() <= self.0.capacity());
+        self.0.extend_from_slice(bytes);
+    }
+
+    /// Borrows the secret bytes
+    pub fn expose(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<Vec<u8>> for SecretBytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        SecretBytes(bytes)
+    }
+}
+
+/// Wipes the whole buffer, including spare capacity, and empties it
+impl Zeroize for SecretBytes {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl Drop for SecretBytes {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl ZeroizeOnDrop for SecretBytes {}
+
+impl fmt::Debug for SecretBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SecretBytes([REDACTED; {}])", self.0.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Wiping on drop is checked against the allocator in tests/zeroize.rs
+
+    #[test]
+    fn secret_key_is_wiped_on_zeroize() {
+        let mut key = SecretKey::from_slice(&[0xA5; 32], "Key").unwrap();
+        let copy = key.clone();
+        key.zeroize();
+        assert_eq!(key.expose(), &[0u8; 32]);
+        assert_eq!(copy.expose(), &[0xA5; 32]);
+    }
+
+    #[test]
+    fn secret_bytes_are_wiped_on_zeroize() {
+        let mut bytes = SecretBytes::from(vec![0xA5; 48]);
+        let (ptr, capacity) = (bytes.0.as_ptr(), bytes.0.capacity());
+        bytes.zeroize();
+        assert!(bytes.is_empty());
+        // SAFETY: the buffer is still allocated; zeroize keeps the capacity
+        let buffer = unsafe { std::slice::from_raw_parts(ptr, capacity) };
+        assert!(buffer.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn debug_output_hides_secrets() {
+        assert_eq!(format!("{:?}", SecretKey::new([0xA5; 32])), "SecretKey([REDACTED])");
+        assert_eq!(format!("{:?}", SecretBytes::from(vec![0xA5; 3])), "SecretBytes([REDACTED; 3])");
+    }
+}
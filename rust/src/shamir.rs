@@ -0,0 +1,431 @@
+//! Social recovery: the user's keys split among guardians with Shamir sharing.
+//!
+//! The X25519 private key and the Ed25519 signing seed are split byte by byte
+//! over GF(256) into one share per guardian, any `threshold` of which rebuild
+//! them. Fewer shares reveal nothing about the keys. Each share is encrypted to
+//! its guardian as a sender-authenticated container, exactly like
+//! [`crate::vault::UnlockedVault::encrypt_file`], so the guardian's app can
+//! check that it came from the user.
+//!
+//! ```javascript
+//! const shares = vault.split_among_guardians(guardianPublicKeys, 3);
+//! for (const share of shares) {
+//!   await saveGuardianShare(share.guardian_key_id_hex, share.container);
+//! }
+//!
+//! // Each guardian opens their share and hands the plaintext back:
+//! const share = guardianVault.decrypt_file_container(container).decrypted_data;
+//!
+//! // With enough shares collected:
+//! const key = wasm.combine_guardian_shares(concatenatedShares, publicKey, newPassword, false);
+//! ```
+//!
+//! A share (the plaintext inside the container) is [`SHARE_LEN`] bytes:
+//!
+//! ```text
+//! version (1) | flags (1) | threshold (1) | x (1) | owner key ID (8)
+//! | signing public key (32) | y (64)
+//! ```
+//!
+//! `y` shares the private key followed by the signing seed. Bit 0 of `flags`
+//! is set if the vault had a signing key; otherwise the seed half and the
+//! signing public key are zero.
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+use aes_gcm::aead::{OsRng, rand_core::RngCore};
+use zeroize::Zeroizing;
+
+use crate::bytes_to_hex;
+use crate::encrypt_file::encrypt_payload;
+use crate::error::NovaultError;
+use crate::kdf::{params_for_new_key, KdfParams};
+use crate::key_wrap::{key_id, public_key_of, KeyWrapScheme, KEY_ID_LEN};
+use crate::masterkey_generator::{seal_master_key, EncryptedMasterKey};
+use crate::secret::SecretKey;
+use crate::signing::{existing_or_new_signing_key, signing_public_key_of};
+use crate::logger::{log_debug, log_info};
+
+/// Version byte of the share format
+pub const SHARE_VERSION: u8 = 1;
+
+/// Size of a decrypted share in bytes
+pub const SHARE_LEN: usize = HEADER_LEN + SECRET_LEN;
+
+/// Flag set if the shares include a signing key
+const FLAG_SIGNING_KEY: u8 = 0x01;
+
+/// Version, flags, threshold, x, owner key ID and signing public key
+const HEADER_LEN: usize = 4 + KEY_ID_LEN + 32;
+
+/// The shared secret: private key followed by signing seed
+const SECRET_LEN: usize = 64;
+
+/// A share encrypted to one guardian
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct GuardianShare {
+    guardian_key_id: Vec<u8>,
+    container: Vec<u8>,
+}
+
+/// Splits the keys into one share per guardian, any `threshold` of which rebuild them
+///
+/// # Arguments
+/// * `private_key` - The user's X25519 private key
+/// * `signing_key` - The user's Ed25519 signing seed, if any
+/// * `guardian_public_keys` - The guardians' X25519 public keys, concatenated (32 bytes each)
+/// * `threshold` - How many shares are needed to rebuild the keys
+pub(crate) fn split_among_guardians(
+    private_key: &SecretKey,
+    signing_key: Option<&SecretKey>,
+    guardian_public_keys: &[u8],
+    threshold: u8,
+) -> Result<Vec<GuardianShare>, NovaultError> {
+    if guardian_public_keys.is_empty() || !guardian_public_keys.len().is_multiple_of(32) {
+        return Err(NovaultError::InvalidInput(format!(
+            "Guardian public keys must be a non-empty list of 32-byte keys, got {} bytes",
+            guardian_public_keys.len()
+        )));
+    }
+    let guardians: Vec<&[u8]> = guardian_public_keys.chunks_exact(32).collect();
+    let count = guardians.len();
+    if threshold < 2 || threshold as usize > count || count > u8::MAX as usize {
+        return Err(NovaultError::InvalidInput(format!(
+            "Threshold must be between 2 and the number of guardians (at most 255), got {} of {}",
+            threshold, count
+        )));
+    }
+    for (i, guardian) in guardians.iter().enumerate() {
+        if guardians[..i].contains(guardian) {
+            return Err(NovaultError::InvalidInput("Each guardian may only be given one share".to_string()));
+        }
+    }
+    log_debug!("[split_among_guardians] Splitting keys {} of {}", threshold, count);
+
+    let mut secret = Zeroizing::new([0u8; SECRET_LEN]);
+    secret[..32].copy_from_slice(private_key.expose());
+    let mut header = [0u8; HEADER_LEN];
+    header[0] = SHARE_VERSION;
+    header[2] = threshold;
+    header[4..4 + KEY_ID_LEN].copy_from_slice(&key_id(&public_key_of(private_key)));
+    if let Some(signing_key) = signing_key {
+        secret[32..].copy_from_slice(signing_key.expose());
+        header[1] = FLAG_SIGNING_KEY;
+        header[4 + KEY_ID_LEN..].copy_from_slice(&signing_public_key_of(signing_key));
+    }
+
+    let ys = split_secret(&secret[..], threshold, count as u8);
+    let shares = guardians
+        .iter()
+        .zip(ys.iter())
+        .enumerate()
+        .map(|(i, (guardian, y))| {
+            header[3] = i as u8 + 1;
+            let mut share = Zeroizing::new(Vec::with_capacity(SHARE_LEN));
+            share.extend_from_slice(&header);
+            share.extend_from_slice(&y[..]);
+            let container = encrypt_payload(&share, guardian, KeyWrapScheme::HpkeAuth, Some(private_key))
+                .into_container()?;
+            Ok(GuardianShare {
+                guardian_key_id: key_id(guardian),
+                container,
+            })
+        })
+        .collect::<Result<Vec<_>, NovaultError>>()?;
+    log_info!("[split_among_guardians] Keys split among {} guardians", count);
+    Ok(shares)
+}
+
+/// Rebuilds the user's keys from guardian shares and seals them under a new password
+///
+/// The rebuilt private key must match `public_key`, so a wrong or tampered
+/// share is detected instead of producing a useless key.
+///
+/// # Arguments
+/// * `shares` - The decrypted shares, concatenated ([`SHARE_LEN`] bytes each);
+///   at least the threshold is needed
+/// * `public_key` - The user's stored X25519 public key (32 bytes)
+/// * `new_password` - The new master password
+/// * `create_signing_key` - Generate a signing key if the vault had none when
+///   it was split, instead of failing with `INVALID_INPUT`; see `rewrap_private_key`
+/// * `params` - Argon2id parameters for the new KEK; defaults to [`KdfParams::DEFAULT`]
+///
+/// # Returns
+/// The keys sealed under the new password, as from `encrypt_master_key`
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn combine_guardian_shares(
+    shares: &[u8],
+    public_key: &[u8],
+    new_password: &str,
+    create_signing_key: bool,
+    params: Option<KdfParams>,
+) -> Result<EncryptedMasterKey, NovaultError> {
+    let params = params_for_new_key(params)?;
+    if public_key.len() != 32 {
+        return Err(NovaultError::InvalidInput(format!(
+            "Public key must be 32 bytes, got {}",
+            public_key.len()
+        )));
+    }
+    if shares.is_empty() || !shares.len().is_multiple_of(SHARE_LEN) {
+        return Err(NovaultError::InvalidInput(format!(
+            "Shares must be a non-empty list of {}-byte shares, got {} bytes",
+            SHARE_LEN,
+            shares.len()
+        )));
+    }
+
+    let shares: Vec<&[u8]> = shares.chunks_exact(SHARE_LEN).collect();
+    let header = &shares[0][..HEADER_LEN];
+    if header[0] != SHARE_VERSION {
+        return Err(NovaultError::InvalidInput(format!("Unsupported share version: {}", header[0])));
+    }
+    let threshold = header[2] as usize;
+    for (i, share) in shares.iter().enumerate() {
+        // Everything but x must agree, and every x must be distinct and non-zero
+        if share[..3] != header[..3] || share[4..HEADER_LEN] != header[4..] {
+            return Err(NovaultError::InvalidInput("Shares belong to different splits".to_string()));
+        }
+        if share[3] == 0 || shares[..i].iter().any(|other| other[3] == share[3]) {
+            return Err(NovaultError::InvalidInput("Shares must be distinct".to_string()));
+        }
+    }
+    if threshold < 2 || shares.len() < threshold {
+        return Err(NovaultError::InvalidInput(format!(
+            "{} shares are needed, got {}",
+            threshold,
+            shares.len()
+        )));
+    }
+    if header[4..4 + KEY_ID_LEN] != key_id(public_key)[..] {
+        return Err(NovaultError::InvalidInput("Shares belong to a different key".to_string()));
+    }
+
+    let secret = combine_secret(&shares[..threshold]);
+    let private_key = SecretKey::from_slice(&secret[..32], "Private key")?;
+    if public_key_of(&private_key)[..] != *public_key {
+        return Err(NovaultError::InvalidInput(
+            "Shares do not rebuild the private key for this public key".to_string(),
+        ));
+    }
+    log_debug!("[combine_guardian_shares] Private key rebuilt from {} shares", threshold);
+
+    let signing_key = if header[1] & FLAG_SIGNING_KEY != 0 {
+        let signing_key = SecretKey::from_slice(&secret[32..], "Signing key")?;
+        if signing_public_key_of(&signing_key)[..] != header[4 + KEY_ID_LEN..] {
+            return Err(NovaultError::InvalidInput(
+                "Shares do not rebuild the signing key".to_string(),
+            ));
+        }
+        Some(signing_key)
+    } else {
+        None
+    };
+    let (signing_key, signing_key_created) = existing_or_new_signing_key(signing_key, create_signing_key)?;
+
+    let key = seal_master_key(new_password, &params, &private_key, &signing_key, signing_key_created)?;
+    log_info!("[combine_guardian_shares] Private key re-encrypted under the new password");
+    Ok(key)
+}
+
+/// Shares every byte of `secret` with a random polynomial of degree `threshold - 1`
+///
+/// # Returns
+/// The `y` values for x = 1..=count
+fn split_secret(secret: &[u8], threshold: u8, count: u8) -> Vec<Zeroizing<Vec<u8>>> {
+    let mut ys: Vec<_> = (0..count).map(|_| Zeroizing::new(vec![0u8; secret.len()])).collect();
+    let mut coefficients = Zeroizing::new(vec![0u8; threshold as usize]);
+    for (i, &byte) in secret.iter().enumerate() {
+        coefficients[0] = byte;
+        OsRng.fill_bytes(&mut coefficients[1..]);
+        for (x, y) in (1..=count).zip(ys.iter_mut()) {
+            // Horner's rule, highest coefficient first
+            y[i] = coefficients.iter().rev().fold(0, |acc, &c| gf_mul(acc, x) ^ c);
+        }
+    }
+    ys
+}
+
+/// Interpolates the shares' polynomials at x = 0
+fn combine_secret(shares: &[&[u8]]) -> Zeroizing<[u8; SECRET_LEN]> {
+    let mut secret = Zeroizing::new([0u8; SECRET_LEN]);
+    for (j, share) in shares.iter().enumerate() {
+        // Lagrange basis at 0; subtraction is XOR in GF(256)
+        let x_j = share[3];
+        let basis = shares
+            .iter()
+            .enumerate()
+            .filter(|&(m, _)| m != j)
+            .fold(1, |acc, (_, other)| gf_mul(acc, gf_mul(other[3], gf_inv(other[3] ^ x_j))));
+        for (byte, &y) in secret.iter_mut().zip(&share[HEADER_LEN..]) {
+            *byte ^= gf_mul(y, basis);
+        }
+    }
+    secret
+}
+
+/// Multiplies in GF(2^8) modulo x^8 + x^4 + x^3 + x + 1, without secret-dependent branches
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    for _ in 0..8 {
+        product ^= a & (b & 1).wrapping_neg();
+        let carry = (a >> 7).wrapping_neg();
+        a = (a << 1) ^ (0x1b & carry);
+        b >>= 1;
+    }
+    product
+}
+
+/// Multiplicative inverse in GF(2^8), as a^254; maps 0 to 0
+fn gf_inv(a: u8) -> u8 {
+    // 254 = 0b11111110
+    let mut result = 1;
+    let mut power = a;
+    for _ in 0..7 {
+        power = gf_mul(power, power);
+        result = gf_mul(result, power);
+    }
+    result
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl GuardianShare {
+    /// Key ID of the guardian this share is encrypted to
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn guardian_key_id(&self) -> Vec<u8> {
+        self.guardian_key_id.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn guardian_key_id_hex(&self) -> String {
+        bytes_to_hex(&self.guardian_key_id)
+    }
+
+    /// The share, as a container only the guardian can decrypt
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn container(&self) -> Vec<u8> {
+        self.container.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decrypt_file::{decrypt_with_private_key, read_container};
+    use crate::masterkey_generator::generate_private_key;
+    use crate::pepper::register_pepper;
+    use crate::signing::generate_signing_key;
+
+    /// Splits fresh keys among `count` guardians and returns the decrypted shares
+    fn guardian_shares(signing_key: Option<&SecretKey>, threshold: u8, count: usize) -> (SecretKey, Vec<Vec<u8>>) {
+        let private_key = generate_private_key();
+        let guardians: Vec<SecretKey> = (0..count).map(|_| generate_private_key()).collect();
+        let public_keys: Vec<u8> = guardians.iter().flat_map(|g| public_key_of(g).to_vec()).collect();
+        let shares = split_among_guardians(&private_key, signing_key, &public_keys, threshold)
+            .unwrap()
+            .iter()
+            .zip(&guardians)
+            .map(|(share, guardian)| {
+                let container = share.container();
+                let (header, ciphertext, sender_tags) = read_container(&container).unwrap();
+                decrypt_with_private_key(ciphertext, guardian, &header, sender_tags).into_data().unwrap().0
+            })
+            .collect();
+        (private_key, shares)
+    }
+
+    fn combine(shares: &[&[u8]], public_key: &[u8], create_signing_key: bool) -> Result<EncryptedMasterKey, NovaultError> {
+        register_pepper(1, b"shamir test pepper").unwrap();
+        combine_guardian_shares(&shares.concat(), public_key, "new password", create_signing_key, Some(KdfParams::MINIMUM))
+    }
+
+    #[test]
+    fn every_nonzero_element_has_an_inverse() {
+        for a in 1..=255u8 {
+            assert_eq!(gf_mul(a, gf_inv(a)), 1, "a = {}", a);
+        }
+        assert_eq!(gf_inv(0), 0);
+        // FIPS 197, section 4.2
+        assert_eq!(gf_mul(0x57, 0x83), 0xc1);
+    }
+
+    #[test]
+    fn any_threshold_of_shares_rebuilds_the_secret() {
+        let mut secret = [0u8; SECRET_LEN];
+        OsRng.fill_bytes(&mut secret);
+        let shares: Vec<Vec<u8>> = split_secret(&secret, 3, 5)
+            .iter()
+            .enumerate()
+            .map(|(i, y)| {
+                let mut share = vec![0u8; HEADER_LEN];
+                share[3] = i as u8 + 1;
+                share.extend_from_slice(y);
+                share
+            })
+            .collect();
+
+        for a in 0..5 {
+            for b in a + 1..5 {
+                assert_ne!(*combine_secret(&[&shares[a], &shares[b]]), secret, "shares {} and {}", a, b);
+                for c in b + 1..5 {
+                    let rebuilt = combine_secret(&[&shares[c], &shares[a], &shares[b]]);
+                    assert_eq!(*rebuilt, secret, "shares {}, {} and {}", a, b, c);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn guardian_shares_rebuild_the_keys() {
+        let signing_key = generate_signing_key();
+        let (private_key, shares) = guardian_shares(Some(&signing_key), 2, 3);
+        let public_key = public_key_of(&private_key);
+
+        let key = combine(&[&shares[2], &shares[0]], &public_key, false).unwrap();
+        assert_eq!(key.public_key(), public_key.to_vec());
+        assert_eq!(key.signing_public_key(), signing_public_key_of(&signing_key).to_vec());
+        assert!(!key.signing_key_created());
+
+        let err = combine(&[&shares[1]], &public_key, false).err().unwrap();
+        assert_eq!(err.code().as_str(), "INVALID_INPUT");
+    }
+
+    #[test]
+    fn wrong_or_tampered_shares_are_rejected() {
+        let (private_key, shares) = guardian_shares(Some(&generate_signing_key()), 2, 3);
+        let public_key = public_key_of(&private_key);
+
+        let other_key = public_key_of(&generate_private_key());
+        let err = combine(&[&shares[0], &shares[1]], &other_key, false).err().unwrap();
+        assert_eq!(err.code().as_str(), "INVALID_INPUT");
+
+        let mut tampered = shares[1].clone();
+        tampered[HEADER_LEN] ^= 1;
+        let err = combine(&[&shares[0], &tampered], &public_key, false).err().unwrap();
+        assert_eq!(err.to_string(), combine_error("Shares do not rebuild the private key for this public key"));
+
+        let err = combine(&[&shares[0], &shares[0]], &public_key, false).err().unwrap();
+        assert_eq!(err.to_string(), combine_error("Shares must be distinct"));
+
+        let mut relabelled = shares[1].clone();
+        relabelled[3] = shares[0][3];
+        let err = combine(&[&shares[0], &relabelled], &public_key, false).err().unwrap();
+        assert_eq!(err.to_string(), combine_error("Shares must be distinct"));
+    }
+
+    #[test]
+    fn shares_without_a_signing_key_need_an_opt_in() {
+        let (private_key, shares) = guardian_shares(None, 2, 2);
+        let public_key = public_key_of(&private_key);
+
+        let err = combine(&[&shares[0], &shares[1]], &public_key, false).err().unwrap();
+        assert_eq!(err.code().as_str(), "INVALID_INPUT");
+        let key = combine(&[&shares[0], &shares[1]], &public_key, true).unwrap();
+        assert_eq!(key.public_key(), public_key.to_vec());
+        assert!(key.signing_key_created());
+    }
+
+    fn combine_error(message: &str) -> String {
+        NovaultError::InvalidInput(message.to_string()).to_string()
+    }
+}
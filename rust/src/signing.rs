@@ -0,0 +1,139 @@
+//! Ed25519 signing identity.
+//!
+//! Alongside the X25519 key pair used for encryption, every master key now
+//! carries an Ed25519 signing key, encrypted under the same password-derived
+//! KEK (see [`crate::masterkey_generator::encrypt_master_key`]). Only the
+//! 32-byte seed is stored; the expanded key is rebuilt when signing.
+//!
+//! Signing needs the unlocked seed and goes through
+//! [`crate::vault::UnlockedVault::sign`]. Verifying needs only the signer's
+//! public key, so [`verify`] is a free function.
+//!
+//! Every signature covers a context prefix followed by the message, so a
+//! signature made for one purpose is never valid for another. Messages
+//! signed with `UnlockedVault.sign` use [`MESSAGE_CONTEXT`]; signed device
+//! lists use [`crate::device::DEVICE_LIST_CONTEXT`].
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+use aes_gcm::aead::{OsRng, rand_core::RngCore};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+
+use crate::error::NovaultError;
+use crate::key_wrap::check_len;
+use crate::secret::SecretKey;
+use crate::logger::{log_debug, log_warn};
+
+/// Length of an Ed25519 signature
+pub const SIGNATURE_LEN: usize = 64;
+
+/// Prefix of the message a signature from `UnlockedVault.sign` covers
+pub const MESSAGE_CONTEXT: &[u8] = b"novault/message/v1\0";
+
+/// Generates a random Ed25519 seed
+pub(crate) fn generate_signing_key() -> SecretKey {
+    let mut seed = SecretKey::new([0u8; 32]);
+    OsRng.fill_bytes(seed.expose_mut());
+    seed
+}
+
+/// The user's existing signing key, or a new one if the caller allows it
+///
+/// A new key is a new signing identity: device lists and signatures made
+/// under an earlier key stop verifying. Callers should only allow it for
+/// master keys created before signing keys existed, which have none.
+///
+/// # Returns
+/// The key, and whether it was generated
+///
+/// # Errors
+/// `INVALID_INPUT` if there is no signing key and `create_if_missing` is false
+pub(crate) fn existing_or_new_signing_key(
+    signing_key: Option<SecretKey>,
+    create_if_missing: bool,
+) -> Result<(SecretKey, bool), NovaultError> {
+    match signing_key {
+        Some(signing_key) => Ok((signing_key, false)),
+        None if create_if_missing => {
+            log_warn!("[existing_or_new_signing_key] No signing key given, generating a new signing identity");
+            Ok((generate_signing_key(), true))
+        }
+        None => Err(NovaultError::InvalidInput(
+            "No signing key given; pass the stored signing key, or allow a new one for accounts that never had one"
+                .to_string(),
+        )),
+    }
+}
+
+/// The Ed25519 public key belonging to a seed
+pub(crate) fn signing_public_key_of(seed: &SecretKey) -> [u8; 32] {
+    SigningKey::from_bytes(seed.expose()).verifying_key().to_bytes()
+}
+
+/// Signs `context` followed by `message` with an Ed25519 seed
+pub(crate) fn sign(seed: &SecretKey, context: &[u8], message: &[u8]) -> [u8; SIGNATURE_LEN] {
+    SigningKey::from_bytes(seed.expose()).sign(&[context, message].concat()).to_bytes()
+}
+
+/// Verifies a signature from `UnlockedVault.sign`
+///
+/// Uses strict verification, which also rejects weak public keys and
+/// non-canonical signatures.
+///
+/// # Arguments
+/// * `public_key` - The signer's Ed25519 public key (32 bytes)
+/// * `message` - The signed message, without [`MESSAGE_CONTEXT`]
+/// * `signature` - The signature (64 bytes)
+///
+/// # Returns
+/// Whether the signature is valid; malformed lengths are an error
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn verify(public_key: &[u8], message: &[u8], signature: &[u8]) -> Result<bool, NovaultError> {
+    verify_with_context(public_key, MESSAGE_CONTEXT, message, signature)
+}
+
+/// Verifies a signature over `context` followed by `message`, as for [`verify`]
+pub(crate) fn verify_with_context(
+    public_key: &[u8],
+    context: &[u8],
+    message: &[u8],
+    signature: &[u8],
+) -> Result<bool, NovaultError> {
+    check_len(32, public_key, "Signing public key")?;
+    check_len(SIGNATURE_LEN, signature, "Signature")?;
+
+    let public_key: [u8; 32] = public_key.try_into().unwrap();
+    let signature = Signature::from_bytes(signature.try_into().unwrap());
+    let Ok(verifying_key) = VerifyingKey::from_bytes(&public_key) else {
+        log_debug!("[verify] Public key is not a valid Ed25519 point");
+        return Ok(false);
+    };
+    Ok(verifying_key.verify_strict(&[context, message].concat(), &signature).is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::DEVICE_LIST_CONTEXT;
+
+    #[test]
+    fn signatures_do_not_cross_contexts() {
+        let seed = generate_signing_key();
+        let public_key = signing_public_key_of(&seed);
+        let body = b"NVDL\x01\x00\x00\x00\x07\x00\x00";
+
+        let signature = sign(&seed, MESSAGE_CONTEXT, body);
+        assert!(verify(&public_key, body, &signature).unwrap());
+        assert!(!verify_with_context(&public_key, DEVICE_LIST_CONTEXT, body, &signature).unwrap());
+
+        // A message that starts with the device list context still cannot pass as a device list
+        let message = [DEVICE_LIST_CONTEXT, body].concat();
+        let signature = sign(&seed, MESSAGE_CONTEXT, &message);
+        assert!(verify(&public_key, &message, &signature).unwrap());
+        assert!(!verify_with_context(&public_key, DEVICE_LIST_CONTEXT, body, &signature).unwrap());
+
+        let signature = sign(&seed, DEVICE_LIST_CONTEXT, body);
+        assert!(!verify(&public_key, body, &signature).unwrap());
+        assert!(!verify(&public_key, &message, &signature).unwrap());
+    }
+}
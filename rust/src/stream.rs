@@ -0,0 +1,540 @@
+//! Chunked streaming encryption for large files.
+//!
+//! The plaintext is split into fixed-size chunks and each chunk is sealed
+//! separately with AES-256-GCM under the file's DEK, following the STREAM
+//! construction. The nonce of chunk `i` is derived from the file nonce:
+//!
+//! ```text
+//! chunk_nonce = file_nonce XOR (0x00 * 7 || i as u32 big-endian || last_flag)
+//! ```
+//!
+//! Binding the index into the nonce detects reordered or dropped chunks, and
+//! the last-chunk flag detects truncation at a chunk boundary. Every chunk is
+//! `chunk_size` bytes of plaintext except the final one, which is always
+//! shorter (possibly empty), so the chunk layout of a ciphertext is fully
+//! determined by its length.
+//!
+//! A sender-authenticated stream ends with the container's sender tags (see
+//! [`crate::container`]), sealed by `finish` over every chunk. The decryptor
+//! reports the sender only once `finish` has checked them.
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+use aes_gcm::{
+    Aes256Gcm, aead::{Aead, KeyInit, generic_array::GenericArray}
+};
+
+pub use crate::{generate_nonce, bytes_to_hex, Nonce};
+use crate::container::{ContainerHeader, ContentCommitment, ALG_X25519_AES256GCM_STREAM, CONTAINER_VERSION};
+use crate::decrypt_file::{unlock_private_key, verified_sender_key_id};
+use crate::error::NovaultError;
+use crate::file_digest::{file_digest_key, FileDigest};
+use crate::key_wrap::{
+    check_len, generate_dek, seal_sender_tags, unwrap_dek_for_recipient, wrap_dek_for_recipients, KeyWrapScheme, WrappedDek
+};
+use crate::secret::SecretKey;
+use crate::logger::{log_debug, log_info, log_warn};
+
+/// Default plaintext chunk size (64 KiB)
+pub const DEFAULT_CHUNK_SIZE: u32 = 64 * 1024;
+
+/// AES-GCM authentication tag appended to every chunk
+pub const TAG_LEN: usize = 16;
+
+/// AES-256-GCM keyed with a file DEK, sealing individual chunks
+pub(crate) struct ChunkCipher {
+    cipher: Aes256Gcm,
+    file_nonce: [u8; 12],
+}
+
+impl ChunkCipher {
+    pub(crate) fn new(dek: &SecretKey, file_nonce: &[u8]) -> Result<Self, NovaultError> {
+        check_len(12, file_nonce, "File Nonce")?;
+        Ok(ChunkCipher {
+            cipher: Aes256Gcm::new(GenericArray::from_slice(dek.expose())),
+            file_nonce: file_nonce.try_into().unwrap(),
+        })
+    }
+
+    fn nonce(&self, index: u32, last: bool) -> Nonce {
+        let mut nonce = self.file_nonce;
+        for (n, i) in nonce[7..11].iter_mut().zip(index.to_be_bytes()) {
+            *n ^= i;
+        }
+        nonce[11] ^= last as u8;
+        Nonce::clone_from_slice(&nonce)
+    }
+
+    pub(crate) fn encrypt_chunk(&self, index: u32, last: bool, data: &[u8]) -> Result<Vec<u8>, NovaultError> {
+        self.cipher
+            .encrypt(&self.nonce(index, last), data)
+            .map_err(|e| NovaultError::EncryptionFailed(format!("Chunk {} encryption failed: {}", index, e)))
+    }
+
+    pub(crate) fn decrypt_chunk(&self, index: u32, last: bool, data: &[u8]) -> Result<Vec<u8>, NovaultError> {
+        self.cipher
+            .decrypt(&self.nonce(index, last), data)
+            .map_err(|_| NovaultError::PayloadAuthFailed { chunk: Some(index) })
+    }
+}
+
+fn next_index(index: u32) -> Result<u32, NovaultError> {
+    index
+        .checked_add(1)
+        .ok_or_else(|| NovaultError::InvalidInput("Stream exceeds the maximum number of chunks".to_string()))
+}
+
+fn already_finished() -> NovaultError {
+    NovaultError::InvalidState("Stream already finished".to_string())
+}
+
+/// Incremental encryptor producing a chunked container
+///
+/// Write `header` first, then every buffer returned by `push`, then the
+/// buffer returned by `finish`. The concatenation is a complete container.
+///
+/// # Example (JavaScript)
+///
+/// ```javascript
+/// const enc = new wasm.StreamEncryptor(publicKeyBytes);
+/// const parts = [enc.header];
+/// for await (const chunk of file.stream()) parts.push(enc.push(chunk));
+/// parts.push(enc.finish());
+/// ```
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct StreamEncryptor {
+    cipher: ChunkCipher,
+    header: Vec<u8>,
+    chunk_size: usize,
+    buffer: Vec<u8>,
+    index: u32,
+    digest: Option<FileDigest>,
+    original_hash_hex: String,
+    /// Sender key, recipients and commitment for the sender tags
+    sender: Option<(SecretKey, Vec<u8>, ContentCommitment)>,
+    finished: bool,
+}
+
+impl StreamEncryptor {
+    /// Starts a new encrypted stream with an explicit scheme and optional sender key
+    pub(crate) fn with_sender(
+        recipient_public_key: &[u8],
+        chunk_size: Option<u32>,
+        scheme: KeyWrapScheme,
+        sender: Option<&SecretKey>,
+        digest_key: &SecretKey,
+    ) -> Result<StreamEncryptor, NovaultError> {
+        let chunk_size = chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE);
+        if chunk_size == 0 {
+            return Err(NovaultError::InvalidInput("Chunk size must be non-zero".to_string()));
+        }
+        log_debug!("[StreamEncryptor] Starting stream, chunk size: {} bytes", chunk_size);
+
+        let dek = generate_dek();
+        let file_nonce = generate_nonce();
+        let mut header = ContainerHeader {
+            version: CONTAINER_VERSION,
+            algorithm: ALG_X25519_AES256GCM_STREAM,
+            file_nonce: file_nonce.to_vec(),
+            chunk_size: Some(chunk_size),
+            recipients: Vec::new(),
+            sender_tag_count: 0,
+        };
+        header.recipients = wrap_dek_for_recipients(&dek, recipient_public_key, scheme, sender, &header.context())?;
+        if sender.is_some() {
+            header.sender_tag_count = header.recipients.len() as u16;
+        }
+        let sender = sender.map(|sender| (sender.clone(), recipient_public_key.to_vec(), ContentCommitment::new(&header)));
+
+        Ok(StreamEncryptor {
+            cipher: ChunkCipher::new(&dek, file_nonce.as_slice())?,
+            header: header.to_bytes(),
+            chunk_size: chunk_size as usize,
+            buffer: Vec::new(),
+            index: 0,
+            digest: Some(FileDigest::new(digest_key)),
+            original_hash_hex: String::new(),
+            sender,
+            finished: false,
+        })
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl StreamEncryptor {
+    /// Starts a new encrypted stream for the given recipients
+    ///
+    /// # Arguments
+    /// * `recipient_public_key` - The recipient's X25519 public key (32 bytes), or
+    ///   several keys concatenated to encrypt for multiple recipients
+    /// * `chunk_size` - Plaintext bytes per chunk (defaults to 64 KiB)
+    /// * `wrap_scheme` - Key wrap scheme ID; defaults to the latest, since the
+    ///   container records it
+    /// * `digest_key` - The uploader's file digest key, as for `encrypt_file`
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(
+        recipient_public_key: &[u8],
+        chunk_size: Option<u32>,
+        wrap_scheme: Option<u8>,
+        digest_key: &[u8],
+    ) -> Result<StreamEncryptor, NovaultError> {
+        let scheme = KeyWrapScheme::from_option(wrap_scheme, KeyWrapScheme::LATEST)?;
+        let digest_key = SecretKey::from_slice(digest_key, "File digest key")?;
+        StreamEncryptor::with_sender(recipient_public_key, chunk_size, scheme, None, &digest_key)
+    }
+
+    /// The container header; must be written before any chunk
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn header(&self) -> Vec<u8> {
+        self.header.clone()
+    }
+
+    /// Feeds plaintext and returns the ciphertext of every chunk completed so far
+    pub fn push(&mut self, data: &[u8]) -> Result<Vec<u8>, NovaultError> {
+        if self.finished {
+            return Err(already_finished());
+        }
+        if let Some(digest) = &mut self.digest {
+            digest.update(data);
+        }
+        self.buffer.extend_from_slice(data);
+
+        let full_chunks = self.buffer.len() / self.chunk_size;
+        let mut out = Vec::with_capacity(full_chunks * (self.chunk_size + TAG_LEN));
+        for chunk in self.buffer.chunks_exact(self.chunk_size) {
+            out.extend(self.cipher.encrypt_chunk(self.index, false, chunk)?);
+            self.index = next_index(self.index)?;
+        }
+        self.buffer.drain(..full_chunks * self.chunk_size);
+        if let Some((_, _, commitment)) = &mut self.sender {
+            commitment.update(&out);
+        }
+        Ok(out)
+    }
+
+    /// Seals the remaining buffered plaintext as the final chunk, followed by
+    /// the sender tags of a sender-authenticated stream
+    pub fn finish(&mut self) -> Result<Vec<u8>, NovaultError> {
+        if self.finished {
+            return Err(already_finished());
+        }
+        let mut out = self.cipher.encrypt_chunk(self.index, true, &self.buffer)?;
+        if let Some((sender, recipient_public_keys, mut commitment)) = self.sender.take() {
+            commitment.update(&out);
+            out.extend(seal_sender_tags(&recipient_public_keys, &sender, &commitment.finalize())?);
+        }
+        self.buffer.clear();
+        self.finished = true;
+        self.original_hash_hex = self.digest.take().map(FileDigest::finalize_hex).unwrap_or_default();
+        log_info!("[StreamEncryptor] Stream finished after {} chunks", self.index as u64 + 1);
+        Ok(out)
+    }
+
+    /// Keyed digest of the whole plaintext, available after `finish`
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn original_hash_hex(&self) -> String {
+        self.original_hash_hex.clone()
+    }
+}
+
+/// Incremental decryptor for a chunked container
+///
+/// Feed the container bytes in order through `push` and call `finish` at the
+/// end of the stream. `finish` fails if the stream was truncated.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct StreamDecryptor {
+    cipher: ChunkCipher,
+    chunk_size: usize,
+    /// Length of the sender tags at the end of the stream
+    sender_tags_len: usize,
+    buffer: Vec<u8>,
+    index: u32,
+    digest: Option<FileDigest>,
+    file_hash_hex: String,
+    /// Everything needed to check the sender tag in `finish`
+    sender: Option<SenderCheck>,
+    sender_key_id: Vec<u8>,
+    finished: bool,
+}
+
+/// The recipient's key and entry, and the commitment to the chunks read so far
+struct SenderCheck {
+    private_key: SecretKey,
+    header: ContainerHeader,
+    entry: WrappedDek,
+    commitment: ContentCommitment,
+}
+
+impl StreamDecryptor {
+    /// Opens a chunked container with an already decrypted private key
+    ///
+    /// `digest_key` keys `file_hash_hex`; it belongs to the user's current
+    /// key, which need not be the one the stream is addressed to.
+    pub(crate) fn with_private_key(
+        header: &[u8],
+        private_key: &SecretKey,
+        digest_key: &SecretKey,
+    ) -> Result<StreamDecryptor, NovaultError> {
+        let (parsed, header_len) = ContainerHeader::parse(header)?;
+        let chunk_size = parsed.chunk_size.ok_or_else(|| {
+            NovaultError::InvalidInput("Container is not chunked; use decrypt_file_container instead".to_string())
+        })?;
+        log_debug!("[StreamDecryptor] Opening stream, chunk size: {} bytes", chunk_size);
+
+        let (dek, entry) = unwrap_dek_for_recipient(private_key, &parsed.recipients, &parsed.context())?;
+        let cipher = ChunkCipher::new(&dek, &parsed.file_nonce)?;
+        let entry = entry.clone();
+        let sender_tags_len = parsed.sender_tags_len();
+
+        let sender = entry.scheme.is_authenticated().then(|| SenderCheck {
+            private_key: private_key.clone(),
+            commitment: ContentCommitment::new(&parsed),
+            header: parsed,
+            entry,
+        });
+
+        Ok(StreamDecryptor {
+            cipher,
+            chunk_size: chunk_size as usize,
+            sender_tags_len,
+            buffer: header[header_len..].to_vec(),
+            index: 0,
+            digest: Some(FileDigest::new(digest_key)),
+            file_hash_hex: String::new(),
+            sender,
+            sender_key_id: Vec::new(),
+            finished: false,
+        })
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl StreamDecryptor {
+    /// Opens a chunked container for decryption
+    ///
+    /// # Arguments
+    /// * `header` - The start of the container; must contain at least the full
+    ///   header. Any bytes after the header are treated as ciphertext.
+    /// * `password` - The user's master password
+    /// * `pk_salt` - Salt used for deriving the key encryption key
+    /// * `encrypted_private_key` - The encrypted private key bytes (48 bytes)
+    /// * `pk_nonce` - The nonce used for private key encryption (12 bytes)
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(
+        header: &[u8],
+        password: &str,
+        pk_salt: &str,
+        encrypted_private_key: &[u8],
+        pk_nonce: &[u8],
+    ) -> Result<StreamDecryptor, NovaultError> {
+        let private_key = unlock_private_key(password, pk_salt, encrypted_private_key, pk_nonce)?;
+        StreamDecryptor::with_private_key(header, &private_key, &file_digest_key(&private_key))
+    }
+
+    /// Feeds ciphertext and returns the plaintext of every chunk completed so far
+    pub fn push(&mut self, data: &[u8]) -> Result<Vec<u8>, NovaultError> {
+        if self.finished {
+            return Err(already_finished());
+        }
+        self.buffer.extend_from_slice(data);
+
+        // A full-size chunk is never the final one, so it can be opened right
+        // away, as long as the sender tags that end the stream stay buffered
+        let sealed_size = self.chunk_size + TAG_LEN;
+        let full_chunks = self.buffer.len().saturating_sub(self.sender_tags_len) / sealed_size;
+        let mut out = Vec::with_capacity(full_chunks * self.chunk_size);
+        for chunk in self.buffer[..full_chunks * sealed_size].chunks_exact(sealed_size) {
+            if let Some(sender) = &mut self.sender {
+                sender.commitment.update(chunk);
+            }
+            let plaintext = self.cipher.decrypt_chunk(self.index, false, chunk)?;
+            if let Some(digest) = &mut self.digest {
+                digest.update(&plaintext);
+            }
+            out.extend(plaintext);
+            self.index = next_index(self.index)?;
+        }
+        self.buffer.drain(..full_chunks * sealed_size);
+        Ok(out)
+    }
+
+    /// Opens the final chunk and verifies the stream was not truncated
+    pub fn finish(&mut self) -> Result<Vec<u8>, NovaultError> {
+        if self.finished {
+            return Err(already_finished());
+        }
+        // Open any full chunks still buffered from the constructor
+        let mut out = self.push(&[])?;
+        if self.buffer.len() < TAG_LEN + self.sender_tags_len {
+            log_warn!("[StreamDecryptor] Stream ended without a final chunk");
+            return Err(NovaultError::Truncated("Stream is truncated: final chunk is missing".to_string()));
+        }
+        let (final_chunk, sender_tags) = self.buffer.split_at(self.buffer.len() - self.sender_tags_len);
+        let last = self.cipher.decrypt_chunk(self.index, true, final_chunk)?;
+        if let Some(SenderCheck { private_key, header, entry, mut commitment }) = self.sender.take() {
+            commitment.update(final_chunk);
+            self.sender_key_id =
+                verified_sender_key_id(&private_key, &header, &entry, sender_tags, || commitment.finalize())?;
+        }
+        if let Some(digest) = &mut self.digest {
+            digest.update(&last);
+        }
+        out.extend(last);
+        self.buffer.clear();
+        self.finished = true;
+        self.file_hash_hex = self.digest.take().map(FileDigest::finalize_hex).unwrap_or_default();
+        log_info!("[StreamDecryptor] Stream finished after {} chunks", self.index as u64 + 1);
+        Ok(out)
+    }
+
+    /// Keyed digest of the whole plaintext (see [`crate::file_digest`]),
+    /// available after `finish`
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn file_hash_hex(&self) -> String {
+        self.file_hash_hex.clone()
+    }
+
+    /// Key ID of the verified sender, available after `finish`; an empty
+    /// string if the stream is not sender-authenticated (see
+    /// `DecryptedFileResult::sender_key_id_hex`)
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn sender_key_id_hex(&self) -> String {
+        bytes_to_hex(&self.sender_key_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_wrap::{key_id, public_key_of};
+
+    fn encrypt(encryptor: &mut StreamEncryptor, plaintext: &[u8]) -> Vec<u8> {
+        let mut out = encryptor.header();
+        out.extend(encryptor.push(plaintext).unwrap());
+        out.extend(encryptor.finish().unwrap());
+        out
+    }
+
+    fn decryptor(container: &[u8], private_key: &SecretKey) -> Result<StreamDecryptor, NovaultError> {
+        StreamDecryptor::with_private_key(container, private_key, &file_digest_key(private_key))
+    }
+
+    fn decrypt(container: &[u8], private_key: &SecretKey) -> Result<Vec<u8>, NovaultError> {
+        decryptor(container, private_key)?.finish()
+    }
+
+    /// An anonymous stream with 8-byte chunks, and the recipient's private key
+    fn anonymous_stream(plaintext: &[u8]) -> (Vec<u8>, SecretKey) {
+        let recipient = generate_dek();
+        let digest_key = file_digest_key(&recipient);
+        let mut encryptor =
+            StreamEncryptor::with_sender(&public_key_of(&recipient), Some(8), KeyWrapScheme::HpkeBase, None, &digest_key)
+                .unwrap();
+        (encrypt(&mut encryptor, plaintext), recipient)
+    }
+
+    /// Splits an anonymous stream into its header and sealed chunks
+    fn split(container: &[u8]) -> (Vec<u8>, Vec<Vec<u8>>) {
+        let (_, header_len) = ContainerHeader::parse(container).unwrap();
+        let chunks = container[header_len..].chunks(8 + TAG_LEN).map(<[u8]>::to_vec).collect();
+        (container[..header_len].to_vec(), chunks)
+    }
+
+    /// The chunk cipher of a stream, as its recipient can rebuild it
+    fn chunk_cipher(container: &[u8], private_key: &SecretKey) -> ChunkCipher {
+        let (header, _) = ContainerHeader::parse(container).unwrap();
+        let (dek, _) = unwrap_dek_for_recipient(private_key, &header.recipients, &header.context()).unwrap();
+        ChunkCipher::new(&dek, &header.file_nonce).unwrap()
+    }
+
+    #[test]
+    fn round_trips_any_length_in_any_pieces() {
+        for len in [0u8, 1, 7, 8, 9, 16, 100] {
+            let plaintext: Vec<u8> = (0..len).collect();
+            let (container, recipient) = anonymous_stream(&plaintext);
+            assert_eq!(decrypt(&container, &recipient).unwrap(), plaintext);
+
+            let (_, header_len) = ContainerHeader::parse(&container).unwrap();
+            for piece in [1, 5, 8 + TAG_LEN, 1000] {
+                let mut decryptor = decryptor(&container[..header_len], &recipient).unwrap();
+                let mut out = Vec::new();
+                for part in container[header_len..].chunks(piece) {
+                    out.extend(decryptor.push(part).unwrap());
+                }
+                out.extend(decryptor.finish().unwrap());
+                assert_eq!(out, plaintext);
+                assert_eq!(decryptor.file_hash_hex().len(), 64);
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_streams_fail() {
+        let (container, recipient) = anonymous_stream(&[1; 20]);
+        let (header, chunks) = split(&container);
+        assert_eq!(chunks.len(), 3);
+
+        let at_boundary = [header.as_slice(), &chunks[0], &chunks[1]].concat();
+        assert!(matches!(decrypt(&at_boundary, &recipient), Err(NovaultError::Truncated(_))));
+        assert!(matches!(decrypt(&header, &recipient), Err(NovaultError::Truncated(_))));
+        assert_eq!(
+            decrypt(&container[..container.len() - 1], &recipient).unwrap_err(),
+            NovaultError::PayloadAuthFailed { chunk: Some(2) }
+        );
+    }
+
+    #[test]
+    fn reordered_or_dropped_chunks_fail() {
+        let (container, recipient) = anonymous_stream(&[1; 20]);
+        let (header, chunks) = split(&container);
+
+        let swapped = [header.as_slice(), &chunks[1], &chunks[0], &chunks[2]].concat();
+        assert_eq!(decrypt(&swapped, &recipient).unwrap_err(), NovaultError::PayloadAuthFailed { chunk: Some(0) });
+        let dropped = [header.as_slice(), &chunks[0], &chunks[2]].concat();
+        assert_eq!(decrypt(&dropped, &recipient).unwrap_err(), NovaultError::PayloadAuthFailed { chunk: Some(1) });
+    }
+
+    #[test]
+    fn chunks_with_the_wrong_last_flag_fail() {
+        let (container, recipient) = anonymous_stream(&[1; 20]);
+        let (header, chunks) = split(&container);
+        let cipher = chunk_cipher(&container, &recipient);
+
+        let final_not_last = cipher.encrypt_chunk(2, false, &[1; 4]).unwrap();
+        let forged = [header.as_slice(), &chunks[0], &chunks[1], &final_not_last].concat();
+        assert_eq!(decrypt(&forged, &recipient).unwrap_err(), NovaultError::PayloadAuthFailed { chunk: Some(2) });
+
+        let middle_last = cipher.encrypt_chunk(1, true, &[1; 8]).unwrap();
+        let forged = [header.as_slice(), &chunks[0], &middle_last, &chunks[2]].concat();
+        assert_eq!(decrypt(&forged, &recipient).unwrap_err(), NovaultError::PayloadAuthFailed { chunk: Some(1) });
+    }
+
+    #[test]
+    fn co_recipient_cannot_forge_a_sender_authenticated_stream() {
+        let (sender, alice, bob) = (generate_dek(), generate_dek(), generate_dek());
+        let recipients = [public_key_of(&alice), public_key_of(&bob)].concat();
+        let mut encryptor =
+            StreamEncryptor::with_sender(&recipients, Some(8), KeyWrapScheme::HpkeAuth, Some(&sender), &file_digest_key(&sender))
+                .unwrap();
+        let container = encrypt(&mut encryptor, b"pay 10 to carol, thanks");
+
+        for recipient in [&alice, &bob] {
+            let mut decryptor = decryptor(&container, recipient).unwrap();
+            assert!(decryptor.sender_key_id_hex().is_empty());
+            assert_eq!(decryptor.finish().unwrap(), b"pay 10 to carol, thanks");
+            assert_eq!(decryptor.sender_key_id, key_id(&public_key_of(&sender)));
+        }
+
+        // Bob re-encrypts the chunks under the DEK he shares with Alice and keeps the tags
+        let (header, header_len) = ContainerHeader::parse(&container).unwrap();
+        let cipher = chunk_cipher(&container, &bob);
+        let mut forged = container[..header_len].to_vec();
+        forged.extend(cipher.encrypt_chunk(0, false, b"pay 99 t").unwrap());
+        forged.extend(cipher.encrypt_chunk(1, false, b"o bob, t").unwrap());
+        forged.extend(cipher.encrypt_chunk(2, true, b"hanks").unwrap());
+        forged.extend_from_slice(&container[container.len() - header.sender_tags_len()..]);
+
+        let mut decryptor = decryptor(&forged, &alice).unwrap();
+        assert_eq!(decryptor.finish().unwrap_err(), NovaultError::SenderAuthFailed);
+    }
+}
@@ -0,0 +1,575 @@
+//! Unlock-once session handle for the user's private key.
+//!
+//! Unlocking runs the Argon2id password derivation a single time and keeps the
+//! decrypted X25519 private key inside WASM memory. Every later file operation
+//! reuses it, so the password does not have to stay in JavaScript and each file
+//! no longer costs a full key derivation.
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::decrypt_file::{decrypt_with_private_key, loose_header, read_container, DecryptedFileResult};
+use crate::device::{create_device_grant, open_device_grant, sign_device_list, DeviceList};
+use crate::encrypt_file::{encrypt_payload, EncryptedFileResult};
+use crate::error::NovaultError;
+use crate::file_digest::{file_digest_key, migrate_digest};
+use crate::bytes_to_hex;
+use crate::container::ContainerHeader;
+use crate::kdf::{params_for_new_key, KdfParams};
+use crate::key_wrap::{key_id, public_key_of, rewrap_dek, unwrap_dek, KeyWrapScheme, WrappedDek};
+use crate::masterkey_decryptor::unlock_keys;
+use crate::masterkey_generator::{generate_private_key, seal_master_key};
+use crate::range_reader::RangeDecryptor;
+use crate::recovery::{create_recovery_key, RecoveryKey};
+use crate::rotation::{open_keyring, rotate_container_header, rotate_entry, seal_keyring, RotatedKey, RotationProgress};
+use crate::secret::SecretKey;
+use crate::shamir::{split_among_guardians, GuardianShare};
+use crate::signing::{self, existing_or_new_signing_key};
+use crate::stream::{StreamDecryptor, StreamEncryptor};
+use crate::logger::{log_debug, log_info};
+
+/// An unlocked vault holding the user's X25519 private key
+///
+/// The key never leaves WASM. Call `lock()` as soon as the session ends; the
+/// key is also wiped when the object is freed, since [`SecretKey`] zeroes
+/// itself on drop.
+///
+/// After a key rotation, load the previous keys with `load_keyring`; files
+/// are then decrypted with whichever key they are addressed to.
+///
+/// # Example (JavaScript)
+///
+/// ```javascript
+/// const vault = new wasm.UnlockedVault(password, salt, encryptedPrivateKey, pkNonce);
+/// const result = vault.decrypt_file_container(containerBytes);
+/// vault.lock();
+/// ```
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct UnlockedVault {
+    private_key: Option<SecretKey>,
+    signing_key: Option<SecretKey>,
+    /// Keys from before a rotation, oldest first
+    previous_keys: Vec<SecretKey>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl UnlockedVault {
+    /// Decrypts the user's private keys and keeps them for the session
+    ///
+    /// The password-derived KEK is computed once for both keys.
+    ///
+    /// # Arguments
+    /// * `password` - The user's master password
+    /// * `salt` - Salt used for key derivation
+    /// * `encrypted_private_key` - The encrypted private key bytes (48 bytes)
+    /// * `nonce` - The nonce used for private key encryption (12 bytes)
+    /// * `encrypted_signing_key` - The encrypted Ed25519 signing key (48 bytes), if
+    ///   the user has one; required for `sign`
+    /// * `signing_nonce` - The nonce used for signing key encryption (12 bytes)
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(
+        password: &str,
+        salt: &str,
+        encrypted_private_key: &[u8],
+        nonce: &[u8],
+        encrypted_signing_key: Option<Vec<u8>>,
+        signing_nonce: Option<Vec<u8>>,
+    ) -> Result<UnlockedVault, NovaultError> {
+        log_debug!("[UnlockedVault] Unlocking vault...");
+        let (private_key, signing_key) = unlock_keys(
+            password,
+            salt,
+            encrypted_private_key,
+            nonce,
+            encrypted_signing_key.as_deref(),
+            signing_nonce.as_deref(),
+        )?;
+        log_info!("[UnlockedVault] Vault unlocked");
+        Ok(UnlockedVault {
+            private_key: Some(private_key),
+            signing_key,
+            previous_keys: Vec::new(),
+        })
+    }
+
+    /// Unlocks the vault on an enrolled device with its PIN
+    ///
+    /// See [`crate::device`].
+    ///
+    /// # Arguments
+    /// * `pin` - The device PIN
+    /// * `salt` - Salt of the device key, from `create_device_key`
+    /// * `encrypted_private_key` - The PIN-sealed device private key (48 bytes)
+    /// * `nonce` - Its nonce (12 bytes)
+    /// * `grant` - The device grant container, from `authorize_device`
+    /// * `public_key` - The user's stored X25519 public key (32 bytes)
+    /// * `devices` - The user's device list, from `DeviceList.verify`; unlocking
+    ///   fails if this device is not in it
+    pub fn unlock_with_device(
+        pin: &str,
+        salt: &str,
+        encrypted_private_key: &[u8],
+        nonce: &[u8],
+        grant: &[u8],
+        public_key: &[u8],
+        devices: &DeviceList,
+    ) -> Result<UnlockedVault, NovaultError> {
+        log_debug!("[UnlockedVault] Unlocking vault with device key...");
+        let (private_key, signing_key) =
+            open_device_grant(pin, salt, encrypted_private_key, nonce, grant, public_key, devices)?;
+        log_info!("[UnlockedVault] Vault unlocked");
+        Ok(UnlockedVault {
+            private_key: Some(private_key),
+            signing_key,
+            previous_keys: Vec::new(),
+        })
+    }
+
+    /// Whether `lock()` has been called
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn is_locked(&self) -> bool {
+        self.private_key.is_none()
+    }
+
+    /// Wipes the private keys; every later call on this vault fails
+    pub fn lock(&mut self) {
+        // Dropping the keys zeroes them
+        self.signing_key = None;
+        self.previous_keys.clear();
+        if self.private_key.take().is_some() {
+            log_info!("[UnlockedVault] Vault locked");
+        }
+    }
+
+    /// Decrypts a file stored as loose fields, like [`crate::decrypt_file::decrypt_file`]
+    ///
+    /// # Arguments
+    /// * `encrypted_data` - The encrypted file bytes to decrypt
+    /// * `ephemeral_public_key` - The ephemeral public key used during encryption (32 bytes)
+    /// * `encrypted_dek` - The encrypted DEK bytes
+    /// * `dek_nonce` - The nonce used for DEK encryption (12 bytes)
+    /// * `file_nonce` - The nonce used for file encryption (12 bytes)
+    /// * `wrap_scheme` - The key wrap scheme ID stored with the DEK; defaults to 1 (raw ECDH)
+    /// * `key_id` - The recipient key ID stored with the DEK, if any (8 bytes)
+    #[allow(clippy::too_many_arguments)]
+    pub fn decrypt_file(
+        &self,
+        encrypted_data: &[u8],
+        ephemeral_public_key: &[u8],
+        encrypted_dek: &[u8],
+        dek_nonce: &[u8],
+        file_nonce: &[u8],
+        wrap_scheme: Option<u8>,
+        key_id: Option<Vec<u8>>,
+    ) -> DecryptedFileResult {
+        let wrapped_dek = match WrappedDek::from_fields(ephemeral_public_key, encrypted_dek, dek_nonce, wrap_scheme, key_id.as_deref()) {
+            Ok(wrapped) => wrapped,
+            Err(e) => return DecryptedFileResult::failed(e),
+        };
+        self.decrypt_with_keys(encrypted_data, &loose_header(file_nonce, wrapped_dek), &[])
+    }
+
+    /// Decrypts a single-shot container, like [`crate::decrypt_file::decrypt_file_container`]
+    pub fn decrypt_file_container(&self, container: &[u8]) -> DecryptedFileResult {
+        let (header, ciphertext, sender_tags) = match read_container(container) {
+            Ok(parsed) => parsed,
+            Err(e) => return DecryptedFileResult::failed(e),
+        };
+        self.decrypt_with_keys(ciphertext, &header, sender_tags)
+    }
+
+    /// Unwraps a file's DEK without decrypting the file
+    ///
+    /// # Arguments
+    /// * `ephemeral_public_key` - The ephemeral public key used during encryption (32 bytes)
+    /// * `encrypted_dek` - The encrypted DEK bytes
+    /// * `dek_nonce` - The nonce used for DEK encryption (12 bytes)
+    /// * `wrap_scheme` - The key wrap scheme ID stored with the DEK; defaults to 1 (raw ECDH)
+    /// * `key_id` - The recipient key ID stored with the DEK, if any (8 bytes)
+    ///
+    /// # Returns
+    /// The 32-byte DEK
+    pub fn unwrap_dek(
+        &self,
+        ephemeral_public_key: &[u8],
+        encrypted_dek: &[u8],
+        dek_nonce: &[u8],
+        wrap_scheme: Option<u8>,
+        key_id: Option<Vec<u8>>,
+    ) -> Result<Vec<u8>, NovaultError> {
+        let wrapped_dek = WrappedDek::from_fields(ephemeral_public_key, encrypted_dek, dek_nonce, wrap_scheme, key_id.as_deref())?;
+        let recipients = [wrapped_dek];
+        let dek = self.with_keys(&recipients, |key| unwrap_dek(key, &recipients[0], &[]))?;
+        Ok(dek.expose().to_vec())
+    }
+
+    /// Wraps a file's DEK for another user so the file can be shared without re-uploading it
+    ///
+    /// # Arguments
+    /// * `ephemeral_public_key` - The ephemeral public key of the owner's stored entry (32 bytes)
+    /// * `encrypted_dek` - The owner's stored encrypted DEK bytes
+    /// * `dek_nonce` - The nonce of the owner's stored entry (12 bytes)
+    /// * `recipient_public_key` - The target user's X25519 public key (32 bytes)
+    /// * `wrap_scheme` - The key wrap scheme ID stored with the owner's DEK; defaults to 1 (raw ECDH)
+    /// * `key_id` - The recipient key ID stored with the DEK, if any (8 bytes)
+    ///
+    /// # Returns
+    /// A new wrapped DEK for the target user, to be stored alongside the owner's
+    /// with its own `wrap_scheme` (see [`crate::key_wrap::rewrap_dek`]).
+    pub fn rewrap_dek(
+        &self,
+        ephemeral_public_key: &[u8],
+        encrypted_dek: &[u8],
+        dek_nonce: &[u8],
+        recipient_public_key: &[u8],
+        wrap_scheme: Option<u8>,
+        key_id: Option<Vec<u8>>,
+    ) -> Result<WrappedDek, NovaultError> {
+        let wrapped_dek = WrappedDek::from_fields(ephemeral_public_key, encrypted_dek, dek_nonce, wrap_scheme, key_id.as_deref())?;
+        let recipients = [wrapped_dek];
+        let rewrapped = self.with_keys(&recipients, |key| rewrap_dek(key, &recipients[0], &[], recipient_public_key))?;
+        log_info!("[UnlockedVault] DEK re-wrapped for a new recipient");
+        Ok(rewrapped)
+    }
+
+    /// Signs a message with the user's Ed25519 signing key
+    ///
+    /// The signature covers [`signing::MESSAGE_CONTEXT`] followed by the
+    /// message, so it can never pass as a device list signature. Verify it
+    /// with [`crate::signing::verify`] and the user's signing public key.
+    ///
+    /// # Returns
+    /// The 64-byte signature
+    pub fn sign(&self, message: &[u8]) -> Result<Vec<u8>, NovaultError> {
+        Ok(signing::sign(self.signing_key()?, signing::MESSAGE_CONTEXT, message).to_vec())
+    }
+
+    /// Generates a recovery phrase that restores this vault's keys without the password
+    ///
+    /// See [`crate::recovery`].
+    pub fn create_recovery_key(&self) -> Result<RecoveryKey, NovaultError> {
+        create_recovery_key(self.key()?, self.signing_key.as_ref())
+    }
+
+    /// Lets a device unlock this vault with its PIN
+    ///
+    /// Add the device to the [`DeviceList`] and sign it as well. See [`crate::device`].
+    ///
+    /// # Arguments
+    /// * `device_public_key` - The device's X25519 public key (32 bytes)
+    ///
+    /// # Returns
+    /// The device grant container, to be stored for that device
+    pub fn authorize_device(&self, device_public_key: &[u8]) -> Result<Vec<u8>, NovaultError> {
+        create_device_grant(self.key()?, self.signing_key.as_ref(), device_public_key)
+    }
+
+    /// Signs a device list with the user's signing key
+    ///
+    /// # Returns
+    /// The encoded list and its signature, for `DeviceList.verify`
+    pub fn sign_device_list(&self, list: &DeviceList) -> Result<Vec<u8>, NovaultError> {
+        Ok(sign_device_list(self.signing_key()?, list))
+    }
+
+    /// Splits this vault's keys among guardians, any `threshold` of whom can restore them
+    ///
+    /// See [`crate::shamir`].
+    ///
+    /// # Arguments
+    /// * `guardian_public_keys` - The guardians' X25519 public keys, concatenated (32 bytes each)
+    /// * `threshold` - How many shares are needed, from 2 up to the number of guardians
+    ///
+    /// # Returns
+    /// One encrypted share per guardian, in the order the keys were given
+    pub fn split_among_guardians(
+        &self,
+        guardian_public_keys: &[u8],
+        threshold: u8,
+    ) -> Result<Vec<GuardianShare>, NovaultError> {
+        split_among_guardians(self.key()?, self.signing_key.as_ref(), guardian_public_keys, threshold)
+    }
+
+    /// Encrypts a file as the vault owner, so recipients can verify the sender
+    ///
+    /// The DEK is wrapped with HPKE Auth mode under the owner's private key,
+    /// and the owner seals a tag over the ciphertext to every recipient.
+    /// Decrypting the container reports the owner's key ID in
+    /// `sender_key_id_hex`. Use the container output; the DEK and sender tags
+    /// cannot be stored as loose fields.
+    ///
+    /// # Arguments
+    /// * `file_data` - The raw file bytes to encrypt
+    /// * `recipient_public_keys` - The recipients' X25519 public keys, concatenated (32 bytes each)
+    pub fn encrypt_file(&self, file_data: &[u8], recipient_public_keys: &[u8]) -> EncryptedFileResult {
+        let private_key = match self.key() {
+            Ok(key) => key,
+            Err(e) => return EncryptedFileResult::failed(e),
+        };
+        log_debug!("[UnlockedVault] Encrypting file as sender...");
+        encrypt_payload(file_data, recipient_public_keys, KeyWrapScheme::HpkeAuth, Some(private_key))
+            .with_digest(file_data, &file_digest_key(private_key))
+    }
+
+    /// Starts a sender-authenticated chunked stream, as for [`UnlockedVault::encrypt_file`]
+    ///
+    /// # Arguments
+    /// * `recipient_public_keys` - The recipients' X25519 public keys, concatenated (32 bytes each)
+    /// * `chunk_size` - Plaintext bytes per chunk (defaults to 64 KiB)
+    pub fn stream_encryptor(
+        &self,
+        recipient_public_keys: &[u8],
+        chunk_size: Option<u32>,
+    ) -> Result<StreamEncryptor, NovaultError> {
+        let private_key = self.key()?;
+        StreamEncryptor::with_sender(
+            recipient_public_keys,
+            chunk_size,
+            KeyWrapScheme::HpkeAuth,
+            Some(private_key),
+            &file_digest_key(private_key),
+        )
+    }
+
+    /// Opens a chunked container for streaming decryption
+    ///
+    /// # Arguments
+    /// * `header` - The start of the container; must contain at least the full header
+    pub fn stream_decryptor(&self, header: &[u8]) -> Result<StreamDecryptor, NovaultError> {
+        let recipients = ContainerHeader::parse(header)?.0.recipients;
+        let digest_key = file_digest_key(self.key()?);
+        self.with_keys(&recipients, |key| StreamDecryptor::with_private_key(header, key, &digest_key))
+    }
+
+    /// Opens a chunked container for random-access decryption
+    ///
+    /// # Arguments
+    /// * `header` - The start of the container; must contain at least the full header
+    /// * `container_len` - Total size of the stored container in bytes
+    pub fn range_decryptor(&self, header: &[u8], container_len: u64) -> Result<RangeDecryptor, NovaultError> {
+        let recipients = ContainerHeader::parse(header)?.0.recipients;
+        self.with_keys(&recipients, |key| RangeDecryptor::with_private_key(header, container_len, key))
+    }
+
+    /// The key for file digests, to pass to `encrypt_file` or `StreamEncryptor`
+    ///
+    /// See [`crate::file_digest`]. The key is derived from the private key and
+    /// changes with `rotate_key`.
+    pub fn file_digest_key(&self) -> Result<Vec<u8>, NovaultError> {
+        Ok(file_digest_key(self.key()?).expose().to_vec())
+    }
+
+    /// Recomputes a file's stored digest under the current key
+    ///
+    /// Accepts the current key's digest, one from before a key rotation (load
+    /// the keyring first) or a legacy unkeyed SHA-256. Repeating the call after
+    /// an interruption is safe.
+    ///
+    /// # Arguments
+    /// * `plaintext` - The decrypted file
+    /// * `stored_digest_hex` - The digest stored with the file; may be empty
+    ///
+    /// # Returns
+    /// The digest to store in place of the old one, or `undefined` if it is
+    /// already current. Fails with `DIGEST_MISMATCH` if the stored digest does
+    /// not match the file.
+    pub fn migrate_file_digest(&self, plaintext: &[u8], stored_digest_hex: &str) -> Result<Option<String>, NovaultError> {
+        migrate_digest(self.key()?, &self.previous_keys, plaintext, stored_digest_hex)
+    }
+
+    /// Loads the keys kept from earlier rotations, so their files stay readable
+    ///
+    /// See [`crate::rotation`].
+    ///
+    /// # Arguments
+    /// * `keyring` - The stored keyring, from `rotate_key`
+    ///
+    /// # Returns
+    /// The number of previous keys loaded
+    pub fn load_keyring(&mut self, keyring: &[u8]) -> Result<u32, NovaultError> {
+        self.previous_keys = open_keyring(self.key()?, keyring)?;
+        log_info!("[UnlockedVault] Loaded {} previous keys", self.previous_keys.len());
+        Ok(self.previous_keys.len() as u32)
+    }
+
+    /// Replaces the X25519 key pair with a new one, keeping the old key in the keyring
+    ///
+    /// The vault switches to the new key at once. Load the existing keyring
+    /// with `load_keyring` first, or the keys in it are left out of the new
+    /// one. The signing key is kept; a vault unlocked without one fails unless
+    /// `create_signing_key` is set (see `rewrap_private_key`). Afterwards,
+    /// move every file to the new key with `rotate_wrapped_dek` or
+    /// `rotate_container_header`.
+    ///
+    /// # Arguments
+    /// * `new_password` - The master password for the new key
+    /// * `create_signing_key` - Generate a signing key if none is unlocked
+    /// * `params` - Argon2id parameters for the new KEK; defaults to [`KdfParams::DEFAULT`]
+    ///
+    /// # Returns
+    /// The new master key and keyring; store both in place of the old ones
+    pub fn rotate_key(
+        &mut self,
+        new_password: &str,
+        create_signing_key: bool,
+        params: Option<KdfParams>,
+    ) -> Result<RotatedKey, NovaultError> {
+        let params = params_for_new_key(params)?;
+        let old_key = self.private_key.take().ok_or(NovaultError::VaultLocked)?;
+        let private_key = generate_private_key();
+        let (signing_key, signing_key_created) = match existing_or_new_signing_key(self.signing_key.take(), create_signing_key) {
+            Ok(signing_key) => signing_key,
+            Err(e) => {
+                self.private_key = Some(old_key);
+                return Err(e);
+            }
+        };
+
+        let mut previous_keys = std::mem::take(&mut self.previous_keys);
+        previous_keys.push(old_key);
+        let sealed = seal_master_key(new_password, &params, &private_key, &signing_key, signing_key_created)
+            .and_then(|master_key| {
+                let keyring = seal_keyring(&private_key, &previous_keys)?;
+                Ok(RotatedKey::new(master_key, keyring))
+            });
+        let rotated = match sealed {
+            Ok(rotated) => rotated,
+            Err(e) => {
+                // Leave the vault as it was
+                self.private_key = previous_keys.pop();
+                self.previous_keys = previous_keys;
+                self.signing_key = (!signing_key_created).then_some(signing_key);
+                return Err(e);
+            }
+        };
+
+        log_info!(
+            "[UnlockedVault] Rotated to key {}, keeping {} previous keys",
+            bytes_to_hex(&key_id(&public_key_of(&private_key))),
+            previous_keys.len()
+        );
+        self.private_key = Some(private_key);
+        self.signing_key = Some(signing_key);
+        self.previous_keys = previous_keys;
+        Ok(rotated)
+    }
+
+    /// Re-wraps a DEK stored as loose fields for the current key
+    ///
+    /// # Arguments
+    /// * `ephemeral_public_key` - The ephemeral public key of the stored entry (32 bytes)
+    /// * `encrypted_dek` - The stored encrypted DEK bytes
+    /// * `dek_nonce` - The nonce of the stored entry (12 bytes)
+    /// * `wrap_scheme` - The key wrap scheme ID stored with the DEK; defaults to 1 (raw ECDH)
+    /// * `key_id` - The recipient key ID stored with the DEK, if any (8 bytes)
+    /// * `progress` - Updated with the outcome
+    ///
+    /// # Returns
+    /// The entry to store in place of the old one, or `undefined` if it is
+    /// already addressed to the current key
+    pub fn rotate_wrapped_dek(
+        &self,
+        ephemeral_public_key: &[u8],
+        encrypted_dek: &[u8],
+        dek_nonce: &[u8],
+        wrap_scheme: Option<u8>,
+        key_id: Option<Vec<u8>>,
+        progress: &mut RotationProgress,
+    ) -> Result<Option<WrappedDek>, NovaultError> {
+        let result = WrappedDek::from_fields(ephemeral_public_key, encrypted_dek, dek_nonce, wrap_scheme, key_id.as_deref())
+            .and_then(|wrapped_dek| rotate_entry(self.key()?, &self.previous_keys, &wrapped_dek, &[]));
+        progress.record(&result);
+        result
+    }
+
+    /// Re-wraps this user's entries in a container header for the current key
+    ///
+    /// Entries for other recipients are kept. The payload is not touched.
+    ///
+    /// # Arguments
+    /// * `header` - The start of the container; must contain at least the full header
+    /// * `progress` - Updated with the outcome
+    ///
+    /// # Returns
+    /// The new header followed by the rest of `header`, to replace the first
+    /// `header.length` bytes of the stored container; or `undefined` if
+    /// nothing changed
+    pub fn rotate_container_header(
+        &self,
+        header: &[u8],
+        progress: &mut RotationProgress,
+    ) -> Result<Option<Vec<u8>>, NovaultError> {
+        let result = self
+            .key()
+            .and_then(|key| rotate_container_header(key, &self.previous_keys, header));
+        progress.record(&result);
+        result
+    }
+}
+
+impl UnlockedVault {
+    fn key(&self) -> Result<&SecretKey, NovaultError> {
+        self.private_key.as_ref().ok_or(NovaultError::VaultLocked)
+    }
+
+    /// The unlocked keys a file's entries may be addressed to, best match first
+    ///
+    /// Keys whose ID matches an entry come first. Entries without a key ID
+    /// (loose fields, version 1 containers) may belong to any key, so in that
+    /// case the other keys follow, newest first.
+    fn keys_for(&self, recipients: &[WrappedDek]) -> Result<Vec<&SecretKey>, NovaultError> {
+        let keys = std::iter::once(self.key()?).chain(self.previous_keys.iter().rev());
+        let (mut matching, rest): (Vec<_>, Vec<_>) = keys.partition(|key| {
+            let own_key_id = key_id(&public_key_of(key));
+            recipients.iter().any(|entry| entry.key_id == own_key_id)
+        });
+        if matching.is_empty() || recipients.iter().any(|entry| entry.key_id.is_empty()) {
+            matching.extend(rest);
+        }
+        Ok(matching)
+    }
+
+    /// Runs `f` with each key from [`UnlockedVault::keys_for`] until one succeeds
+    fn with_keys<T>(
+        &self,
+        recipients: &[WrappedDek],
+        f: impl Fn(&SecretKey) -> Result<T, NovaultError>,
+    ) -> Result<T, NovaultError> {
+        let mut last_error = NovaultError::NotRecipient;
+        for key in self.keys_for(recipients)? {
+            match f(key) {
+                Ok(value) => return Ok(value),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+
+    /// [`decrypt_with_private_key`] with each key from [`UnlockedVault::keys_for`]
+    fn decrypt_with_keys(&self, ciphertext: &[u8], header: &ContainerHeader, sender_tags: &[u8]) -> DecryptedFileResult {
+        // The digest is always keyed by the current key, whichever key opens the file
+        let digest_key = match self.key() {
+            Ok(key) => file_digest_key(key),
+            Err(e) => return DecryptedFileResult::failed(e),
+        };
+        let keys = match self.keys_for(&header.recipients) {
+            Ok(keys) => keys,
+            Err(e) => return DecryptedFileResult::failed(e),
+        };
+        let mut result = DecryptedFileResult::failed(NovaultError::NotRecipient);
+        for key in keys {
+            result = decrypt_with_private_key(ciphertext, key, header, sender_tags);
+            if result.success() {
+                break;
+            }
+        }
+        result.with_digest(&digest_key)
+    }
+
+    fn signing_key(&self) -> Result<&SecretKey, NovaultError> {
+        self.key()?;
+        self.signing_key.as_ref().ok_or_else(|| {
+            NovaultError::InvalidState("No signing key was unlocked with this vault".to_string())
+        })
+    }
+}
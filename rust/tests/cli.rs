@@ -0,0 +1,149 @@
+//! Runs the `novault` binary end to end.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+const PEPPERS: &str = "1:6e6f7661756c742d636c692d74657374";
+
+/// A scratch directory with a fresh secrets file
+struct Workspace {
+    dir: PathBuf,
+}
+
+impl Workspace {
+    fn new(name: &str) -> Workspace {
+        let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("cli-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let workspace = Workspace { dir };
+        let secrets = workspace.path("secrets.json");
+        workspace.ok(&["keygen", "--calibrate", "1", "-o", secrets.to_str().unwrap()]);
+        workspace
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    fn arg(&self, name: &str) -> String {
+        self.path(name).to_str().unwrap().to_string()
+    }
+
+    fn public_key(&self) -> String {
+        let secrets: serde_json::Value = serde_json::from_slice(&fs::read(self.path("secrets.json")).unwrap()).unwrap();
+        secrets["public_key"].as_str().unwrap().to_string()
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_novault"))
+            .args(args)
+            .env("NOVAULT_PASSWORD", "correct horse battery staple")
+            .env("NOVAULT_PEPPERS", PEPPERS)
+            .output()
+            .unwrap()
+    }
+
+    fn ok(&self, args: &[&str]) -> Output {
+        let output = self.run(args);
+        assert!(output.status.success(), "{:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+        output
+    }
+
+    /// Runs a command that must fail cleanly, and returns its stderr
+    fn fails(&self, args: &[&str]) -> String {
+        let output = self.run(args);
+        // A panic exits with 101; errors are reported with exit code 1
+        assert_eq!(output.status.code(), Some(1), "{:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stderr).unwrap()
+    }
+}
+
+#[test]
+fn container_round_trip() {
+    let ws = Workspace::new("container");
+    let plaintext = b"hello from the command line".repeat(100);
+    fs::write(ws.path("plain"), &plaintext).unwrap();
+    let (secrets, public_key) = (ws.arg("secrets.json"), ws.public_key());
+
+    let encrypted = ws.ok(&[
+        "encrypt", "--recipient", &public_key, "--secrets", &secrets, "--chunk-size", "1024",
+        "-i", &ws.arg("plain"), "-o", &ws.arg("sealed"),
+    ]);
+    let digest = String::from_utf8(encrypted.stderr).unwrap();
+    let digest = digest.lines().find_map(|line| line.strip_prefix("digest ")).unwrap().to_string();
+
+    ws.ok(&["decrypt", "--secrets", &secrets, "-i", &ws.arg("sealed"), "-o", &ws.arg("opened")]);
+    assert_eq!(fs::read(ws.path("opened")).unwrap(), plaintext);
+
+    let verified = ws.ok(&["verify", "--secrets", &secrets, "--hash", &digest, "-i", &ws.arg("sealed")]);
+    assert!(String::from_utf8(verified.stdout).unwrap().contains("(matches)"));
+    let stderr = ws.fails(&["verify", "--secrets", &secrets, "--hash", &"0".repeat(64), "-i", &ws.arg("sealed")]);
+    assert!(stderr.contains("Digest mismatch"), "{}", stderr);
+
+    let inspected = String::from_utf8(ws.ok(&["inspect", "-i", &ws.arg("sealed")]).stdout).unwrap();
+    assert!(inspected.contains("chunk size:           1024 bytes"), "{}", inspected);
+    assert!(inspected.contains("recipients:           1"), "{}", inspected);
+
+    let mut tampered = fs::read(ws.path("sealed")).unwrap();
+    *tampered.last_mut().unwrap() ^= 1;
+    fs::write(ws.path("tampered"), tampered).unwrap();
+    ws.fails(&["decrypt", "--secrets", &secrets, "-i", &ws.arg("tampered"), "-o", &ws.arg("bad")]);
+}
+
+#[test]
+fn loose_round_trip() {
+    let ws = Workspace::new("loose");
+    fs::write(ws.path("plain"), b"stored the web app's way").unwrap();
+    let (secrets, public_key, fields) = (ws.arg("secrets.json"), ws.public_key(), ws.arg("fields.json"));
+
+    ws.ok(&[
+        "encrypt", "--recipient", &public_key, "--secrets", &secrets, "--fields", &fields,
+        "-i", &ws.arg("plain"), "-o", &ws.arg("sealed"),
+    ]);
+    ws.ok(&["decrypt", "--secrets", &secrets, "--fields", &fields, "-i", &ws.arg("sealed"), "-o", &ws.arg("opened")]);
+    assert_eq!(fs::read(ws.path("opened")).unwrap(), b"stored the web app's way");
+    let verified = ws.ok(&["verify", "--secrets", &secrets, "--fields", &fields, "-i", &ws.arg("sealed")]);
+    assert!(String::from_utf8(verified.stdout).unwrap().contains("(matches)"));
+}
+
+#[test]
+fn bad_recipients_are_rejected() {
+    let ws = Workspace::new("recipients");
+    fs::write(ws.path("plain"), b"data").unwrap();
+    let secrets = ws.arg("secrets.json");
+    for recipient in ["aé…", "zz".repeat(32).as_str(), "abc", "abcd"] {
+        let stderr = ws.fails(&["encrypt", "--recipient", recipient, "--secrets", &secrets, "-i", &ws.arg("plain"), "-o", &ws.arg("out")]);
+        assert!(stderr.starts_with("novault: "), "{:?}: {}", recipient, stderr);
+    }
+    let stderr = ws.fails(&["encrypt", "--recipient", "aé…", "--secrets", &secrets, "-i", &ws.arg("plain")]);
+    assert!(stderr.contains("Recipient key"), "{}", stderr);
+}
+
+#[test]
+fn bad_fields_are_rejected() {
+    let ws = Workspace::new("fields");
+    fs::write(ws.path("plain"), b"data").unwrap();
+    let (secrets, public_key, fields) = (ws.arg("secrets.json"), ws.public_key(), ws.arg("fields.json"));
+    ws.ok(&[
+        "encrypt", "--recipient", &public_key, "--secrets", &secrets, "--fields", &fields,
+        "-i", &ws.arg("plain"), "-o", &ws.arg("sealed"),
+    ]);
+    let good: serde_json::Value = serde_json::from_slice(&fs::read(&fields).unwrap()).unwrap();
+
+    let decrypt_with = |name: &str, value: &str| {
+        let mut edited = good.clone();
+        edited[name] = serde_json::Value::String(value.to_string());
+        fs::write(ws.path("edited.json"), edited.to_string()).unwrap();
+        ws.fails(&["decrypt", "--secrets", &secrets, "--fields", &ws.arg("edited.json"), "-i", &ws.arg("sealed")])
+    };
+    for name in ["file_nonce", "encrypted_dek", "dek_nonce", "ephemeral_public_key", "key_id"] {
+        let stderr = decrypt_with(name, "é1");
+        assert!(stderr.contains(name), "{}: {}", name, stderr);
+        decrypt_with(name, "00");
+    }
+
+    fs::write(ws.path("broken.json"), "{\"file_nonce\": ").unwrap();
+    ws.fails(&["decrypt", "--secrets", &secrets, "--fields", &ws.arg("broken.json"), "-i", &ws.arg("sealed")]);
+    ws.fails(&["decrypt", "--secrets", &secrets, "--fields", &ws.arg("missing.json"), "-i", &ws.arg("sealed")]);
+}
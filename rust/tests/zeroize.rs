@@ -0,0 +1,67 @@
+//! Checks that secrets are wiped before their memory goes back to the allocator.
+//!
+//! The watching allocator replaces the global allocator for the whole test
+//! binary, so it lives in its own binary without the test harness and runs
+//! its checks one after another on the main thread.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+
+use rust::secret::{SecretBytes, SecretKey};
+
+/// Allocator that records whether one watched buffer was zero when freed
+struct WatchingAllocator;
+
+static WATCHED: AtomicPtr<u8> = AtomicPtr::new(std::ptr::null_mut());
+static WATCHED_WAS_ZERO: AtomicBool = AtomicBool::new(false);
+
+unsafe impl GlobalAlloc for WatchingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if ptr == WATCHED.load(Ordering::SeqCst) {
+            // SAFETY: the buffer is still allocated until `System.dealloc` below
+            let buffer = unsafe { std::slice::from_raw_parts(ptr, layout.size()) };
+            WATCHED_WAS_ZERO.store(buffer.iter().all(|&b| b == 0), Ordering::SeqCst);
+        }
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: WatchingAllocator = WatchingAllocator;
+
+/// Drops `value` and reports whether the buffer at `ptr` was zero when it was freed
+fn wiped_when_freed<T>(value: T, ptr: *const u8) -> bool {
+    WATCHED.store(ptr.cast_mut(), Ordering::SeqCst);
+    WATCHED_WAS_ZERO.store(false, Ordering::SeqCst);
+    drop(value);
+    WATCHED.store(std::ptr::null_mut(), Ordering::SeqCst);
+    WATCHED_WAS_ZERO.load(Ordering::SeqCst)
+}
+
+fn secret_key_is_wiped_on_drop() {
+    let key = Box::new(SecretKey::new([0xA5; 32]));
+    let ptr = key.expose().as_ptr();
+    assert!(wiped_when_freed(key, ptr));
+}
+
+fn secret_bytes_are_wiped_on_drop() {
+    let mut bytes = SecretBytes::with_capacity(64);
+    bytes.extend_from_slice(&[0xA5; 40]);
+    let ptr = bytes.expose().as_ptr();
+    assert!(wiped_when_freed(bytes, ptr));
+}
+
+fn main() {
+    let checks: [(&str, fn()); 2] = [
+        ("secret_key_is_wiped_on_drop", secret_key_is_wiped_on_drop),
+        ("secret_bytes_are_wiped_on_drop", secret_bytes_are_wiped_on_drop),
+    ];
+    for (name, check) in checks {
+        check();
+        println!("test {} ... ok", name);
+    }
+}
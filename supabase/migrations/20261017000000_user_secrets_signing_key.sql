@@ -0,0 +1,14 @@
+-- Ed25519 signing key, sealed under the same password KEK as the X25519
+-- private key. Nullable: rows written before signing keys existed have none
+-- until the password is next changed, which adds one.
+ALTER TABLE api.user_secrets
+    ADD COLUMN encrypted_signing_key TEXT,
+    ADD COLUMN signing_nonce TEXT,
+    ADD COLUMN signing_public_key TEXT;
+
+-- Either all three signing columns are set, or none are
+ALTER TABLE api.user_secrets
+    ADD CONSTRAINT check_signing_key_complete CHECK (
+        (encrypted_signing_key IS NULL) = (signing_nonce IS NULL)
+        AND (signing_nonce IS NULL) = (signing_public_key IS NULL)
+    );
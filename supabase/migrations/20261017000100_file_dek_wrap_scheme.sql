@@ -0,0 +1,11 @@
+-- Key wrap scheme of each wrapped DEK (see rust/src/key_wrap.rs). Rows
+-- written so far used the raw ECDH secret (1). encrypt_file now defaults to
+-- ECDH with HKDF (2), and upload_encrypted_file does not pass a scheme, so
+-- new rows default to 2. Deploy together with the WASM build that makes
+-- HKDF the default.
+ALTER TABLE api.file_dek
+    ADD COLUMN wrap_scheme SMALLINT NOT NULL DEFAULT 1;
+
+ALTER TABLE api.file_dek
+    ALTER COLUMN wrap_scheme SET DEFAULT 2,
+    ADD CONSTRAINT check_wrap_scheme CHECK (wrap_scheme BETWEEN 1 AND 4);
@@ -0,0 +1,14 @@
+import { getPeppers } from "@/app/home/actions";
+import { HexToUint8Array } from "@/utils/hexUtils";
+
+type Wasm = typeof import("@/pkg/rust");
+
+/**
+ * Registers the server's peppers with the crypto core.
+ * Call after loading the module and before deriving a key from a password.
+ */
+export const registerPeppers = async (wasm: Wasm): Promise<void> => {
+  for (const { version, secret } of await getPeppers()) {
+    wasm.register_pepper(version, HexToUint8Array(secret));
+  }
+};